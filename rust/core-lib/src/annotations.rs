@@ -30,6 +30,9 @@ use crate::xi_rope::{Interval, Rope};
 pub enum AnnotationType {
     Selection,
     Find,
+    FoldRange,
+    Bracket,
+    Bookmark,
     Other(String),
 }
 
@@ -38,6 +41,9 @@ impl AnnotationType {
         match self {
             AnnotationType::Find => "find",
             AnnotationType::Selection => "selection",
+            AnnotationType::FoldRange => "fold_range",
+            AnnotationType::Bracket => "bracket",
+            AnnotationType::Bookmark => "bookmark",
             AnnotationType::Other(ref s) => s,
         }
     }
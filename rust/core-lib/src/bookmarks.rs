@@ -0,0 +1,296 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bookmarked lines of a buffer.
+
+use serde_json::Value;
+
+use xi_rope::delta::DeltaElement;
+use xi_rope::{Interval, Rope, RopeDelta, Transformer};
+
+use crate::annotations::{AnnotationRange, AnnotationSlice, AnnotationType, ToAnnotation};
+use crate::line_offset::LineOffset;
+use crate::view::View;
+
+/// The bookmarks of a buffer.
+///
+/// A bookmark is attached to a logical line, and is stored as the offset of
+/// the start of that line. Bookmarks are kept sorted, there is at most one per
+/// line, and a name is used by at most one bookmark.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    marks: Vec<Bookmark>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bookmark {
+    offset: usize,
+    name: Option<String>,
+}
+
+impl Bookmarks {
+    /// Bookmarks the given line, replacing the name of any bookmark already
+    /// there. A bookmark elsewhere with the same name is removed.
+    pub(crate) fn set(&mut self, text: &Rope, line: usize, name: Option<String>) {
+        let offset = text.offset_of_line(line);
+        if name.is_some() {
+            self.marks.retain(|mark| mark.name != name || mark.offset == offset);
+        }
+        match self.marks.binary_search_by_key(&offset, |mark| mark.offset) {
+            Ok(ix) => self.marks[ix].name = name,
+            Err(ix) => self.marks.insert(ix, Bookmark { offset, name }),
+        }
+    }
+
+    /// Removes the bookmark on the given line. Returns `true` if there was one.
+    pub(crate) fn clear(&mut self, text: &Rope, line: usize) -> bool {
+        let offset = text.offset_of_line(line);
+        match self.marks.binary_search_by_key(&offset, |mark| mark.offset) {
+            Ok(ix) => {
+                self.marks.remove(ix);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Removes the bookmark on the given line, or adds an unnamed one if there
+    /// is none.
+    pub(crate) fn toggle(&mut self, text: &Rope, line: usize) {
+        if !self.clear(text, line) {
+            self.set(text, line, None);
+        }
+    }
+
+    pub(crate) fn clear_all(&mut self) {
+        self.marks.clear();
+    }
+
+    /// Returns the offset of the first bookmark after the line containing
+    /// `offset`, wrapping around to the first bookmark of the buffer.
+    pub(crate) fn next(&self, text: &Rope, offset: usize) -> Option<usize> {
+        let line_start = text.offset_of_line(text.line_of_offset(offset));
+        let next = self.marks.iter().find(|mark| mark.offset > line_start);
+        next.or_else(|| self.marks.first()).map(|mark| mark.offset)
+    }
+
+    /// Returns the offset of the last bookmark before the line containing
+    /// `offset`, wrapping around to the last bookmark of the buffer.
+    pub(crate) fn previous(&self, text: &Rope, offset: usize) -> Option<usize> {
+        let line_start = text.offset_of_line(text.line_of_offset(offset));
+        let previous = self.marks.iter().rev().find(|mark| mark.offset < line_start);
+        previous.or_else(|| self.marks.last()).map(|mark| mark.offset)
+    }
+
+    /// Returns the offset of the bookmark with the given name.
+    pub(crate) fn named(&self, name: &str) -> Option<usize> {
+        self.marks.iter().find(|mark| mark.name.as_deref() == Some(name)).map(|mark| mark.offset)
+    }
+
+    /// Updates the bookmarks after an edit, so that they stay on the same
+    /// lines. `text` is the text after the edit. A bookmark whose line is
+    /// deleted or joined to the previous one moves to the line its start
+    /// ends up on, unless that line already has one.
+    pub(crate) fn apply_delta(&mut self, delta: &RopeDelta, text: &Rope) {
+        if self.marks.is_empty() {
+            return;
+        }
+        let mut transformer = Transformer::new(delta);
+        // the bookmarks, and whether each one was moved to another line
+        let mut marks: Vec<(Bookmark, bool)> = Vec::with_capacity(self.marks.len());
+        for mark in &self.marks {
+            // text inserted at the start of a line goes before its bookmark
+            let transformed = transformer.transform(mark.offset, true);
+            let offset = text.offset_of_line(text.line_of_offset(transformed));
+            let moved = offset != transformed || is_deleted(delta, mark.offset);
+            let bookmark = Bookmark { offset, name: mark.name.clone() };
+            match marks.last_mut() {
+                Some((last, last_moved)) if last.offset == offset => {
+                    // the bookmark already on the line wins
+                    if *last_moved && !moved {
+                        *last = bookmark;
+                        *last_moved = false;
+                    }
+                }
+                _ => marks.push((bookmark, moved)),
+            }
+        }
+        self.marks = marks.into_iter().map(|(mark, _)| mark).collect();
+    }
+
+    /// Describes the bookmarks, for the frontend.
+    pub(crate) fn to_json(&self, view: &View, text: &Rope) -> Value {
+        let marks = self
+            .marks
+            .iter()
+            .map(|mark| {
+                let (line, _) = view.offset_to_line_col(text, mark.offset);
+                json!({ "line": line, "name": mark.name })
+            })
+            .collect::<Vec<_>>();
+        json!(marks)
+    }
+}
+
+/// Returns `true` if the character at `offset` in the base text of `delta`
+/// is deleted by it.
+fn is_deleted(delta: &RopeDelta, offset: usize) -> bool {
+    let mut last_end = 0;
+    for el in &delta.els {
+        if let DeltaElement::Copy(start, end) = *el {
+            if last_end <= offset && offset < start {
+                return true;
+            }
+            last_end = end;
+        }
+    }
+    last_end <= offset && offset < delta.base_len
+}
+
+/// Bookmarks are presented to the frontend as empty ranges at the start of
+/// their lines, with their names as payloads.
+impl ToAnnotation for Bookmarks {
+    fn get_annotations(&self, interval: Interval, view: &View, text: &Rope) -> AnnotationSlice {
+        // an empty last line starts at the end of the text
+        let includes_end = interval.end >= text.len();
+        let (ranges, payloads) = self
+            .marks
+            .iter()
+            .filter(|mark| {
+                interval.start <= mark.offset
+                    && (mark.offset < interval.end || (includes_end && mark.offset == text.len()))
+            })
+            .map(|mark| {
+                let (line, col) = view.offset_to_line_col(text, mark.offset);
+                let range = AnnotationRange {
+                    start_line: line,
+                    start_col: col,
+                    end_line: line,
+                    end_col: col,
+                };
+                (range, json!({ "name": mark.name }))
+            })
+            .unzip();
+        AnnotationSlice::new(AnnotationType::Bookmark, ranges, Some(payloads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xi_rope::DeltaBuilder;
+
+    fn lines(bookmarks: &Bookmarks, text: &Rope) -> Vec<usize> {
+        bookmarks.marks.iter().map(|mark| text.line_of_offset(mark.offset)).collect()
+    }
+
+    #[test]
+    fn set_and_clear() {
+        let text = Rope::from("a\nb\nc\n");
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(&text, 2, Some("c".into()));
+        bookmarks.toggle(&text, 0);
+        assert_eq!(lines(&bookmarks, &text), vec![0, 2]);
+        // names are unique
+        bookmarks.set(&text, 1, Some("c".into()));
+        assert_eq!(lines(&bookmarks, &text), vec![0, 1]);
+        assert_eq!(bookmarks.named("c"), Some(2));
+        bookmarks.toggle(&text, 0);
+        assert_eq!(lines(&bookmarks, &text), vec![1]);
+        assert!(!bookmarks.clear(&text, 2));
+        assert!(bookmarks.clear(&text, 1));
+    }
+
+    #[test]
+    fn next_and_previous() {
+        let text = Rope::from("a\nb\nc\nd");
+        let mut bookmarks = Bookmarks::default();
+        assert_eq!(bookmarks.next(&text, 0), None);
+        bookmarks.set(&text, 1, None);
+        bookmarks.set(&text, 3, None);
+        assert_eq!(bookmarks.next(&text, 0), Some(2));
+        assert_eq!(bookmarks.next(&text, 3), Some(6));
+        assert_eq!(bookmarks.next(&text, 7), Some(2));
+        assert_eq!(bookmarks.previous(&text, 7), Some(2));
+        assert_eq!(bookmarks.previous(&text, 2), Some(6));
+    }
+
+    #[test]
+    fn track_edits() {
+        let text = Rope::from("a\nb\nc\n");
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(&text, 1, None);
+        bookmarks.set(&text, 2, None);
+
+        // inserting lines at the start of a bookmarked line
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(2..2, "x\ny\n".into());
+        let delta = builder.build();
+        let text = delta.apply(&text);
+        bookmarks.apply_delta(&delta, &text);
+        assert_eq!(lines(&bookmarks, &text), vec![3, 4]);
+
+        // deleting a bookmarked line
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.delete(6..8);
+        let delta = builder.build();
+        let text = delta.apply(&text);
+        bookmarks.apply_delta(&delta, &text);
+        assert_eq!(String::from(&text), "a\nx\ny\nc\n");
+        assert_eq!(lines(&bookmarks, &text), vec![3]);
+    }
+
+    #[test]
+    fn collapse_keeps_existing_mark() {
+        let text = Rope::from("a\nb\nc\n");
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(&text, 0, Some("a".into()));
+        bookmarks.set(&text, 1, Some("b".into()));
+        bookmarks.set(&text, 2, Some("c".into()));
+
+        // deleting a bookmarked line whose following line has a bookmark
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.delete(2..4);
+        let delta = builder.build();
+        let text = delta.apply(&text);
+        bookmarks.apply_delta(&delta, &text);
+        assert_eq!(lines(&bookmarks, &text), vec![0, 1]);
+        assert_eq!(bookmarks.named("c"), Some(2));
+        assert_eq!(bookmarks.named("b"), None);
+
+        // joining a bookmarked line to the previous one
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.delete(1..2);
+        let delta = builder.build();
+        let text = delta.apply(&text);
+        bookmarks.apply_delta(&delta, &text);
+        assert_eq!(String::from(&text), "ac\n");
+        assert_eq!(lines(&bookmarks, &text), vec![0]);
+        assert_eq!(bookmarks.named("a"), Some(0));
+    }
+
+    #[test]
+    fn annotation_on_empty_last_line() {
+        use crate::tabs::BufferId;
+
+        let text = Rope::from("a\n");
+        let view = View::new(1.into(), BufferId::new(2));
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(&text, 1, None);
+        let annotations = bookmarks.get_annotations(Interval::new(0, text.len()), &view, &text);
+        assert_eq!(annotations.to_json()["n"], 1);
+        let annotations = bookmarks.get_annotations(Interval::new(0, 1), &view, &text);
+        assert_eq!(annotations.to_json()["n"], 0);
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Matching of bracket pairs.
+
+use syntect::parsing::Scope;
+use xi_rope::{Cursor, Interval, Rope};
+
+use crate::annotations::{AnnotationRange, AnnotationSlice, AnnotationType, ToAnnotation};
+use crate::config::BufferItems;
+use crate::layers::Layers;
+use crate::line_offset::LineOffset;
+use crate::view::View;
+
+/// The pairs used when no configuration is available.
+const DEFAULT_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Scopes whose brackets are not matched against brackets outside of them.
+const IGNORED_SCOPES: &[&str] = &["string", "comment"];
+
+/// The number of lines before and after the visible ones searched for the
+/// brackets matching those next to carets.
+pub(crate) const MATCH_SCAN_LINES: usize = 500;
+
+/// Finds the bracket matching a given bracket.
+///
+/// Only pairs of distinct single characters are considered, so quotes are
+/// never matched. When scope information is available, a bracket inside a
+/// string or comment only matches brackets that are also inside a string or
+/// comment, and vice versa.
+pub struct BracketMatcher<'a> {
+    pairs: Vec<(char, char)>,
+    layers: Option<&'a Layers>,
+    ignored_scopes: Vec<Scope>,
+    /// The revision of the text brackets are matched in, which identifies
+    /// matches that can be reused.
+    rev: u64,
+}
+
+/// The brackets next to carets and the brackets matching them, as found in
+/// a revision of the text, near the visible lines.
+pub struct MatchedBrackets {
+    rev: u64,
+    carets: Vec<usize>,
+    bounds: Interval,
+    offsets: Vec<usize>,
+}
+
+impl<'a> BracketMatcher<'a> {
+    /// Creates a matcher for the bracket pairs of the buffer's config: its
+    /// `surrounding_pairs` and `bracket_pairs`.
+    pub fn from_config(config: &BufferItems, layers: &'a Layers) -> Self {
+        BracketMatcher::new(config.surrounding_pairs.iter().chain(&config.bracket_pairs), layers)
+    }
+
+    /// Creates a matcher for the given pairs, using the scopes in `layers` if
+    /// there are any.
+    pub fn new<'p, I>(pairs: I, layers: &'a Layers) -> Self
+    where
+        I: IntoIterator<Item = &'p (String, String)>,
+    {
+        let pairs = pairs
+            .into_iter()
+            .filter_map(|(open, close)| {
+                let mut open_chars = open.chars();
+                let mut close_chars = close.chars();
+                match (open_chars.next(), open_chars.next(), close_chars.next(), close_chars.next())
+                {
+                    (Some(open), None, Some(close), None) if open != close => Some((open, close)),
+                    _ => None,
+                }
+            })
+            .collect();
+        let ignored_scopes = if layers.has_scopes() {
+            IGNORED_SCOPES.iter().filter_map(|s| Scope::new(s).ok()).collect()
+        } else {
+            Vec::new()
+        };
+        BracketMatcher { pairs, layers: Some(layers), ignored_scopes, rev: 0 }
+    }
+
+    /// Sets the revision of the text brackets are matched in.
+    pub fn with_rev(self, rev: u64) -> Self {
+        BracketMatcher { rev, ..self }
+    }
+
+    /// Returns the offsets of the bracket adjacent to `offset` and of the
+    /// bracket matching it. A bracket following `offset` takes precedence
+    /// over one preceding it.
+    pub fn match_adjacent(&self, text: &Rope, offset: usize) -> Option<(usize, usize)> {
+        self.match_adjacent_within(text, offset, Interval::new(0, text.len()))
+    }
+
+    /// Returns the offset of the bracket matching the bracket at `offset`.
+    pub fn match_at(&self, text: &Rope, offset: usize) -> Option<usize> {
+        self.match_within(text, offset, Interval::new(0, text.len()))
+    }
+
+    /// Returns the brackets next to `carets` and the brackets matching them,
+    /// searching only within `bounds`. The brackets in `cached` are reused if
+    /// they were found for the same revision, carets and bounds.
+    pub fn match_carets(
+        &self,
+        text: &Rope,
+        carets: Vec<usize>,
+        bounds: Interval,
+        cached: Option<MatchedBrackets>,
+    ) -> MatchedBrackets {
+        match cached {
+            Some(cached)
+                if cached.rev == self.rev && cached.carets == carets && cached.bounds == bounds =>
+            {
+                cached
+            }
+            _ => {
+                let mut offsets = carets
+                    .iter()
+                    .filter_map(|&caret| self.match_adjacent_within(text, caret, bounds))
+                    .flat_map(|(bracket, other)| vec![bracket, other])
+                    .collect::<Vec<_>>();
+                offsets.sort_unstable();
+                offsets.dedup();
+                MatchedBrackets { rev: self.rev, carets, bounds, offsets }
+            }
+        }
+    }
+
+    fn match_adjacent_within(
+        &self,
+        text: &Rope,
+        offset: usize,
+        bounds: Interval,
+    ) -> Option<(usize, usize)> {
+        let after = self.match_within(text, offset, bounds).map(|other| (offset, other));
+        after.or_else(|| {
+            let before = text.prev_codepoint_offset(offset)?;
+            self.match_within(text, before, bounds).map(|other| (before, other))
+        })
+    }
+
+    /// Returns the offset of the bracket matching the bracket at `offset`,
+    /// if it is within `bounds`.
+    fn match_within(&self, text: &Rope, offset: usize, bounds: Interval) -> Option<usize> {
+        let ch = Cursor::new(text, offset).peek_next_codepoint()?;
+        let ignored = self.is_ignored(offset);
+        if let Some(&(open, close)) = self.pairs.iter().find(|pair| pair.0 == ch) {
+            let mut cursor = Cursor::new(text, offset + ch.len_utf8());
+            let mut depth = 0;
+            while let Some(c) = cursor.next_codepoint() {
+                let pos = cursor.pos() - c.len_utf8();
+                if pos >= bounds.end {
+                    break;
+                }
+                if (c != open && c != close) || self.is_ignored(pos) != ignored {
+                    continue;
+                }
+                if c == open {
+                    depth += 1;
+                } else if depth == 0 {
+                    return Some(pos);
+                } else {
+                    depth -= 1;
+                }
+            }
+        } else if let Some(&(open, close)) = self.pairs.iter().find(|pair| pair.1 == ch) {
+            let mut cursor = Cursor::new(text, offset);
+            let mut depth = 0;
+            while let Some(c) = cursor.prev_codepoint() {
+                let pos = cursor.pos();
+                if pos < bounds.start {
+                    break;
+                }
+                if (c != open && c != close) || self.is_ignored(pos) != ignored {
+                    continue;
+                }
+                if c == close {
+                    depth += 1;
+                } else if depth == 0 {
+                    return Some(pos);
+                } else {
+                    depth -= 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the offsets of the brackets of the innermost pair enclosing
+    /// the range from `start` to `end`. From outside of strings and comments,
+    /// brackets inside of them are not considered.
+    pub fn enclosing(&self, text: &Rope, start: usize, end: usize) -> Option<(usize, usize)> {
+        let ignored = self.is_ignored(start);
+        let mut cursor = Cursor::new(text, start);
+        let mut depth = 0;
+        while let Some(c) = cursor.prev_codepoint() {
+            let pos = cursor.pos();
+            let is_bracket = self.pairs.iter().any(|pair| pair.0 == c || pair.1 == c);
+            if !is_bracket || (!ignored && self.is_ignored(pos)) {
+                continue;
+            }
+            if self.pairs.iter().any(|pair| pair.1 == c) {
+                depth += 1;
+            } else if self.pairs.iter().any(|pair| pair.0 == c) {
+                if depth > 0 {
+                    depth -= 1;
+                    continue;
+                }
+                match self.match_at(text, pos) {
+                    Some(close) if close >= end => return Some((pos, close)),
+                    _ => (),
+                }
+            }
+        }
+        None
+    }
+
+    fn is_ignored(&self, offset: usize) -> bool {
+        match self.layers {
+            Some(layers) if !self.ignored_scopes.is_empty() => {
+                layers.has_scope_at(offset, &self.ignored_scopes)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for BracketMatcher<'_> {
+    fn default() -> Self {
+        BracketMatcher {
+            pairs: DEFAULT_PAIRS.to_vec(),
+            layers: None,
+            ignored_scopes: Vec::new(),
+            rev: 0,
+        }
+    }
+}
+
+impl MatchedBrackets {
+    /// Returns the offsets of the brackets next to carets and of the brackets
+    /// matching them, in order.
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+}
+
+/// The bracket pairs adjacent to carets are presented to the frontend as
+/// annotations, one range per bracket.
+impl ToAnnotation for MatchedBrackets {
+    fn get_annotations(&self, interval: Interval, view: &View, text: &Rope) -> AnnotationSlice {
+        let ranges = self
+            .offsets
+            .iter()
+            .filter(|&&offset| interval.start <= offset && offset < interval.end)
+            .map(|&offset| {
+                let end = text.next_codepoint_offset(offset).unwrap_or(offset);
+                let (start_line, start_col) = view.offset_to_line_col(text, offset);
+                let (end_line, end_col) = view.offset_to_line_col(text, end);
+                AnnotationRange { start_line, start_col, end_line, end_col }
+            })
+            .collect();
+        AnnotationSlice::new(AnnotationType::Bracket, ranges, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_brackets() {
+        let text = Rope::from("fn a(b: [u8]) { c(d[0]) }");
+        let brackets = BracketMatcher::default();
+        assert_eq!(brackets.match_at(&text, 4), Some(12));
+        assert_eq!(brackets.match_at(&text, 12), Some(4));
+        assert_eq!(brackets.match_at(&text, 14), Some(24));
+        assert_eq!(brackets.match_at(&text, 19), Some(21));
+        assert_eq!(brackets.match_at(&text, 0), None);
+        // the bracket after the offset wins
+        assert_eq!(brackets.match_adjacent(&text, 17), Some((17, 22)));
+        assert_eq!(brackets.match_adjacent(&text, 23), Some((22, 17)));
+        assert_eq!(brackets.match_adjacent(&text, 25), Some((24, 14)));
+        assert_eq!(brackets.match_adjacent(&text, 2), None);
+    }
+
+    #[test]
+    fn enclosing_brackets() {
+        let text = Rope::from("a(b[c], {d}) e");
+        let brackets = BracketMatcher::default();
+        assert_eq!(brackets.enclosing(&text, 5, 5), Some((3, 5)));
+        assert_eq!(brackets.enclosing(&text, 3, 6), Some((1, 11)));
+        assert_eq!(brackets.enclosing(&text, 8, 9), Some((1, 11)));
+        assert_eq!(brackets.enclosing(&text, 10, 13), None);
+        assert_eq!(brackets.enclosing(&text, 0, 1), None);
+    }
+
+    #[test]
+    fn configured_pairs() {
+        let text = Rope::from("\"(<a>)\"");
+        let layers = Layers::default();
+        let pairs = vec![("\"".to_string(), "\"".to_string()), ("<".to_string(), ">".to_string())];
+        let brackets = BracketMatcher::new(&pairs, &layers);
+        assert_eq!(brackets.match_at(&text, 0), None);
+        assert_eq!(brackets.match_at(&text, 1), None);
+        assert_eq!(brackets.match_at(&text, 2), Some(4));
+    }
+
+    #[test]
+    fn unbalanced() {
+        let text = Rope::from("(é[)");
+        let brackets = BracketMatcher::default();
+        assert_eq!(brackets.match_at(&text, 0), Some(4));
+        assert_eq!(brackets.match_at(&text, 3), None);
+    }
+
+    #[test]
+    fn match_carets_within_bounds() {
+        let text = Rope::from("(a)\n(\n\n)");
+        let brackets = BracketMatcher::default();
+        let matched = brackets.match_carets(&text, vec![0, 4], Interval::new(0, 6), None);
+        assert_eq!(matched.offsets, vec![0, 2]);
+        let matched = brackets.match_carets(&text, vec![4], Interval::new(0, 8), Some(matched));
+        assert_eq!(matched.offsets, vec![4, 7]);
+        // matches are only found again for a new revision
+        let text = Rope::from("a\nb\nc\nd\n");
+        let matched = brackets.match_carets(&text, vec![4], Interval::new(0, 8), Some(matched));
+        assert_eq!(matched.offsets, vec![4, 7]);
+        let brackets = brackets.with_rev(1);
+        let matched = brackets.match_carets(&text, vec![4], Interval::new(0, 8), Some(matched));
+        assert!(matched.offsets.is_empty());
+    }
+}
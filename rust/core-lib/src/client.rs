@@ -20,6 +20,8 @@ use serde_json::{self, Value};
 use xi_rpc::{self, RpcPeer};
 
 use crate::config::Table;
+use crate::line_ending::LineEnding;
+use crate::modal::Mode;
 use crate::plugins::rpc::ClientPluginInfo;
 use crate::plugins::Command;
 use crate::styles::ThemeSettings;
@@ -94,6 +96,66 @@ impl Client {
         );
     }
 
+    /// Notify the client that the mode of the modal editing layer changed.
+    pub fn mode_changed(&self, view_id: ViewId, mode: Mode) {
+        self.0.send_rpc_notification(
+            "mode_changed",
+            &json!({
+                "view_id": view_id,
+                "mode": mode,
+            }),
+        );
+    }
+
+    /// Notify the client that the buffer of a view is indented with both tabs
+    /// and spaces, so that it can offer to convert its indentation.
+    pub fn mixed_indentation(&self, view_id: ViewId) {
+        self.0.send_rpc_notification("mixed_indentation", &json!({ "view_id": view_id }));
+    }
+
+    /// Notify the client of the line ending used by the buffer of a view, or
+    /// that the buffer mixes several line endings if `line_ending` is `None`.
+    pub fn line_ending_changed(&self, view_id: ViewId, line_ending: Option<LineEnding>) {
+        let line_ending = line_ending.map_or_else(|| json!("mixed"), |ending| json!(ending));
+        self.0.send_rpc_notification(
+            "line_ending_changed",
+            &json!({
+                "view_id": view_id,
+                "line_ending": line_ending,
+            }),
+        );
+    }
+
+    /// Notify the client of the progress of loading a large file, in bytes.
+    pub fn load_progress(&self, view_id: ViewId, loaded: u64, total: u64) {
+        self.0.send_rpc_notification(
+            "load_progress",
+            &json!({
+                "view_id": view_id,
+                "loaded": loaded,
+                "total": total,
+            }),
+        );
+    }
+
+    /// Notify the client whether expensive features are disabled for the
+    /// large file shown in a view.
+    pub fn large_file_mode(&self, view_id: ViewId, enabled: bool) {
+        self.0.send_rpc_notification(
+            "large_file_mode",
+            &json!({
+                "view_id": view_id,
+                "enabled": enabled,
+            }),
+        );
+    }
+
+    /// Notify the client that unsaved changes to the file shown in a view
+    /// were recovered after a crash, and can be restored.
+    pub fn recovery_available(&self, view_id: ViewId) {
+        self.0.send_rpc_notification("recovery_available", &json!({ "view_id": view_id }));
+    }
+
     /// Notify the client that a plugin has started.
     pub fn plugin_started(&self, view_id: ViewId, plugin: &str) {
         self.0.send_rpc_notification(
@@ -185,7 +185,20 @@ pub struct BufferItems {
     pub word_wrap: bool,
     pub autodetect_whitespace: bool,
     pub surrounding_pairs: Vec<(String, String)>,
+    pub bracket_pairs: Vec<(String, String)>,
+    pub word_separators: String,
+    pub reflow_column: usize,
+    pub reflow_markers: Vec<String>,
+    pub reflow_skipped_prefixes: Vec<String>,
     pub save_with_newline: bool,
+    pub trim_trailing_whitespace: bool,
+    pub ensure_single_final_newline: bool,
+    pub persistent_undo: bool,
+    pub large_file_threshold: u64,
+    pub read_only: bool,
+    pub recovery_interval: u64,
+    pub autosave_delay: u64,
+    pub autosave_on_focus_loss: bool,
 }
 
 pub type BufferConfig = Config<BufferItems>;
@@ -544,6 +557,19 @@ impl ConfigManager {
         None
     }
 
+    /// Path to the sub directory inside config directory where undo histories
+    /// are persisted. It is created when the first history is saved.
+    pub(crate) fn get_undo_dir(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|p| p.join("undo"))
+    }
+
+    /// Path to the sub directory inside config directory where unsaved
+    /// changes are kept for recovery. It is created when the first recovery
+    /// file is saved.
+    pub(crate) fn get_recovery_dir(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|p| p.join("recovery"))
+    }
+
     /// Path to plugins sub directory inside config directory.
     /// Creates one if not present.
     pub(crate) fn get_plugins_dir(&self) -> Option<PathBuf> {
@@ -15,15 +15,19 @@
 //! Functions for editing ropes.
 
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
 
 use xi_rope::{Cursor, DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta};
+use xi_unicode::LineBreakIterator;
 
 use crate::backspace::offset_for_delete_backwards;
+use crate::brackets::BracketMatcher;
 use crate::config::BufferItems;
 use crate::line_offset::{LineOffset, LogicalLines};
 use crate::linewrap::Lines;
-use crate::movement::{region_movement, Movement};
+use crate::movement::{region_movement_with_config, Movement};
 use crate::selection::{SelRegion, Selection};
 use crate::word_boundaries::WordCursor;
 
@@ -33,6 +37,12 @@ pub enum IndentDirection {
     Out,
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum LineDirection {
+    Up,
+    Down,
+}
+
 /// Replaces the selection with the text `T`.
 pub fn insert<T: Into<Rope>>(base: &Rope, regions: &[SelRegion], text: T) -> RopeDelta {
     let rope = text.into();
@@ -139,29 +149,39 @@ pub(crate) fn delete_by_movement(
     lines: &Lines,
     movement: Movement,
     height: usize,
+    word_separators: &str,
     save: bool,
-) -> (RopeDelta, Option<Rope>) {
+) -> (RopeDelta, Option<Vec<Rope>>) {
     // We compute deletions as a selection because the merge logic
     // is convenient. Another possibility would be to make the delta
     // builder able to handle overlapping deletions (with union semantics).
     let mut deletions = Selection::new();
     for &r in regions {
         if r.is_caret() {
-            let new_region = region_movement(movement, r, lines, height, base, true);
+            let new_region = region_movement_with_config(
+                movement,
+                r,
+                lines,
+                height,
+                base,
+                word_separators,
+                &BracketMatcher::default(),
+                true,
+            );
             deletions.add_region(new_region);
         } else {
             deletions.add_region(r);
         }
     }
 
-    let kill_ring = if save {
-        let saved = extract_sel_regions(base, &deletions).unwrap_or_default();
-        Some(Rope::from(saved))
+    // one piece per deletion, even if it is empty
+    let killed = if save {
+        Some(deletions.iter().map(|region| base.subseq(region.min()..region.max())).collect())
     } else {
         None
     };
 
-    (delete_sel_regions(base, &deletions), kill_ring)
+    (delete_sel_regions(base, &deletions), killed)
 }
 
 /// Deletes the given regions.
@@ -340,6 +360,110 @@ pub fn transform_text<F: Fn(&str) -> String>(
     builder.build()
 }
 
+/// The identifier cases that `convert_case` can convert text to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// `snake_case`
+    Snake,
+    /// `camelCase`
+    Camel,
+    /// `PascalCase`
+    Pascal,
+    /// `kebab-case`
+    Kebab,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnake,
+    /// `Title Case`
+    Title,
+}
+
+/// Converts each identifier of `text`, a run of alphanumeric characters and
+/// underscores, to the given case. The text between identifiers is kept.
+pub fn convert_case(text: &str, case: Case) -> String {
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_identifier_char) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+        result.push_str(&convert_identifier(&rest[..end], case));
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Converts an identifier to the given case, keeping its leading and
+/// trailing underscores, as in `_private`.
+fn convert_identifier(identifier: &str, case: Case) -> String {
+    let words = identifier_words(identifier);
+    if words.is_empty() {
+        return identifier.to_owned();
+    }
+    let converted = match case {
+        Case::Snake => words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>().join("_"),
+        Case::Kebab => words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>().join("-"),
+        Case::ScreamingSnake => {
+            words.iter().map(|w| w.to_uppercase()).collect::<Vec<_>>().join("_")
+        }
+        Case::Camel => {
+            let rest = words[1..].iter().map(|w| capitalize(w));
+            std::iter::once(words[0].to_lowercase()).chain(rest).collect()
+        }
+        Case::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+        Case::Title => words.iter().map(|w| capitalize(w)).collect::<Vec<_>>().join(" "),
+    };
+    let leading = &identifier[..identifier.len() - identifier.trim_start_matches('_').len()];
+    let trailing = &identifier[identifier.trim_end_matches('_').len()..];
+    format!("{}{}{}", leading, converted, trailing)
+}
+
+/// Splits an identifier into its words. A word ends before a non-alphanumeric
+/// character, before an uppercase letter following a lowercase letter or a
+/// digit, and before the last uppercase letter of a run followed by a
+/// lowercase letter, as in `HTTPServer`.
+fn identifier_words(text: &str) -> Vec<&str> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(start) = start.take() {
+                words.push(&text[start..offset]);
+            }
+            continue;
+        }
+        if let Some(word_start) = start {
+            let prev = chars[i - 1].1;
+            let next = chars.get(i + 1).map(|&(_, c)| c);
+            let hump = c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+            if hump {
+                words.push(&text[word_start..offset]);
+                start = Some(offset);
+            }
+        } else {
+            start = Some(offset);
+        }
+    }
+    if let Some(start) = start {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Uppercases the first character of `word` and lowercases the others.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.as_str().to_lowercase().chars()).collect(),
+        None => String::new(),
+    }
+}
+
 /// Changes the number(s) under the cursor(s) with the `transform_function`.
 /// If there is a number next to or on the beginning of the region, then
 /// this number will be replaced with the result of `transform_function` and
@@ -409,6 +533,472 @@ pub fn capitalize_text(base: &Rope, regions: &[SelRegion]) -> (RopeDelta, Select
     (builder.build(), final_selection)
 }
 
+/// The ways in which `reorder_lines` can rearrange lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOrder {
+    /// Sorts lines lexically, by their UTF-8 bytes.
+    Sort,
+    /// Sorts lines lexically, ignoring case.
+    SortCaseInsensitive,
+    /// Sorts lines lexically, except that runs of digits are compared by
+    /// their numeric value.
+    SortNatural,
+    /// Removes lines that are equal to an earlier line.
+    Unique,
+    /// Reverses the order of the lines.
+    Reverse,
+    /// Shuffles lines randomly, using the given seed.
+    Shuffle(u64),
+}
+
+/// Rearranges the lines touched by each selection region, or all lines of the
+/// buffer if every region is a caret. Returns the delta and the selection
+/// covering the lines to be rearranged, in the coordinates of `base`.
+pub fn reorder_lines(
+    base: &Rope,
+    regions: &[SelRegion],
+    order: LineOrder,
+) -> (RopeDelta, Selection) {
+    let mut blocks = line_blocks(base, regions.iter().filter(|region| !region.is_caret()))
+        .into_iter()
+        .map(|lines| lines_interval(base, lines))
+        .collect::<Vec<_>>();
+    if blocks.is_empty() {
+        blocks.push(Interval::new(0, base.len()));
+    }
+
+    let mut builder = DeltaBuilder::new(base.len());
+    let mut selection = Selection::new();
+    for block in blocks {
+        let block_text = base.slice_to_cow(block);
+        let newline = if block_text.contains("\r\n") { "\r\n" } else { "\n" };
+        let trailing_newline = block_text.ends_with('\n');
+        let mut lines = block_text.lines().collect::<Vec<_>>();
+        rearrange(&mut lines, order);
+        let mut new_text = lines.join(newline);
+        if trailing_newline {
+            new_text.push_str(newline);
+        }
+        if new_text != block_text {
+            builder.replace(block, Rope::from(new_text));
+        }
+        selection.add_region(SelRegion::new(block.start, block.end));
+    }
+    (builder.build(), selection)
+}
+
+/// Moves the lines touched by each selection region past the line above or
+/// below them. Nothing moves if any of these lines is already at the top or
+/// bottom of the buffer.
+///
+/// Selections are expected to drift before insertions for upward moves, and
+/// after them for downward moves.
+pub fn move_lines(base: &Rope, regions: &[SelRegion], direction: LineDirection) -> RopeDelta {
+    let mut builder = DeltaBuilder::new(base.len());
+    let blocks = line_blocks(base, regions);
+    let line_count = base.measure::<LinesMetric>() + 1;
+    let can_move = match direction {
+        LineDirection::Up => blocks.first().is_some_and(|lines| lines.start > 0),
+        LineDirection::Down => blocks.last().is_some_and(|lines| lines.end < line_count),
+    };
+    if !can_move {
+        return builder.build();
+    }
+
+    for lines in blocks {
+        let block = lines_interval(base, lines.clone());
+        match direction {
+            LineDirection::Up => {
+                // the line above is deleted and reinserted after the block
+                let above = lines_interval(base, lines.start - 1..lines.start);
+                let above_text = base.slice_to_cow(above);
+                let mut moved = above_text.to_string();
+                if !base.slice_to_cow(block).ends_with('\n') {
+                    let (content, newline) = split_line_ending(&above_text);
+                    moved = format!("{}{}", newline, content);
+                }
+                builder.delete(above);
+                builder.replace(Interval::new(block.end, block.end), Rope::from(moved));
+            }
+            LineDirection::Down => {
+                // the line below is deleted and reinserted before the block
+                let below = lines_interval(base, lines.end..lines.end + 1);
+                let below_text = base.slice_to_cow(below);
+                let mut moved = below_text.to_string();
+                let mut deleted = below;
+                if !below_text.ends_with('\n') {
+                    let block_text = base.slice_to_cow(block);
+                    let (_, newline) = split_line_ending(&block_text);
+                    moved.push_str(newline);
+                    deleted = Interval::new(block.end - newline.len(), below.end);
+                }
+                builder.replace(Interval::new(block.start, block.start), Rope::from(moved));
+                builder.delete(deleted);
+            }
+        }
+    }
+
+    builder.build()
+}
+
+/// Joins the line of each caret with the following line, and the lines of
+/// each selection spanning several lines with one another. The line breaks
+/// and the leading whitespace of the joined lines are replaced by a single
+/// space, unless one of the lines is blank.
+pub fn join_lines(base: &Rope, regions: &[SelRegion]) -> RopeDelta {
+    let mut builder = DeltaBuilder::new(base.len());
+    let mut to_join = BTreeSet::new();
+    for region in regions {
+        let line_range = LogicalLines.get_line_range(base, region);
+        let last_line = line_range.end.max(line_range.start + 2) - 1;
+        to_join.extend(line_range.start..last_line);
+    }
+
+    for line in to_join {
+        let next_start = LogicalLines.offset_of_line(base, line + 1);
+        if next_start >= base.len() {
+            break;
+        }
+        let start = LogicalLines.offset_of_line(base, line);
+        let line_text = base.slice_to_cow(start..next_start);
+        let (content, newline) = split_line_ending(&line_text);
+        let content_end = next_start - newline.len();
+
+        let mut cursor = Cursor::new(base, next_start);
+        while let Some(c) = cursor.next_codepoint() {
+            if c != ' ' && c != '\t' {
+                cursor.prev_codepoint();
+                break;
+            }
+        }
+        let next_content_start = cursor.pos();
+        let next_is_blank = match cursor.peek_next_codepoint() {
+            Some(c) => c == '\n' || c == '\r',
+            None => true,
+        };
+
+        let separator =
+            if content.trim_end().len() < content.len() || content.is_empty() || next_is_blank {
+                ""
+            } else {
+                " "
+            };
+        builder.replace(Interval::new(content_end, next_content_start), Rope::from(separator));
+    }
+
+    builder.build()
+}
+
+/// Rewraps the paragraphs touched by each selection region so that their
+/// lines are at most `reflow_column` wide where possible, breaking lines at
+/// Unicode line break opportunities.
+///
+/// A paragraph is a run of non-blank lines with the same indentation and
+/// comment or quote marker from the `reflow_markers` config key, which are
+/// kept at the start of each rewrapped line. Lines starting with one of the
+/// `reflow_skipped_prefixes`, such as Markdown headings, are left alone.
+pub fn reflow_paragraphs(base: &Rope, regions: &[SelRegion], config: &BufferItems) -> RopeDelta {
+    let markers = &config.reflow_markers;
+    let mut paragraphs = BTreeSet::new();
+    for region in regions {
+        for line in LogicalLines.get_line_range(base, region) {
+            paragraphs.extend(paragraph_at(base, line, config));
+        }
+    }
+
+    let mut builder = DeltaBuilder::new(base.len());
+    for (first, last) in paragraphs {
+        let start = LogicalLines.offset_of_line(base, first);
+        let end = LogicalLines.offset_of_line(base, last + 1);
+        let text = base.slice_to_cow(start..end);
+        let (text, _) = split_line_ending(&text);
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+        let (_, prefix, _) = split_line_prefix(text.lines().next().unwrap_or(""), markers);
+        let words = text
+            .lines()
+            .map(|line| split_line_prefix(line, markers).2)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let prefix_width =
+            prefix.chars().map(|c| if c == '\t' { config.tab_size } else { 1 }).sum::<usize>();
+        let width = config.reflow_column.saturating_sub(prefix_width).max(1);
+        let new_text = wrap_words(&words, width)
+            .iter()
+            .map(|line| format!("{}{}", prefix, line))
+            .collect::<Vec<_>>()
+            .join(newline);
+        if new_text != text {
+            builder.replace(Interval::new(start, start + text.len()), Rope::from(new_text));
+        }
+    }
+    builder.build()
+}
+
+/// Returns the first and last lines of the paragraph containing `line`, or
+/// `None` if the line is blank or skipped.
+fn paragraph_at(base: &Rope, line: usize, config: &BufferItems) -> Option<(usize, usize)> {
+    let line_text = |line: usize| {
+        let start = LogicalLines.offset_of_line(base, line);
+        let end = LogicalLines.offset_of_line(base, line + 1);
+        base.slice_to_cow(start..end)
+    };
+    let key = |line: usize| {
+        let text = line_text(line);
+        let (key, _, content) = split_line_prefix(&text, &config.reflow_markers);
+        let skipped =
+            config.reflow_skipped_prefixes.iter().any(|p| content.starts_with(p.as_str()));
+        if content.is_empty() || skipped {
+            None
+        } else {
+            Some(key.to_owned())
+        }
+    };
+
+    let paragraph_key = key(line)?;
+    let last_line = base.measure::<LinesMetric>();
+    let mut first = line;
+    while first > 0 && key(first - 1).as_ref() == Some(&paragraph_key) {
+        first -= 1;
+    }
+    let mut last = line;
+    while last < last_line && key(last + 1).as_ref() == Some(&paragraph_key) {
+        last += 1;
+    }
+    Some((first, last))
+}
+
+/// Splits a line into the indentation and longest of `markers` identifying
+/// its paragraph, the prefix to repeat on each line of the paragraph, which
+/// also includes the whitespace following the marker, and its trimmed content.
+fn split_line_prefix<'a>(line: &'a str, markers: &[String]) -> (&'a str, &'a str, &'a str) {
+    let (line, _) = split_line_ending(line);
+    let rest = line.trim_start();
+    let marker_len = markers
+        .iter()
+        .filter(|marker| rest.starts_with(marker.as_str()))
+        .map(String::len)
+        .max()
+        .unwrap_or(0);
+    let key_len = line.len() - rest.len() + marker_len;
+    let content = line[key_len..].trim_start();
+    let prefix_len = line.len() - content.len();
+    (&line[..key_len], &line[..prefix_len], content.trim_end())
+}
+
+/// Breaks `text` into lines at most `width` characters wide, except for the
+/// parts of the text that cannot be broken.
+fn wrap_words(text: &str, width: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut last_break = 0;
+    for (offset, _) in LineBreakIterator::new(text) {
+        let line_width = text[line_start..offset].trim_end().chars().count();
+        if line_width > width && last_break > line_start {
+            lines.push(text[line_start..last_break].trim_end());
+            line_start = last_break;
+        }
+        last_break = offset;
+    }
+    lines.push(text[line_start..].trim_end());
+    lines
+}
+
+/// Cleans up the whitespace of the buffer before saving: removes trailing
+/// spaces and tabs from every line if `trim_trailing`, and makes the buffer
+/// end with exactly one `line_ending` if `single_final_newline`.
+pub fn clean_up_whitespace(
+    base: &Rope,
+    trim_trailing: bool,
+    single_final_newline: bool,
+    line_ending: &str,
+) -> RopeDelta {
+    let is_trailing =
+        |c: char| c == '\n' || c == '\r' || (trim_trailing && (c == ' ' || c == '\t'));
+    let content_end = if single_final_newline {
+        let mut cursor = Cursor::new(base, base.len());
+        while let Some(c) = cursor.prev_codepoint() {
+            if !is_trailing(c) {
+                cursor.next_codepoint();
+                break;
+            }
+        }
+        cursor.pos()
+    } else {
+        base.len()
+    };
+
+    let mut builder = DeltaBuilder::new(base.len());
+    if trim_trailing {
+        let mut offset = 0;
+        for line in base.lines_raw(..content_end) {
+            let (content, _) = split_line_ending(&line);
+            let trimmed = content.trim_end_matches([' ', '\t']);
+            if trimmed.len() < content.len() {
+                builder.delete(Interval::new(offset + trimmed.len(), offset + content.len()));
+            }
+            offset += line.len();
+        }
+    }
+    if single_final_newline {
+        // an empty buffer stays empty
+        let final_newline = if content_end == 0 { "" } else { line_ending };
+        if base.slice_to_cow(content_end..) != final_newline {
+            builder.replace(Interval::new(content_end, base.len()), Rope::from(final_newline));
+        }
+    }
+    builder.build()
+}
+
+/// Converts the leading indentation of the lines touched by each selection
+/// region, or of every line if all regions are carets, to tabs if `use_tabs`
+/// and to spaces otherwise. A tab stands for `tab_size` columns.
+pub fn convert_indentation(
+    base: &Rope,
+    regions: &[SelRegion],
+    use_tabs: bool,
+    tab_size: usize,
+) -> RopeDelta {
+    let mut blocks = line_blocks(base, regions.iter().filter(|region| !region.is_caret()));
+    if blocks.is_empty() {
+        blocks.push(0..base.measure::<LinesMetric>() + 1);
+    }
+    let tab_size = tab_size.max(1);
+
+    let mut builder = DeltaBuilder::new(base.len());
+    for line in blocks.into_iter().flatten() {
+        let start = LogicalLines.offset_of_line(base, line);
+        let end = LogicalLines.offset_of_line(base, line + 1);
+        let line_text = base.slice_to_cow(start..end);
+        let indent_len = line_text.len() - line_text.trim_start_matches([' ', '\t']).len();
+        let indent = &line_text[..indent_len];
+        let width = indent.chars().fold(0, |width, c| match c {
+            '\t' => (width / tab_size + 1) * tab_size,
+            _ => width + 1,
+        });
+        let new_indent = if use_tabs {
+            format!("{}{}", "\t".repeat(width / tab_size), " ".repeat(width % tab_size))
+        } else {
+            " ".repeat(width)
+        };
+        if new_indent != indent {
+            builder.replace(Interval::new(start, start + indent_len), Rope::from(new_indent));
+        }
+    }
+    builder.build()
+}
+
+/// Replaces every line ending of the buffer which differs from `line_ending`,
+/// including the lone `\r` of classic Mac line endings.
+pub fn convert_line_endings(base: &Rope, line_ending: &str) -> RopeDelta {
+    let mut builder = DeltaBuilder::new(base.len());
+    let mut offset = 0;
+    for line in base.lines_raw(..) {
+        let mut endings = line.match_indices(['\r', '\n']);
+        while let Some((i, ending)) = endings.next() {
+            let len = if ending == "\r" && line[i + 1..].starts_with('\n') {
+                endings.next();
+                2
+            } else {
+                1
+            };
+            if &line[i..i + len] != line_ending {
+                let interval = Interval::new(offset + i, offset + i + len);
+                builder.replace(interval, Rope::from(line_ending));
+            }
+        }
+        offset += line.len();
+    }
+    builder.build()
+}
+
+/// Returns the ranges of lines touched by `regions`, merging the ranges that
+/// overlap or are adjacent.
+fn line_blocks<'a, I>(base: &Rope, regions: I) -> Vec<Range<usize>>
+where
+    I: IntoIterator<Item = &'a SelRegion>,
+{
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+    for region in regions {
+        let lines = LogicalLines.get_line_range(base, region);
+        match blocks.last_mut() {
+            Some(last) if last.end >= lines.start => last.end = last.end.max(lines.end),
+            _ => blocks.push(lines),
+        }
+    }
+    blocks
+}
+
+/// Returns the interval covering `lines`, including the last line ending.
+fn lines_interval(base: &Rope, lines: Range<usize>) -> Interval {
+    let start = LogicalLines.offset_of_line(base, lines.start);
+    let end = LogicalLines.offset_of_line(base, lines.end);
+    Interval::new(start, end)
+}
+
+/// Splits a line into its content and its line ending, if it has one.
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let content = line.trim_end_matches(['\n', '\r']);
+    (content, &line[content.len()..])
+}
+
+fn rearrange(lines: &mut Vec<&str>, order: LineOrder) {
+    match order {
+        LineOrder::Sort => lines.sort(),
+        LineOrder::SortCaseInsensitive => lines.sort_by_cached_key(|line| line.to_lowercase()),
+        LineOrder::SortNatural => lines.sort_by(|a, b| natural_cmp(a, b)),
+        LineOrder::Unique => {
+            let mut seen = HashSet::new();
+            lines.retain(|line| seen.insert(*line));
+        }
+        LineOrder::Reverse => lines.reverse(),
+        LineOrder::Shuffle(seed) => {
+            // xorshift64*; good enough for shuffling lines
+            let mut state = seed | 1;
+            for i in (1..lines.len()).rev() {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+                let random = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+                lines.swap(i, (random % (i as u64 + 1)) as usize);
+            }
+        }
+    }
+}
+
+/// Compares two strings, treating runs of ASCII digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_rest = a;
+    let mut b_rest = b;
+    loop {
+        let (a_chunk, a_digits) = split_natural_chunk(a_rest);
+        let (b_chunk, b_digits) = split_natural_chunk(b_rest);
+        if a_chunk.is_empty() || b_chunk.is_empty() {
+            return a_chunk.len().cmp(&b_chunk.len()).then_with(|| a.cmp(b));
+        }
+        let ordering = if a_digits && b_digits {
+            let a_num = a_chunk.trim_start_matches('0');
+            let b_num = b_chunk.trim_start_matches('0');
+            a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num))
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a_rest = &a_rest[a_chunk.len()..];
+        b_rest = &b_rest[b_chunk.len()..];
+    }
+}
+
+/// Splits off the leading run of digits or of non-digits, returning it and
+/// whether it is made of digits.
+fn split_natural_chunk(s: &str) -> (&str, bool) {
+    let digits = s.starts_with(|c: char| c.is_ascii_digit());
+    let len = s.find(|c: char| c.is_ascii_digit() != digits).unwrap_or(s.len());
+    (&s[..len], digits)
+}
+
 fn sel_region_to_interval_and_rope(base: &Rope, region: SelRegion) -> (Interval, Rope) {
     let as_interval = Interval::new(region.min(), region.max());
     let interval_rope = base.subseq(as_interval);
@@ -437,3 +1027,35 @@ fn n_spaces(n: usize) -> &'static str {
     assert!(n <= spaces.len());
     &spaces[..n]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::InsertDrift;
+
+    #[test]
+    fn shuffle_lines() {
+        let base = Rope::from("a\nb\nc\nd\ne\nf\n");
+        // the selection touches the lines "b" to "e"
+        let regions = [SelRegion::new(2, 9)];
+        let (delta, selection) = reorder_lines(&base, &regions, LineOrder::Shuffle(42));
+        let text = delta.apply(&base);
+        assert_eq!(String::from(&text), "a\nd\ne\nb\nc\nf\n");
+        // the selection keeps covering the shuffled lines
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Inside);
+        let covered =
+            selection.iter().map(|r| text.slice_to_cow(r.min()..r.max())).collect::<Vec<_>>();
+        assert_eq!(covered, ["d\ne\nb\nc\n"]);
+    }
+
+    #[test]
+    fn convert_case_keeps_punctuation() {
+        assert_eq!(convert_case("self.fooBar", Case::Snake), "self.foo_bar");
+        assert_eq!(convert_case("a = b", Case::Snake), "a = b");
+        assert_eq!(
+            convert_case("x.get_HTTPServer(_someArg)", Case::Camel),
+            "x.getHttpServer(_someArg)"
+        );
+        assert_eq!(convert_case("__init__ + 1", Case::ScreamingSnake), "__INIT__ + 1");
+    }
+}
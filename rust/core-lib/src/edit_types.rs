@@ -18,6 +18,8 @@
 //! This simplifies code elsewhere, and makes it easier to route events to
 //! the editor or view as appropriate.
 
+use crate::edit_ops::Case;
+use crate::line_ending::LineEnding;
 use crate::movement::Movement;
 use crate::rpc::{
     EditNotification, FindQuery, GestureType, LineRange, MouseAction, Position,
@@ -34,10 +36,12 @@ pub(crate) enum ViewEvent {
     Scroll(LineRange),
     AddSelectionAbove,
     AddSelectionBelow,
+    ExtendBlockSelection(Movement),
     Click(MouseAction),
     Drag(MouseAction),
     Gesture { line: u64, col: u64, ty: GestureType },
     GotoLine { line: u64 },
+    SelectLinesTo { line: u64, newline: bool },
     Find { chars: String, case_sensitive: bool, regex: bool, whole_words: bool },
     MultiFind { queries: Vec<FindQuery> },
     FindNext { wrap_around: bool, allow_same: bool, modify_selection: SelectionModifier },
@@ -49,6 +53,14 @@ pub(crate) enum ViewEvent {
     SelectionForReplace,
     SelectionIntoLines,
     CollapseSelections,
+    ExpandSelection,
+    ShrinkSelection,
+    Fold,
+    Unfold,
+    ToggleFold,
+    UnfoldAll,
+    JumpBack,
+    JumpForward,
 }
 
 /// Events that modify the buffer
@@ -59,9 +71,19 @@ pub(crate) enum BufferEvent {
     Transpose,
     Undo,
     Redo,
+    JumpToUndoNode(usize),
+    RevertToRevision(u64),
+    SetBookmark(Option<String>),
+    ClearBookmark,
+    ToggleBookmark,
+    ClearAllBookmarks,
+    NextBookmark,
+    PreviousBookmark,
+    GotoBookmark(String),
     Uppercase,
     Lowercase,
     Capitalize,
+    ConvertCase(Case),
     Indent,
     Outdent,
     Insert(String),
@@ -69,11 +91,28 @@ pub(crate) enum BufferEvent {
     InsertNewline,
     InsertTab,
     Yank,
+    YankLines,
+    YankPop,
+    KillSelection,
+    CopyToKillRing,
     ReplaceNext,
     ReplaceAll,
     DuplicateLine,
     IncreaseNumber,
     DecreaseNumber,
+    SortLines,
+    SortLinesCaseInsensitive,
+    SortLinesNatural,
+    UniqueLines,
+    ReverseLines,
+    ShuffleLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    ReflowParagraph,
+    ConvertIndentationToSpaces,
+    ConvertIndentationToTabs,
+    ConvertLineEndings(LineEnding),
 }
 
 /// An event that needs special handling
@@ -90,6 +129,7 @@ pub(crate) enum SpecialEvent {
     ToggleRecording(Option<String>),
     PlayRecording(String),
     ClearRecording(String),
+    Key(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -124,7 +164,7 @@ impl From<EditNotification> for EventDomain {
         match src {
             Insert { chars } =>
                 BufferEvent::Insert(chars).into(),
-            Paste { chars } =>
+            Paste { chars, .. } =>
                 BufferEvent::Paste(chars).into(),
             DeleteForward =>
                 BufferEvent::Delete {
@@ -143,6 +183,16 @@ impl From<EditNotification> for EventDomain {
                     movement: Movement::LeftWord,
                     kill: false
                 }.into(),
+            DeleteSubwordForward =>
+                BufferEvent::Delete {
+                    movement: Movement::RightSubword,
+                    kill: false
+                }.into(),
+            DeleteSubwordBackward =>
+                BufferEvent::Delete {
+                    movement: Movement::LeftSubword,
+                    kill: false
+                }.into(),
             DeleteToEndOfParagraph =>
                 BufferEvent::Delete {
                     movement: Movement::EndOfParagraphKill,
@@ -181,6 +231,14 @@ impl From<EditNotification> for EventDomain {
                 ViewEvent::Move(Movement::RightWord).into(),
             MoveWordRightAndModifySelection =>
                 ViewEvent::ModifySelection(Movement::RightWord).into(),
+            MoveSubwordLeft =>
+                ViewEvent::Move(Movement::LeftSubword).into(),
+            MoveSubwordLeftAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::LeftSubword).into(),
+            MoveSubwordRight =>
+                ViewEvent::Move(Movement::RightSubword).into(),
+            MoveSubwordRightAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::RightSubword).into(),
             MoveToBeginningOfParagraph =>
                 ViewEvent::Move(Movement::StartOfParagraph).into(),
             MoveToBeginningOfParagraphAndModifySelection =>
@@ -205,6 +263,10 @@ impl From<EditNotification> for EventDomain {
                 ViewEvent::Move(Movement::EndOfDocument).into(),
             MoveToEndOfDocumentAndModifySelection =>
                 ViewEvent::ModifySelection(Movement::EndOfDocument).into(),
+            MoveToMatchingBracket =>
+                ViewEvent::Move(Movement::MatchingBracket).into(),
+            MoveToMatchingBracketAndModifySelection =>
+                ViewEvent::ModifySelection(Movement::MatchingBracket).into(),
             ScrollPageUp =>
                 ViewEvent::Move(Movement::UpPage).into(),
             PageUpAndModifySelection =>
@@ -216,11 +278,20 @@ impl From<EditNotification> for EventDomain {
             SelectAll => ViewEvent::SelectAll.into(),
             AddSelectionAbove => ViewEvent::AddSelectionAbove.into(),
             AddSelectionBelow => ViewEvent::AddSelectionBelow.into(),
+            ExtendBlockSelectionUp =>
+                ViewEvent::ExtendBlockSelection(Movement::Up).into(),
+            ExtendBlockSelectionDown =>
+                ViewEvent::ExtendBlockSelection(Movement::Down).into(),
+            ExtendBlockSelectionLeft =>
+                ViewEvent::ExtendBlockSelection(Movement::Left).into(),
+            ExtendBlockSelectionRight =>
+                ViewEvent::ExtendBlockSelection(Movement::Right).into(),
             Scroll(range) => ViewEvent::Scroll(range).into(),
             Resize(size) => SpecialEvent::Resize(size).into(),
             GotoLine { line } => ViewEvent::GotoLine { line }.into(),
             RequestLines(range) => SpecialEvent::RequestLines(range).into(),
             Yank => BufferEvent::Yank.into(),
+            YankPop => BufferEvent::YankPop.into(),
             Transpose => BufferEvent::Transpose.into(),
             Click(action) => ViewEvent::Click(action).into(),
             Drag(action) => ViewEvent::Drag(action).into(),
@@ -261,6 +332,15 @@ impl From<EditNotification> for EventDomain {
             },
             Undo => BufferEvent::Undo.into(),
             Redo => BufferEvent::Redo.into(),
+            JumpToUndoNode { id } => BufferEvent::JumpToUndoNode(id).into(),
+            RevertToRevision { rev } => BufferEvent::RevertToRevision(rev).into(),
+            SetBookmark { name } => BufferEvent::SetBookmark(name).into(),
+            ClearBookmark => BufferEvent::ClearBookmark.into(),
+            ToggleBookmark => BufferEvent::ToggleBookmark.into(),
+            ClearAllBookmarks => BufferEvent::ClearAllBookmarks.into(),
+            NextBookmark => BufferEvent::NextBookmark.into(),
+            PreviousBookmark => BufferEvent::PreviousBookmark.into(),
+            GotoBookmark { name } => BufferEvent::GotoBookmark(name).into(),
             Find { chars, case_sensitive, regex, whole_words } =>
                 ViewEvent::Find { chars, case_sensitive, regex, whole_words }.into(),
             MultiFind { queries } =>
@@ -276,6 +356,12 @@ impl From<EditNotification> for EventDomain {
             Uppercase => BufferEvent::Uppercase.into(),
             Lowercase => BufferEvent::Lowercase.into(),
             Capitalize => BufferEvent::Capitalize.into(),
+            ToSnakeCase => BufferEvent::ConvertCase(Case::Snake).into(),
+            ToCamelCase => BufferEvent::ConvertCase(Case::Camel).into(),
+            ToPascalCase => BufferEvent::ConvertCase(Case::Pascal).into(),
+            ToKebabCase => BufferEvent::ConvertCase(Case::Kebab).into(),
+            ToScreamingSnakeCase => BufferEvent::ConvertCase(Case::ScreamingSnake).into(),
+            ToTitleCase => BufferEvent::ConvertCase(Case::Title).into(),
             Indent => BufferEvent::Indent.into(),
             Outdent => BufferEvent::Outdent.into(),
             Reindent => SpecialEvent::Reindent.into(),
@@ -294,10 +380,33 @@ impl From<EditNotification> for EventDomain {
             DuplicateLine => BufferEvent::DuplicateLine.into(),
             IncreaseNumber => BufferEvent::IncreaseNumber.into(),
             DecreaseNumber => BufferEvent::DecreaseNumber.into(),
+            SortLines => BufferEvent::SortLines.into(),
+            SortLinesCaseInsensitive => BufferEvent::SortLinesCaseInsensitive.into(),
+            SortLinesNatural => BufferEvent::SortLinesNatural.into(),
+            UniqueLines => BufferEvent::UniqueLines.into(),
+            ReverseLines => BufferEvent::ReverseLines.into(),
+            ShuffleLines => BufferEvent::ShuffleLines.into(),
+            MoveLinesUp => BufferEvent::MoveLinesUp.into(),
+            MoveLinesDown => BufferEvent::MoveLinesDown.into(),
+            JoinLines => BufferEvent::JoinLines.into(),
+            ReflowParagraph => BufferEvent::ReflowParagraph.into(),
+            ConvertIndentationToSpaces => BufferEvent::ConvertIndentationToSpaces.into(),
+            ConvertIndentationToTabs => BufferEvent::ConvertIndentationToTabs.into(),
+            ConvertLineEndings { line_ending } =>
+                BufferEvent::ConvertLineEndings(line_ending).into(),
             ToggleRecording { recording_name } => SpecialEvent::ToggleRecording(recording_name).into(),
             PlayRecording { recording_name } => SpecialEvent::PlayRecording(recording_name).into(),
             ClearRecording { recording_name } => SpecialEvent::ClearRecording(recording_name).into(),
             CollapseSelections => ViewEvent::CollapseSelections.into(),
+            ExpandSelection => ViewEvent::ExpandSelection.into(),
+            ShrinkSelection => ViewEvent::ShrinkSelection.into(),
+            Fold => ViewEvent::Fold.into(),
+            Unfold => ViewEvent::Unfold.into(),
+            ToggleFold => ViewEvent::ToggleFold.into(),
+            UnfoldAll => ViewEvent::UnfoldAll.into(),
+            JumpBack => ViewEvent::JumpBack.into(),
+            JumpForward => ViewEvent::JumpForward.into(),
+            Key { key } => SpecialEvent::Key(key).into(),
         }
     }
 }
@@ -15,6 +15,7 @@
 use std::borrow::{Borrow, Cow};
 use std::cmp::min;
 use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde_json::Value;
 
@@ -26,18 +27,24 @@ use xi_rope::{DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta, Transformer}
 use xi_trace::{trace_block, trace_payload};
 
 use crate::annotations::{AnnotationType, Annotations};
+use crate::bookmarks::Bookmarks;
 use crate::config::BufferItems;
-use crate::edit_ops::{self, IndentDirection};
+use crate::edit_ops::{self, Case, IndentDirection, LineDirection, LineOrder};
 use crate::edit_types::BufferEvent;
 use crate::event_context::MAX_SIZE_LIMIT;
+use crate::folds::FoldRanges;
+use crate::kill_ring::KillRing;
 use crate::layers::Layers;
+use crate::line_ending::LineEnding;
 use crate::line_offset::{LineOffset, LogicalLines};
 use crate::movement::Movement;
-use crate::plugins::rpc::{DataSpan, GetDataResponse, PluginEdit, ScopeSpan, TextUnit};
+use crate::plugins::rpc::{DataSpan, FoldRange, GetDataResponse, PluginEdit, ScopeSpan, TextUnit};
 use crate::plugins::PluginId;
+use crate::revision_log::{delta_size, RevisionLog, CORE_AUTHOR};
 use crate::rpc::SelectionModifier;
 use crate::selection::{InsertDrift, SelRegion, Selection};
 use crate::styles::ThemeStyleMap;
+use crate::undo_tree::UndoTree;
 use crate::view::{Replace, View};
 
 pub struct SyncStore;
@@ -45,6 +52,9 @@ pub struct SyncStore;
 // TODO This could go much higher without issue but while developing it is
 // better to keep it low to expose bugs in the GC during casual testing.
 const MAX_UNDOS: usize = 20;
+/// The number of undo groups kept in the undo tree, including the undone
+/// branches, before the oldest branches are dropped.
+const MAX_UNDO_TREE_SIZE: usize = 5 * MAX_UNDOS;
 
 pub struct Editor {
     /// The contents of the buffer.
@@ -66,6 +76,11 @@ pub struct Editor {
     undos: BTreeSet<usize>,
     /// undo groups that are no longer live and should be gc'ed
     gc_undos: BTreeSet<usize>,
+    /// All the undo groups that may still be toggled, including undone
+    /// branches that are not in `live_undos`
+    undo_tree: UndoTree,
+    /// When and by whom the revisions of the engine were made.
+    revision_log: RevisionLog,
     force_undo_group: bool,
 
     this_edit_type: EditType,
@@ -74,6 +89,9 @@ pub struct Editor {
     revs_in_flight: usize,
 
     layers: Layers,
+    /// Foldable regions provided by plugins.
+    fold_ranges: FoldRanges,
+    bookmarks: Bookmarks,
 }
 
 impl Editor {
@@ -101,14 +119,77 @@ impl Editor {
             cur_undo: 1,
             undos: BTreeSet::new(),
             gc_undos: BTreeSet::new(),
+            undo_tree: UndoTree::new(0),
+            revision_log: RevisionLog::new(last_rev_id.token()),
             force_undo_group: false,
             last_edit_type: EditType::Other,
             this_edit_type: EditType::Other,
             layers: Layers::default(),
+            fold_ranges: FoldRanges::default(),
+            bookmarks: Bookmarks::default(),
             revs_in_flight: 0,
         }
     }
 
+    /// Creates a new `Editor` from a persisted undo history, whose text is
+    /// replaced by `text` if they differ.
+    pub(crate) fn with_undo_history(history: UndoHistory<Engine, UndoTree>, text: Rope) -> Editor {
+        let UndoHistory {
+            mut engine,
+            undo_group_id,
+            live_undos,
+            cur_undo,
+            undos,
+            gc_undos,
+            undo_tree,
+        } = history;
+        engine.resume_rev_id_counter();
+        let buffer = engine.get_head().clone();
+        let last_rev_id = engine.get_head_rev_id();
+
+        let mut editor = Editor {
+            text: buffer,
+            engine,
+            last_rev_id,
+            pristine_rev_id: last_rev_id,
+            undo_group_id,
+            live_undos,
+            cur_undo,
+            undos,
+            gc_undos,
+            undo_tree,
+            revision_log: RevisionLog::new(last_rev_id.token()),
+            force_undo_group: false,
+            last_edit_type: EditType::Other,
+            this_edit_type: EditType::Other,
+            layers: Layers::default(),
+            fold_ranges: FoldRanges::default(),
+            bookmarks: Bookmarks::default(),
+            revs_in_flight: 0,
+        };
+        let delta = LineHashDiff::compute_delta(&editor.text, &text);
+        if !delta.is_identity() {
+            editor.add_delta(delta);
+            editor.last_rev_id = editor.engine.get_head_rev_id();
+            editor.set_pristine();
+            editor.revision_log = RevisionLog::new(editor.last_rev_id.token());
+        }
+        editor
+    }
+
+    /// Returns the undo state of this editor, for persisting it.
+    pub(crate) fn undo_history(&self) -> UndoHistory<&Engine, &UndoTree> {
+        UndoHistory {
+            engine: &self.engine,
+            undo_group_id: self.undo_group_id,
+            live_undos: self.live_undos.clone(),
+            cur_undo: self.cur_undo,
+            undos: self.undos.clone(),
+            gc_undos: self.gc_undos.clone(),
+            undo_tree: &self.undo_tree,
+        }
+    }
+
     pub(crate) fn get_buffer(&self) -> &Rope {
         &self.text
     }
@@ -121,6 +202,18 @@ impl Editor {
         &mut self.layers
     }
 
+    pub(crate) fn get_fold_ranges(&self) -> &FoldRanges {
+        &self.fold_ranges
+    }
+
+    pub(crate) fn get_fold_ranges_mut(&mut self) -> &mut FoldRanges {
+        &mut self.fold_ranges
+    }
+
+    pub(crate) fn get_bookmarks(&self) -> &Bookmarks {
+        &self.bookmarks
+    }
+
     pub(crate) fn get_head_rev_token(&self) -> u64 {
         self.engine.get_head_rev_id().token()
     }
@@ -159,9 +252,15 @@ impl Editor {
     /// Sets this Editor's contents to `text`, preserving undo state and cursor
     /// position when possible.
     pub fn reload(&mut self, text: Rope) {
+        self.restore(text);
+        self.set_pristine();
+    }
+
+    /// Sets this Editor's contents to `text`, which is not saved yet, as an
+    /// edit that can be undone.
+    pub(crate) fn restore(&mut self, text: Rope) {
         let delta = LineHashDiff::compute_delta(self.get_buffer(), &text);
         self.add_delta(delta);
-        self.set_pristine();
     }
 
     // each outstanding plugin edit represents a rev_in_flight.
@@ -191,9 +290,12 @@ impl Editor {
         let head_rev_id = self.engine.get_head_rev_id();
         let undo_group = self.calculate_undo_group();
         self.last_edit_type = self.this_edit_type;
+        self.undo_tree.record_edit(undo_group, &delta);
+        let (inserted, deleted) = delta_size(&delta);
         let priority = 0x10000;
         self.engine.edit_rev(priority, undo_group, head_rev_id.token(), delta);
         self.text = self.engine.get_head().clone();
+        self.record_revision(CORE_AUTHOR, inserted, deleted);
     }
 
     pub(crate) fn calculate_undo_group(&mut self) -> usize {
@@ -205,29 +307,50 @@ impl Editor {
             *self.live_undos.last().unwrap()
         } else {
             let undo_group = self.undo_group_id;
-            self.gc_undos.extend(&self.live_undos[self.cur_undo..]);
+            // the undone groups are kept in the undo tree, as another branch
+            let parent = self.cur_undo.checked_sub(1).map(|i| self.live_undos[i]);
+            self.undo_tree.add(undo_group, parent, self.this_edit_type);
             self.live_undos.truncate(self.cur_undo);
             self.live_undos.push(undo_group);
-            if self.live_undos.len() <= MAX_UNDOS {
-                self.cur_undo += 1;
-            } else {
-                self.gc_undos.insert(self.live_undos.remove(0));
-            }
+            self.cur_undo += 1;
+            self.prune_undo_tree();
             self.undo_group_id += 1;
             undo_group
         }
     }
 
+    /// Forgets the oldest undo group once there are too many undos, along with
+    /// the branches forking from it, and then the oldest branches if the undo
+    /// tree is still too large.
+    fn prune_undo_tree(&mut self) {
+        if self.live_undos.len() > MAX_UNDOS {
+            let root = self.live_undos.remove(0);
+            self.cur_undo -= 1;
+            self.gc_undos.extend(self.undo_tree.remove_root(root, self.live_undos[0]));
+        }
+        while self.undo_tree.len() > MAX_UNDO_TREE_SIZE {
+            match self.undo_tree.remove_oldest_leaf(&self.live_undos) {
+                Some(group) => self.gc_undos.insert(group),
+                None => break,
+            };
+        }
+    }
+
     /// generates a delta from a plugin's response and applies it to the buffer.
     pub fn apply_plugin_edit(&mut self, edit: PluginEdit) {
         let _t = trace_block("Editor::apply_plugin_edit", &["core"]);
         //TODO: get priority working, so that plugin edits don't necessarily move cursor
-        let PluginEdit { rev, delta, priority, undo_group, .. } = edit;
+        let PluginEdit { rev, delta, priority, undo_group, author, .. } = edit;
         let priority = priority as usize;
         let undo_group = undo_group.unwrap_or_else(|| self.calculate_undo_group());
-        match self.engine.try_edit_rev(priority, undo_group, rev, delta) {
+        match self.engine.try_edit_rev(priority, undo_group, rev, delta.clone()) {
             Err(e) => error!("Error applying plugin edit: {}", e),
-            Ok(_) => self.text = self.engine.get_head().clone(),
+            Ok(_) => {
+                self.undo_tree.record_edit(undo_group, &delta);
+                self.text = self.engine.get_head().clone();
+                let (inserted, deleted) = delta_size(&delta);
+                self.record_revision(&author, inserted, deleted);
+            }
         };
     }
 
@@ -249,13 +372,19 @@ impl Editor {
         let last_text = self.engine.get_rev(last_token).expect("last_rev not found");
 
         // Transpose can rotate characters inside of a selection; this is why it's an Inside edit.
+        // Replacing a selection puts its new text inside of it.
         // Surround adds characters on either side of a selection, that's why it's an Outside edit.
+        // Moving lines up reinserts the line above right after the moved lines, and yanking lines
+        // inserts them at the end of the carets' lines; neither must be pulled into selections.
         let drift = match self.this_edit_type {
-            EditType::Transpose => InsertDrift::Inside,
+            EditType::Transpose | EditType::Replace => InsertDrift::Inside,
             EditType::Surround => InsertDrift::Outside,
+            EditType::MoveLinesUp | EditType::YankLines => InsertDrift::Before,
             _ => InsertDrift::Default,
         };
         self.layers.update_all(&delta);
+        self.fold_ranges.apply_delta(&delta);
+        self.bookmarks.apply_delta(&delta, &self.text);
 
         self.last_rev_id = self.engine.get_head_rev_id();
         self.sync_state_changed();
@@ -273,6 +402,8 @@ impl Editor {
         if self.revs_in_flight == 0 && !self.gc_undos.is_empty() {
             self.engine.gc(&self.gc_undos);
             self.undos = &self.undos - &self.gc_undos;
+            let engine = &self.engine;
+            self.revision_log.retain(|rev| engine.has_rev(rev));
             self.gc_undos.clear();
         }
     }
@@ -359,9 +490,135 @@ impl Editor {
         }
     }
 
+    /// Restores the buffer to the state right after the edits of the given undo
+    /// group, undoing the groups that aren't its ancestors. The groups after it
+    /// on its most recent branch can then be redone.
+    fn do_jump_to_undo_node(&mut self, group: usize) {
+        if !self.undo_tree.contains(group) {
+            warn!("no undo group {} in the undo tree", group);
+            return;
+        }
+        let path = self.undo_tree.path_to(group);
+        // undone groups waiting to be gc'ed must stay undone
+        let mut undos = &self.undos & &self.gc_undos;
+        undos.extend(self.undo_tree.groups().filter(|group| !path.contains(group)));
+        self.undos = undos;
+        self.cur_undo = path.len();
+        self.live_undos = path;
+        self.live_undos.extend(self.undo_tree.latest_descendants(group));
+        self.this_edit_type = EditType::Undo;
+        self.update_undos();
+    }
+
+    /// Returns the undo tree and the current undo group, for the frontend.
+    pub(crate) fn undo_tree_json(&self) -> Value {
+        self.undo_tree.to_json(self.live_undos[self.cur_undo - 1])
+    }
+
     fn update_undos(&mut self) {
+        let head_rev_id = self.engine.get_head_rev_id();
         self.engine.undo(self.undos.clone());
         self.text = self.engine.get_head().clone();
+        if self.engine.get_head_rev_id() != head_rev_id {
+            let delta = self.engine.try_delta_rev_head(head_rev_id.token()).unwrap();
+            let (inserted, deleted) = delta_size(&delta);
+            self.record_revision(CORE_AUTHOR, inserted, deleted);
+        }
+    }
+
+    fn record_revision(&mut self, author: &str, inserted: usize, deleted: usize) {
+        let rev = self.engine.get_head_rev_id().token();
+        self.revision_log.record(rev, author, inserted, deleted);
+    }
+
+    /// Returns the revisions of the buffer that can still be reverted to,
+    /// oldest first.
+    pub(crate) fn revision_history_json(&self) -> Value {
+        self.revision_log.to_json()
+    }
+
+    /// Replaces the buffer with its contents at revision `rev`, as a new edit.
+    fn do_revert_to_revision(&mut self, rev: RevToken) {
+        let text = match self.engine.get_rev(rev) {
+            Some(text) => text,
+            None => {
+                warn!("no revision {} in the history", rev);
+                return;
+            }
+        };
+        let delta = LineHashDiff::compute_delta(&self.text, &text);
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    /// Returns the lines of the carets and selections, each line only once.
+    fn selected_lines(&self, view: &View) -> BTreeSet<usize> {
+        let mut lines = BTreeSet::new();
+        for region in view.sel_regions() {
+            let first = self.text.line_of_offset(region.min());
+            let last = self.text.line_of_offset(region.max());
+            lines.extend(first..=last);
+        }
+        lines
+    }
+
+    fn do_set_bookmark(&mut self, view: &View, name: Option<String>) {
+        if name.is_some() {
+            // a name can only be given to one line
+            if let Some(region) = view.sel_regions().last() {
+                let line = self.text.line_of_offset(region.end);
+                self.bookmarks.set(&self.text, line, name);
+            }
+        } else {
+            for line in self.selected_lines(view) {
+                self.bookmarks.set(&self.text, line, None);
+            }
+        }
+    }
+
+    fn do_clear_bookmark(&mut self, view: &View) {
+        for line in self.selected_lines(view) {
+            self.bookmarks.clear(&self.text, line);
+        }
+    }
+
+    fn do_toggle_bookmark(&mut self, view: &View) {
+        for line in self.selected_lines(view) {
+            self.bookmarks.toggle(&self.text, line);
+        }
+    }
+
+    fn do_next_bookmark(&mut self, view: &mut View, forward: bool) {
+        let offset = match view.sel_regions().last() {
+            Some(region) => region.end,
+            None => return,
+        };
+        let next = if forward {
+            self.bookmarks.next(&self.text, offset)
+        } else {
+            self.bookmarks.previous(&self.text, offset)
+        };
+        if let Some(offset) = next {
+            view.record_jump(&self.text);
+            view.set_selection(&self.text, SelRegion::caret(offset));
+        }
+    }
+
+    fn do_goto_bookmark(&mut self, view: &mut View, name: &str) {
+        match self.bookmarks.named(name) {
+            Some(offset) => {
+                view.record_jump(&self.text);
+                view.set_selection(&self.text, SelRegion::caret(offset));
+            }
+            None => warn!("no bookmark named {}", name),
+        }
+    }
+
+    /// Returns the bookmarks of the buffer, in the view's line numbers.
+    pub(crate) fn bookmarks_json(&self, view: &View) -> Value {
+        self.bookmarks.to_json(view, &self.text)
     }
 
     fn do_replace(&mut self, view: &mut View, replace_all: bool) {
@@ -391,23 +648,28 @@ impl Editor {
         view: &View,
         movement: Movement,
         save: bool,
-        kill_ring: &mut Rope,
+        kill_ring: &mut KillRing,
     ) {
-        let (delta, rope) = edit_ops::delete_by_movement(
+        let (delta, killed) = edit_ops::delete_by_movement(
             &self.text,
             view.sel_regions(),
             view.get_lines(),
             movement,
             view.scroll_height(),
+            view.word_separators(),
             save,
         );
-        if let Some(rope) = rope {
-            *kill_ring = rope;
+        if delta.is_identity() {
+            return;
         }
-        if !delta.is_identity() {
-            self.this_edit_type = EditType::Delete;
-            self.add_delta(delta);
+        if let Some(killed) = killed {
+            if killed.iter().any(|piece| !piece.is_empty()) {
+                // consecutive kills are accumulated in the same kill
+                kill_ring.kill(killed, self.last_edit_type == EditType::Kill);
+            }
         }
+        self.this_edit_type = if save { EditType::Kill } else { EditType::Delete };
+        self.add_delta(delta);
     }
 
     fn do_delete_backward(&mut self, view: &View, config: &BufferItems) {
@@ -447,6 +709,16 @@ impl Editor {
         view.set_selection(&self.text, final_selection);
     }
 
+    fn do_convert_case(&mut self, view: &View, case: Case) {
+        let delta = edit_ops::transform_text(&self.text, view.sel_regions(), |s| {
+            edit_ops::convert_case(s, case)
+        });
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Replace;
+            self.add_delta(delta);
+        }
+    }
+
     fn do_modify_indent(&mut self, view: &View, config: &BufferItems, direction: IndentDirection) {
         let delta = edit_ops::modify_indent(&self.text, view.sel_regions(), config, direction);
         self.add_delta(delta);
@@ -479,12 +751,92 @@ impl Editor {
             if regions.len() > 1 || condition { EditType::Indent } else { EditType::InsertChars };
     }
 
-    fn do_yank(&mut self, view: &View, kill_ring: &Rope) {
-        // TODO: if there are multiple cursors and the number of newlines
-        // is one less than the number of cursors, split and distribute one
-        // line per cursor.
-        let delta = edit_ops::insert(&self.text, view.sel_regions(), kill_ring.clone());
-        self.add_delta(delta);
+    fn do_yank(&mut self, view: &View, kill_ring: &mut KillRing) {
+        let regions = view.sel_regions();
+        if let Some(pieces) = kill_ring.yank(0, regions.len()) {
+            let mut builder = DeltaBuilder::new(self.text.len());
+            for (region, piece) in regions.iter().zip(pieces) {
+                builder.replace(region.min()..region.max(), piece);
+            }
+            self.this_edit_type = EditType::Yank;
+            self.add_delta(builder.build());
+        }
+    }
+
+    /// Inserts the last kill as whole lines below the line of each region,
+    /// leaving the regions where they are.
+    fn do_yank_lines(&mut self, view: &View, config: &BufferItems, kill_ring: &mut KillRing) {
+        let regions = view.sel_regions();
+        let pieces = match kill_ring.yank(0, regions.len()) {
+            Some(pieces) => pieces,
+            None => return,
+        };
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for (region, piece) in regions.iter().zip(pieces) {
+            let line = LogicalLines.line_of_offset(&self.text, region.max());
+            let line_start = LogicalLines.offset_of_line(&self.text, line);
+            let mut line_end = LogicalLines.offset_of_line(&self.text, line + 1);
+            if line_end > line_start && self.text.byte_at(line_end - 1) == b'\n' {
+                line_end -= 1;
+                if line_end > line_start && self.text.byte_at(line_end - 1) == b'\r' {
+                    line_end -= 1;
+                }
+            }
+            let piece = String::from(piece);
+            let piece = piece.strip_suffix('\n').unwrap_or(&piece);
+            let piece = piece.strip_suffix('\r').unwrap_or(piece);
+            let lines = format!("{}{}", config.line_ending, piece);
+            builder.replace(Interval::new(line_end, line_end), lines.into());
+        }
+        self.this_edit_type = EditType::YankLines;
+        self.add_delta(builder.build());
+    }
+
+    /// Replaces the text inserted by the previous yank with the kill before it.
+    fn do_yank_pop(&mut self, view: &View, kill_ring: &mut KillRing) {
+        if self.last_edit_type != EditType::Yank {
+            return;
+        }
+        let regions = view.sel_regions();
+        let (index, yanked) = match kill_ring.last_yank() {
+            Some((index, yanked)) if yanked.len() == regions.len() => (index, yanked.to_vec()),
+            _ => return,
+        };
+        // the carets are after the yanked text, unless it was edited since
+        if regions.iter().zip(&yanked).any(|(region, &len)| !region.is_caret() || region.end < len)
+        {
+            return;
+        }
+        let pieces = match kill_ring.yank((index + 1) % kill_ring.len(), regions.len()) {
+            Some(pieces) => pieces,
+            None => return,
+        };
+        let mut builder = DeltaBuilder::new(self.text.len());
+        for ((region, len), piece) in regions.iter().zip(yanked).zip(pieces) {
+            builder.replace(region.end - len..region.end, piece);
+        }
+        self.this_edit_type = EditType::Yank;
+        self.add_delta(builder.build());
+    }
+
+    /// Saves the text of the non-empty selections in the kill ring, and
+    /// deletes it if `delete` is set.
+    fn do_kill_selection(&mut self, view: &View, kill_ring: &mut KillRing, delete: bool) {
+        let regions = view
+            .sel_regions()
+            .iter()
+            .filter(|region| !region.is_caret())
+            .copied()
+            .collect::<Vec<_>>();
+        if regions.is_empty() {
+            return;
+        }
+        let pieces = regions.iter().map(|region| self.text.subseq(region.min()..region.max()));
+        kill_ring.kill(pieces.collect(), false);
+        if delete {
+            self.this_edit_type = EditType::Kill;
+            self.add_delta(edit_ops::delete_sel_regions(&self.text, &regions));
+        }
     }
 
     fn do_duplicate_line(&mut self, view: &View, config: &BufferItems) {
@@ -505,10 +857,83 @@ impl Editor {
         }
     }
 
+    fn do_move_lines(&mut self, view: &View, direction: LineDirection) {
+        let delta = edit_ops::move_lines(&self.text, view.sel_regions(), direction);
+        if !delta.is_identity() {
+            self.this_edit_type = match direction {
+                LineDirection::Up => EditType::MoveLinesUp,
+                LineDirection::Down => EditType::MoveLinesDown,
+            };
+            self.add_delta(delta);
+        }
+    }
+
+    fn do_join_lines(&mut self, view: &View) {
+        let delta = edit_ops::join_lines(&self.text, view.sel_regions());
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    fn do_reflow_paragraph(&mut self, view: &View, config: &BufferItems) {
+        let delta = edit_ops::reflow_paragraphs(&self.text, view.sel_regions(), config);
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Replace;
+            self.add_delta(delta);
+        }
+    }
+
+    fn do_convert_indentation(&mut self, view: &View, config: &BufferItems, use_tabs: bool) {
+        // `tab_size` comes from the indentation detected when the file was
+        // opened, unless the file mixes tabs and spaces
+        let delta = edit_ops::convert_indentation(
+            &self.text,
+            view.sel_regions(),
+            use_tabs,
+            config.tab_size,
+        );
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    fn do_convert_line_endings(&mut self, line_ending: LineEnding) {
+        let delta = edit_ops::convert_line_endings(&self.text, line_ending.as_str());
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    /// Cleans up the whitespace of the buffer as configured for saving.
+    pub(crate) fn clean_up_whitespace(&mut self, config: &BufferItems) {
+        let delta = edit_ops::clean_up_whitespace(
+            &self.text,
+            config.trim_trailing_whitespace,
+            config.ensure_single_final_newline,
+            &config.line_ending,
+        );
+        if !delta.is_identity() {
+            self.this_edit_type = EditType::Other;
+            self.add_delta(delta);
+        }
+    }
+
+    fn do_reorder_lines(&mut self, view: &mut View, order: LineOrder) {
+        let (delta, selection) = edit_ops::reorder_lines(&self.text, view.sel_regions(), order);
+        if !delta.is_identity() {
+            view.set_selection(&self.text, selection);
+            self.this_edit_type = EditType::Replace;
+            self.add_delta(delta);
+        }
+    }
+
     pub(crate) fn do_edit(
         &mut self,
         view: &mut View,
-        kill_ring: &mut Rope,
+        kill_ring: &mut KillRing,
         config: &BufferItems,
         cmd: BufferEvent,
     ) {
@@ -521,9 +946,19 @@ impl Editor {
             Transpose => self.do_transpose(view),
             Undo => self.do_undo(),
             Redo => self.do_redo(),
+            JumpToUndoNode(group) => self.do_jump_to_undo_node(group),
+            RevertToRevision(rev) => self.do_revert_to_revision(rev),
+            SetBookmark(name) => self.do_set_bookmark(view, name),
+            ClearBookmark => self.do_clear_bookmark(view),
+            ToggleBookmark => self.do_toggle_bookmark(view),
+            ClearAllBookmarks => self.bookmarks.clear_all(),
+            NextBookmark => self.do_next_bookmark(view, true),
+            PreviousBookmark => self.do_next_bookmark(view, false),
+            GotoBookmark(name) => self.do_goto_bookmark(view, &name),
             Uppercase => self.do_transform_text(view, |s| s.to_uppercase()),
             Lowercase => self.do_transform_text(view, |s| s.to_lowercase()),
             Capitalize => self.do_capitalize_text(view),
+            ConvertCase(case) => self.do_convert_case(view, case),
             Indent => self.do_modify_indent(view, config, IndentDirection::In),
             Outdent => self.do_modify_indent(view, config, IndentDirection::Out),
             InsertNewline => self.do_insert_newline(view, config),
@@ -531,11 +966,34 @@ impl Editor {
             Insert(chars) => self.do_insert(view, config, &chars),
             Paste(chars) => self.do_paste(view, &chars),
             Yank => self.do_yank(view, kill_ring),
+            YankLines => self.do_yank_lines(view, config, kill_ring),
+            YankPop => self.do_yank_pop(view, kill_ring),
+            KillSelection => self.do_kill_selection(view, kill_ring, true),
+            CopyToKillRing => self.do_kill_selection(view, kill_ring, false),
             ReplaceNext => self.do_replace(view, false),
             ReplaceAll => self.do_replace(view, true),
             DuplicateLine => self.do_duplicate_line(view, config),
             IncreaseNumber => self.do_change_number(view, |s| s.checked_add(1)),
             DecreaseNumber => self.do_change_number(view, |s| s.checked_sub(1)),
+            SortLines => self.do_reorder_lines(view, LineOrder::Sort),
+            SortLinesCaseInsensitive => self.do_reorder_lines(view, LineOrder::SortCaseInsensitive),
+            SortLinesNatural => self.do_reorder_lines(view, LineOrder::SortNatural),
+            UniqueLines => self.do_reorder_lines(view, LineOrder::Unique),
+            ReverseLines => self.do_reorder_lines(view, LineOrder::Reverse),
+            MoveLinesUp => self.do_move_lines(view, LineDirection::Up),
+            MoveLinesDown => self.do_move_lines(view, LineDirection::Down),
+            JoinLines => self.do_join_lines(view),
+            ReflowParagraph => self.do_reflow_paragraph(view, config),
+            ConvertIndentationToSpaces => self.do_convert_indentation(view, config, false),
+            ConvertIndentationToTabs => self.do_convert_indentation(view, config, true),
+            ConvertLineEndings(line_ending) => self.do_convert_line_endings(line_ending),
+            ShuffleLines => {
+                let seed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_nanos() as u64)
+                    .unwrap_or(0);
+                self.do_reorder_lines(view, LineOrder::Shuffle(seed))
+            }
         }
     }
 
@@ -619,6 +1077,40 @@ impl Editor {
         view.update_annotations(plugin, iv, Annotations { items: spans, annotation_type });
     }
 
+    pub fn update_fold_ranges(
+        &mut self,
+        plugin: PluginId,
+        start: usize,
+        len: usize,
+        ranges: Vec<FoldRange>,
+        rev: RevToken,
+    ) {
+        let _t = trace_block("Editor::update_fold_ranges", &["core"]);
+
+        let mut start = start;
+        let mut end_offset = start + len;
+        let mut ranges = ranges
+            .iter()
+            .map(|range| Interval::new(start + range.start, start + range.end))
+            .collect::<Vec<_>>();
+        if rev != self.engine.get_head_rev_id().token() {
+            if let Ok(delta) = self.engine.try_delta_rev_head(rev) {
+                let mut transformer = Transformer::new(&delta);
+                for range in ranges.iter_mut() {
+                    let range_start = transformer.transform(range.start, false);
+                    let range_end = transformer.transform(range.end, false);
+                    *range = Interval::new(range_start, range_end);
+                }
+                start = transformer.transform(start, false);
+                end_offset = transformer.transform(end_offset, true);
+            } else {
+                error!("Revision {} not found", rev);
+            }
+        }
+        let iv = Interval::new(start, end_offset);
+        self.fold_ranges.update(plugin, iv, ranges);
+    }
+
     pub(crate) fn get_rev(&self, rev: RevToken) -> Option<Cow<Rope>> {
         let text_cow = if rev == self.engine.get_head_rev_id().token() {
             Cow::Borrowed(&self.text)
@@ -662,6 +1154,19 @@ impl Editor {
     }
 }
 
+/// The state needed to undo and redo the edits of an `Editor` in a later
+/// session.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct UndoHistory<E, T> {
+    engine: E,
+    undo_group_id: usize,
+    live_undos: Vec<usize>,
+    cur_undo: usize,
+    undos: BTreeSet<usize>,
+    gc_undos: BTreeSet<usize>,
+    undo_tree: T,
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EditType {
@@ -676,16 +1181,30 @@ pub enum EditType {
     /// An indentation adjustment.
     Indent,
     Delete,
+    /// A deletion saving the deleted text in the kill ring.
+    Kill,
+    /// An insertion of text from the kill ring.
+    Yank,
+    /// An insertion of lines from the kill ring below the carets' lines.
+    YankLines,
     Undo,
     Redo,
     Transpose,
     Surround,
+    /// A replacement of each selection as a whole, such as sorting its lines,
+    /// after which the selections cover the new text.
+    Replace,
+    MoveLinesUp,
+    MoveLinesDown,
 }
 
 impl EditType {
     /// Checks whether a new undo group should be created between two edits.
     fn breaks_undo_group(self, previous: EditType) -> bool {
-        self == EditType::Other || self == EditType::Transpose || self != previous
+        self == EditType::Other
+            || self == EditType::Transpose
+            || self == EditType::Replace
+            || self != previous
     }
 }
 
@@ -734,4 +1253,87 @@ mod tests {
 
         assert_eq!(editor.get_buffer().to_string(), "sshello");
     }
+
+    #[test]
+    fn undo_history_round_trip() {
+        let mut editor = Editor::with_text("hello");
+        let mut builder = DeltaBuilder::new("hello".len());
+        builder.replace(5..5, " world".into());
+        editor.add_delta(builder.build());
+        editor.commit_delta();
+
+        let json = serde_json::to_string(&editor.undo_history()).unwrap();
+        let mut editor = Editor::with_undo_history(
+            serde_json::from_str(&json).unwrap(),
+            Rope::from("hello world"),
+        );
+        assert_eq!(editor.get_buffer().to_string(), "hello world");
+        assert!(editor.is_pristine());
+
+        let mut builder = DeltaBuilder::new("hello world".len());
+        builder.replace(11..11, "!".into());
+        editor.add_delta(builder.build());
+        editor.commit_delta();
+        assert_eq!(editor.get_buffer().to_string(), "hello world!");
+        editor.do_undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello world");
+        editor.do_undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello");
+        editor.do_redo();
+        assert_eq!(editor.get_buffer().to_string(), "hello world");
+
+        // a different text is applied as a new edit
+        let mut editor = Editor::with_undo_history(
+            serde_json::from_str(&json).unwrap(),
+            Rope::from("hello world\n"),
+        );
+        assert_eq!(editor.get_buffer().to_string(), "hello world\n");
+        assert!(editor.is_pristine());
+        editor.do_undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello world");
+    }
+
+    #[test]
+    fn revert_to_revision() {
+        let mut editor = Editor::with_text("hello");
+        let opened_rev = editor.get_head_rev_token();
+        let mut builder = DeltaBuilder::new("hello".len());
+        builder.replace(0..5, "goodbye".into());
+        editor.add_delta(builder.build());
+        let rev = editor.get_head_rev_token();
+
+        let mut builder = DeltaBuilder::new("goodbye".len());
+        builder.replace(7..7, " world".into());
+        let edit = PluginEdit {
+            rev,
+            delta: builder.build(),
+            priority: 55,
+            after_cursor: false,
+            undo_group: None,
+            author: "plugin_one".into(),
+        };
+        editor.apply_plugin_edit(edit);
+        assert_eq!(editor.get_buffer().to_string(), "goodbye world");
+
+        let history = editor.revision_history_json();
+        let revisions = history.as_array().unwrap();
+        assert_eq!(revisions.len(), 3);
+        // the revision the buffer was opened at comes first
+        assert_eq!(revisions[0]["rev"], json!(opened_rev));
+        assert_eq!(revisions[1]["rev"], json!(rev));
+        assert_eq!(revisions[1]["author"], json!("core"));
+        assert_eq!(revisions[1]["inserted"], json!(7));
+        assert_eq!(revisions[1]["deleted"], json!(5));
+        assert_eq!(revisions[2]["author"], json!("plugin_one"));
+        assert_eq!(revisions[2]["inserted"], json!(6));
+
+        editor.do_revert_to_revision(rev);
+        assert_eq!(editor.get_buffer().to_string(), "goodbye");
+        // the revert is an edit of its own, which can be undone
+        editor.do_undo();
+        assert_eq!(editor.get_buffer().to_string(), "goodbye world");
+
+        editor.do_revert_to_revision(opened_rev);
+        assert_eq!(editor.get_buffer().to_string(), "hello");
+    }
 }
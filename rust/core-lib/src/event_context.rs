@@ -29,16 +29,23 @@ use xi_trace::trace_block;
 use crate::plugins::rpc::{
     ClientPluginInfo, Hover, PluginBufferInfo, PluginNotification, PluginRequest, PluginUpdate,
 };
-use crate::rpc::{EditNotification, EditRequest, LineRange, Position as ClientPosition};
+use crate::rpc::{
+    EditNotification, EditRequest, GestureType, LineRange, Position as ClientPosition,
+};
 
+use crate::brackets::BracketMatcher;
 use crate::client::Client;
 use crate::config::{BufferItems, Table};
-use crate::edit_types::{EventDomain, SpecialEvent};
+use crate::edit_types::{BufferEvent, EventDomain, SpecialEvent, ViewEvent};
 use crate::editor::Editor;
 use crate::file::FileInfo;
+use crate::kill_ring::KillRing;
 use crate::line_offset::LineOffset;
+use crate::modal;
+use crate::movement::Movement;
 use crate::plugins::Plugin;
 use crate::recorder::Recorder;
+use crate::registers::Registers;
 use crate::selection::InsertDrift;
 use crate::styles::ThemeStyleMap;
 use crate::syntax::LanguageId;
@@ -46,7 +53,7 @@ use crate::tabs::{
     BufferId, PluginId, ViewId, FIND_VIEW_IDLE_MASK, RENDER_VIEW_IDLE_MASK, REWRAP_VIEW_IDLE_MASK,
 };
 use crate::view::View;
-use crate::width_cache::WidthCache;
+use crate::width_cache::{CodepointMono, ColumnMeasure, WidthCache, WidthMeasure};
 use crate::WeakXiCore;
 
 // Maximum returned result from plugin get_data RPC.
@@ -76,7 +83,8 @@ pub struct EventContext<'a> {
     pub(crate) client: &'a Client,
     pub(crate) style_map: &'a RefCell<ThemeStyleMap>,
     pub(crate) width_cache: &'a RefCell<WidthCache>,
-    pub(crate) kill_ring: &'a RefCell<Rope>,
+    pub(crate) kill_ring: &'a RefCell<KillRing>,
+    pub(crate) registers: &'a RefCell<Registers>,
     pub(crate) weak_core: &'a WeakXiCore,
 }
 
@@ -85,7 +93,7 @@ impl<'a> EventContext<'a> {
     /// common in edit actions that modify the text.
     pub(crate) fn with_editor<R, F>(&mut self, f: F) -> R
     where
-        F: FnOnce(&mut Editor, &mut View, &mut Rope, &BufferItems) -> R,
+        F: FnOnce(&mut Editor, &mut View, &mut KillRing, &BufferItems) -> R,
     {
         let mut editor = self.editor.borrow_mut();
         let mut view = self.view.borrow_mut();
@@ -105,11 +113,42 @@ impl<'a> EventContext<'a> {
         f(&mut view, editor.get_buffer())
     }
 
+    /// Executes a closure with a mutable reference to the view, the current
+    /// text, and a `ColumnMeasure` matching the way the view is wrapped.
+    fn with_columns<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut View, &Rope, &mut ColumnMeasure) -> R,
+    {
+        let editor = self.editor.borrow();
+        let mut view = self.view.borrow_mut();
+        let mut width_cache = self.width_cache.borrow_mut();
+        let measure: &dyn WidthMeasure =
+            if self.config.word_wrap { self.client } else { &CodepointMono };
+        let mut columns = ColumnMeasure::new(&mut width_cache, measure, self.config.tab_size);
+        f(&mut view, editor.get_buffer(), &mut columns)
+    }
+
     fn with_each_plugin<F: FnMut(&&Plugin)>(&self, f: F) {
         self.plugins.iter().for_each(f)
     }
 
     pub(crate) fn do_edit(&mut self, cmd: EditNotification) {
+        let cmd = match cmd {
+            // pasting from a register pastes its contents
+            EditNotification::Paste { register: Some(name), .. } => {
+                match self.registers.borrow().get(&name) {
+                    Ok(Some(text)) => {
+                        EditNotification::Paste { chars: text.into(), register: None }
+                    }
+                    Ok(None) => return,
+                    Err(err) => {
+                        warn!("{}", err);
+                        return;
+                    }
+                }
+            }
+            cmd => cmd,
+        };
         let event: EventDomain = cmd.into();
 
         {
@@ -119,6 +158,8 @@ impl<'a> EventContext<'a> {
                 (_, EventDomain::Special(SpecialEvent::ToggleRecording(recording_name))) => {
                     recorder.toggle_recording(recording_name.clone());
                 }
+                // keys are recorded as the events they stand for
+                (_, EventDomain::Special(SpecialEvent::Key(_))) => {}
                 // Don't save special events
                 (true, EventDomain::Special(_)) => {
                     warn!("Special events cannot be recorded-- ignoring event {:?}", event)
@@ -135,9 +176,68 @@ impl<'a> EventContext<'a> {
 
     fn dispatch_event(&mut self, event: EventDomain) {
         use self::EventDomain as E;
+        {
+            // typing and searching fill the special registers
+            let mut registers = self.registers.borrow_mut();
+            match &event {
+                E::Buffer(BufferEvent::Insert(chars)) => registers.record_insert(chars),
+                E::Special(_) => (),
+                E::View(ViewEvent::Find { chars, .. }) => {
+                    registers.end_insert();
+                    registers.record_search(chars);
+                }
+                _ => registers.end_insert(),
+            }
+        }
         match event {
             E::View(cmd) => {
-                self.with_view(|view, text| view.do_edit(text, cmd));
+                match cmd {
+                    // folding uses the fold ranges provided by plugins, when available
+                    ViewEvent::Fold => self.with_editor(|ed, view, _, _| {
+                        view.fold(ed.get_buffer(), ed.get_fold_ranges())
+                    }),
+                    ViewEvent::ToggleFold => self.with_editor(|ed, view, _, _| {
+                        view.toggle_fold(ed.get_buffer(), ed.get_fold_ranges())
+                    }),
+                    // bracket pairs come from the buffer config
+                    ViewEvent::Move(Movement::MatchingBracket)
+                    | ViewEvent::ModifySelection(Movement::MatchingBracket) => {
+                        let modify = matches!(cmd, ViewEvent::ModifySelection(_));
+                        self.with_editor(|ed, view, _, config| {
+                            let brackets = BracketMatcher::from_config(config, ed.get_layers());
+                            view.do_move(
+                                ed.get_buffer(),
+                                Movement::MatchingBracket,
+                                &brackets,
+                                modify,
+                            )
+                        })
+                    }
+                    ViewEvent::ExpandSelection => self.with_editor(|ed, view, _, config| {
+                        let brackets = BracketMatcher::from_config(config, ed.get_layers());
+                        view.expand_selection(ed.get_buffer(), &brackets, ed.get_layers())
+                    }),
+                    // rectangular selections need to measure columns
+                    ViewEvent::Gesture { line, col, ty: GestureType::BlockSelect } => self
+                        .with_columns(|view, text, columns| {
+                            view.block_select(text, line as usize, col as usize, false, columns)
+                        }),
+                    ViewEvent::Gesture { line, col, ty: GestureType::Drag }
+                        if self.view.borrow().is_block_selecting() =>
+                    {
+                        self.with_columns(|view, text, columns| {
+                            view.block_select(text, line as usize, col as usize, true, columns)
+                        })
+                    }
+                    ViewEvent::ExtendBlockSelection(movement) => {
+                        self.with_columns(|view, text, columns| {
+                            view.extend_block_selection(text, movement, columns)
+                        })
+                    }
+                    // highlighting every match would search all of a large file
+                    ViewEvent::HighlightFind { visible: true } if self.is_large_file() => (),
+                    cmd => self.with_view(|view, text| view.do_edit(text, cmd)),
+                }
                 self.editor.borrow_mut().update_edit_type();
                 if self.with_view(|v, t| v.needs_wrap_in_visible_region(t)) {
                     self.rewrap();
@@ -146,6 +246,10 @@ impl<'a> EventContext<'a> {
                     self.do_incremental_find();
                 }
             }
+            // read-only buffers can't be changed
+            E::Buffer(ref cmd) if cmd.is_mutating() && self.config.read_only => {}
+            E::Special(SpecialEvent::Reindent | SpecialEvent::DebugToggleComment)
+                if self.config.read_only => {}
             E::Buffer(cmd) => {
                 self.with_editor(|ed, view, k_ring, conf| ed.do_edit(view, k_ring, conf, cmd))
             }
@@ -179,52 +283,107 @@ impl<'a> EventContext<'a> {
             SpecialEvent::Reindent => self.do_reindent(),
             SpecialEvent::ToggleRecording(_) => {}
             SpecialEvent::PlayRecording(recording_name) => {
-                let recorder = self.recorder.borrow();
+                let mut events = Vec::new();
+                self.recorder.borrow().play(&recording_name, |event| events.push(event.clone()));
+                self.dispatch_group(events);
+            }
+            SpecialEvent::ClearRecording(recording_name) => {
+                let mut recorder = self.recorder.borrow_mut();
+                recorder.clear(&recording_name);
+            }
+            SpecialEvent::Key(key) => self.do_key(&key),
+        }
+    }
 
-                let starting_revision = self.editor.borrow_mut().get_head_rev_token();
+    /// Dispatches a sequence of events as a single undo group.
+    fn dispatch_group(&mut self, events: Vec<EventDomain>) {
+        let starting_revision = self.editor.borrow_mut().get_head_rev_token();
+
+        // Don't group with the previous action
+        self.editor.borrow_mut().update_edit_type();
+        self.editor.borrow_mut().calculate_undo_group();
+
+        // No matter what, our entire block must belong to the same undo group
+        self.editor.borrow_mut().set_force_undo_group(true);
+        for event in events {
+            self.dispatch_event(event);
+
+            let mut editor = self.editor.borrow_mut();
+            let (delta, last_text, drift) = match editor.commit_delta() {
+                Some(edit_info) => edit_info,
+                None => continue,
+            };
+            self.update_views(&editor, &delta, &last_text, drift);
+        }
+        self.editor.borrow_mut().set_force_undo_group(false);
 
-                // Don't group with the previous action
-                self.editor.borrow_mut().update_edit_type();
-                self.editor.borrow_mut().calculate_undo_group();
-
-                // No matter what, our entire block must belong to the same undo group
-                self.editor.borrow_mut().set_force_undo_group(true);
-                recorder.play(&recording_name, |event| {
-                    self.dispatch_event(event.clone());
-
-                    let mut editor = self.editor.borrow_mut();
-                    let (delta, last_text, drift) = match editor.commit_delta() {
-                        Some(edit_info) => edit_info,
-                        None => return,
-                    };
-                    self.update_views(&editor, &delta, &last_text, drift);
-                });
-                self.editor.borrow_mut().set_force_undo_group(false);
+        // The action that follows the block must belong to a separate undo group
+        self.editor.borrow_mut().update_edit_type();
 
-                // The action that follows the block must belong to a separate undo group
-                self.editor.borrow_mut().update_edit_type();
+        let delta = self.editor.borrow_mut().delta_rev_head(starting_revision).unwrap();
+        self.update_plugins(&mut self.editor.borrow_mut(), delta, "core");
+    }
 
-                let delta = self.editor.borrow_mut().delta_rev_head(starting_revision).unwrap();
-                self.update_plugins(&mut self.editor.borrow_mut(), delta, "core");
+    /// Interprets a key with the modal editing layer, and dispatches the
+    /// events it stands for.
+    fn do_key(&mut self, key: &str) {
+        let (events, old_mode, new_mode) = {
+            let mut view = self.view.borrow_mut();
+            let old_mode = view.mode();
+            let events = view.handle_key(key);
+            (events, old_mode, view.mode())
+        };
+        {
+            let mut recorder = self.recorder.borrow_mut();
+            if recorder.is_recording() {
+                events.iter().for_each(|event| recorder.record(event.clone()));
             }
-            SpecialEvent::ClearRecording(recording_name) => {
-                let mut recorder = self.recorder.borrow_mut();
-                recorder.clear(&recording_name);
+        }
+        // changes made of several events are undone at once
+        if events.len() > 1 && events.iter().any(modal::is_change) {
+            self.dispatch_group(events);
+        } else {
+            for event in events {
+                self.dispatch_event(event);
+                self.after_edit("core");
             }
         }
+        if new_mode != old_mode {
+            self.client.mode_changed(self.view_id, new_mode);
+        }
     }
 
     pub(crate) fn do_edit_sync(&mut self, cmd: EditRequest) -> Result<Value, RemoteError> {
         use self::EditRequest::*;
         let result = match cmd {
-            Cut => Ok(self.with_editor(|ed, view, _, _| ed.do_cut(view))),
-            Copy => Ok(self.with_editor(|ed, view, _, _| ed.do_copy(view))),
+            Cut { register } if !self.config.read_only => self
+                .copy_to_register(register)
+                .map(|_| self.with_editor(|ed, view, _, _| ed.do_cut(view))),
+            Cut { register } | Copy { register } => self
+                .copy_to_register(register)
+                .map(|_| self.with_editor(|ed, view, _, _| ed.do_copy(view))),
+            UndoTree => Ok(self.with_editor(|ed, _, _, _| ed.undo_tree_json())),
+            Bookmarks => Ok(self.with_editor(|ed, view, _, _| ed.bookmarks_json(view))),
         };
         self.after_edit("core");
         self.render_if_needed();
         result
     }
 
+    /// Copies the active selection into the given register, if any.
+    fn copy_to_register(&mut self, register: Option<String>) -> Result<(), RemoteError> {
+        let name = match register {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let text = self.with_editor(|ed, view, _, _| ed.do_copy(view));
+        let text = text.as_str().unwrap_or_default();
+        self.registers
+            .borrow_mut()
+            .set(&name, text)
+            .map_err(|err| RemoteError::custom(400, err, None))
+    }
+
     pub(crate) fn do_plugin_cmd(&mut self, plugin: PluginId, cmd: PluginNotification) {
         use self::PluginNotification::*;
         match cmd {
@@ -232,10 +391,15 @@ impl<'a> EventContext<'a> {
                 let mut ed = self.editor.borrow_mut();
                 let style_map = self.style_map.borrow();
                 ed.get_layers_mut().add_scopes(plugin, scopes, &style_map);
+                self.clear_matched_brackets();
+            }
+            UpdateSpans { start, len, spans, rev } => {
+                self.with_editor(|ed, view, _, _| {
+                    ed.update_spans(view, plugin, start, len, spans, rev)
+                });
+                // brackets in strings and comments now match differently
+                self.clear_matched_brackets();
             }
-            UpdateSpans { start, len, spans, rev } => self.with_editor(|ed, view, _, _| {
-                ed.update_spans(view, plugin, start, len, spans, rev)
-            }),
             Edit { edit } => self.with_editor(|ed, _, _, _| ed.apply_plugin_edit(edit)),
             Alert { msg } => self.client.alert(&msg),
             AddStatusItem { key, value, alignment } => {
@@ -250,6 +414,8 @@ impl<'a> EventContext<'a> {
                     ed.update_annotations(view, plugin, start, len, spans, annotation_type, rev)
                 })
             }
+            UpdateFoldRanges { start, len, ranges, rev } => self
+                .with_editor(|ed, _, _, _| ed.update_fold_ranges(plugin, start, len, ranges, rev)),
             RemoveStatusItem { key } => self.client.remove_status_item(self.view_id, &key),
             ShowHover { request_id, result } => self.do_show_hover(request_id, result),
         };
@@ -374,6 +540,10 @@ impl<'a> EventContext<'a> {
             self.client,
             self.style_map,
             ed.get_layers().get_merged(),
+            ed.get_fold_ranges(),
+            ed.get_bookmarks(),
+            &BracketMatcher::from_config(self.config, ed.get_layers())
+                .with_rev(ed.get_head_rev_token()),
             ed.is_pristine(),
         )
     }
@@ -386,10 +556,10 @@ impl<'a> EventContext<'a> {
 /// special cases here.
 impl<'a> EventContext<'a> {
     pub(crate) fn view_init(&mut self) {
-        let wrap_width = self.config.wrap_width;
-        let word_wrap = self.config.word_wrap;
+        let (wrap_width, word_wrap) = self.wrap_settings();
 
         self.with_view(|view, text| view.update_wrap_settings(text, wrap_width, word_wrap));
+        self.view.borrow_mut().set_word_separators(&self.config.word_separators);
     }
 
     pub(crate) fn finish_init(&mut self, config: &Table) {
@@ -456,12 +626,26 @@ impl<'a> EventContext<'a> {
             }
             self.update_wrap_settings(true);
         }
+        if changes.contains_key("word_separators") {
+            self.view.borrow_mut().set_word_separators(&self.config.word_separators);
+        }
+        if changes.contains_key("surrounding_pairs") || changes.contains_key("bracket_pairs") {
+            self.clear_matched_brackets();
+        }
 
         self.client.config_changed(self.view_id, changes);
         self.plugins.iter().for_each(|plug| plug.config_changed(self.view_id, changes));
         self.render()
     }
 
+    /// Forgets the brackets matched in every view of the buffer, so they are
+    /// matched again on the next render.
+    fn clear_matched_brackets(&self) {
+        iter::once(&self.view)
+            .chain(self.siblings.iter())
+            .for_each(|view| view.borrow_mut().clear_matched_brackets());
+    }
+
     pub(crate) fn language_changed(&mut self, new_language_id: &LanguageId) {
         self.language = new_language_id.clone();
         self.client.language_changed(self.view_id, new_language_id);
@@ -474,6 +658,14 @@ impl<'a> EventContext<'a> {
         self.render();
     }
 
+    /// Replaces the contents of the buffer with unsaved changes recovered
+    /// after a crash.
+    pub(crate) fn restore(&mut self, text: Rope) {
+        self.with_editor(|ed, _, _, _| ed.restore(text));
+        self.after_edit("core");
+        self.render();
+    }
+
     pub(crate) fn plugin_info(&mut self) -> PluginBufferInfo {
         let ed = self.editor.borrow();
         let nb_lines = ed.get_buffer().measure::<LinesMetric>() + 1;
@@ -503,7 +695,8 @@ impl<'a> EventContext<'a> {
     pub(crate) fn plugin_stopped(&mut self, plugin: &Plugin) {
         self.client.plugin_stopped(self.view_id, &plugin.name, 0);
         let needs_render = self.with_editor(|ed, view, _, _| {
-            if ed.get_layers_mut().remove_layer(plugin.id).is_some() {
+            let had_folds = ed.get_fold_ranges_mut().remove_plugin(plugin.id);
+            if ed.get_layers_mut().remove_layer(plugin.id).is_some() || had_folds {
                 view.set_dirty(ed.get_buffer());
                 true
             } else {
@@ -524,6 +717,17 @@ impl<'a> EventContext<'a> {
         self.editor.borrow_mut().dec_revs_in_flight();
     }
 
+    /// Applies the whitespace cleanup configured for saving, as an edit that
+    /// can be undone.
+    pub(crate) fn before_save(&mut self) {
+        if self.config.read_only {
+            return;
+        }
+        self.with_editor(|ed, _, _, config| ed.clean_up_whitespace(config));
+        self.after_edit("core");
+        self.render_if_needed();
+    }
+
     /// Returns the text to be saved, appending a newline if necessary.
     pub(crate) fn text_for_save(&mut self) -> Rope {
         let editor = self.editor.borrow();
@@ -553,8 +757,7 @@ impl<'a> EventContext<'a> {
     /// except in the resize case; during live resize we want to delay recalculation
     /// to avoid unnecessary work.
     fn update_wrap_settings(&mut self, rewrap_immediately: bool) {
-        let wrap_width = self.config.wrap_width;
-        let word_wrap = self.config.word_wrap;
+        let (wrap_width, word_wrap) = self.wrap_settings();
         self.with_view(|view, text| view.update_wrap_settings(text, wrap_width, word_wrap));
         if rewrap_immediately {
             self.rewrap();
@@ -565,6 +768,37 @@ impl<'a> EventContext<'a> {
         }
     }
 
+    /// Returns the wrap width and whether to wrap words. Large files are not
+    /// wrapped, since wrapping all their lines takes too long.
+    fn wrap_settings(&self) -> (usize, bool) {
+        if self.is_large_file() {
+            (0, false)
+        } else {
+            (self.config.wrap_width, self.config.word_wrap)
+        }
+    }
+
+    /// Returns `true` if the buffer is a large file, for which expensive
+    /// features are disabled.
+    pub(crate) fn is_large_file(&self) -> bool {
+        self.info.is_some_and(|info| info.large_file)
+    }
+
+    /// Enables the features disabled for a large file: starts the running
+    /// plugins for the buffer, and wraps it according to the config.
+    pub(crate) fn exit_large_file_mode(&mut self) {
+        if !self.plugins.is_empty() {
+            let info = self.plugin_info();
+            self.plugins.iter().for_each(|plugin| {
+                plugin.new_buffer(&info);
+                self.plugin_started(plugin);
+            });
+        }
+        self.client.large_file_mode(self.view_id, false);
+        self.update_wrap_settings(true);
+        self.render();
+    }
+
     /// Tells the view to rewrap a batch of lines, if needed. This guarantees that
     /// the currently visible region will be correctly wrapped; the caller should
     /// check if additional wrapping is necessary and schedule that if so.
@@ -627,6 +861,10 @@ impl<'a> EventContext<'a> {
             self.client,
             self.style_map,
             ed.get_layers().get_merged(),
+            ed.get_fold_ranges(),
+            ed.get_bookmarks(),
+            &BracketMatcher::from_config(self.config, ed.get_layers())
+                .with_rev(ed.get_head_rev_token()),
             first,
             last,
             ed.is_pristine(),
@@ -724,7 +962,8 @@ mod tests {
         editor: RefCell<Editor>,
         client: Client,
         core_ref: WeakXiCore,
-        kill_ring: RefCell<Rope>,
+        kill_ring: RefCell<KillRing>,
+        registers: RefCell<Registers>,
         style_map: RefCell<ThemeStyleMap>,
         width_cache: RefCell<WidthCache>,
         config_manager: ConfigManager,
@@ -743,12 +982,13 @@ mod tests {
             let editor = RefCell::new(Editor::with_text(s));
             let client = Client::new(Box::new(DummyPeer));
             let core_ref = dummy_weak_core();
-            let kill_ring = RefCell::new(Rope::from(""));
+            let kill_ring = RefCell::new(KillRing::default());
+            let registers = RefCell::new(Registers::default());
             let style_map = RefCell::new(ThemeStyleMap::new(None));
             let width_cache = RefCell::new(WidthCache::new());
             let recorder = RefCell::new(Recorder::new());
             let harness = ContextHarness { view, editor, client, core_ref, kill_ring,
-                             style_map, width_cache, config_manager, recorder };
+                             registers, style_map, width_cache, config_manager, recorder };
             harness.make_context().view_init();
             harness.make_context().finish_init(&config);
             harness
@@ -801,6 +1041,7 @@ mod tests {
                 recorder: &self.recorder,
                 client: &self.client,
                 kill_ring: &self.kill_ring,
+                registers: &self.registers,
                 style_map: &self.style_map,
                 width_cache: &self.width_cache,
                 weak_core: &self.core_ref,
@@ -2020,4 +2261,736 @@ mod tests {
 
         assert_eq!(harness.debug_render(), "wor\nd|");
     }
+
+    #[test]
+    fn fold_and_move() {
+        let harness = ContextHarness::new("fn a() {\n    one;\n    two;\n}\nend");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Fold);
+        assert_eq!(ctx.view.borrow().line_of_offset(ctx.editor.borrow().get_buffer(), 32), 2);
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(),"\
+        fn a() {\n    one;\n    two;\n|}\nend");
+        ctx.do_edit(EditNotification::MoveLeft);
+        assert_eq!(harness.debug_render(),"\
+        fn a() {|\n    one;\n    two;\n}\nend");
+        ctx.do_edit(EditNotification::MoveRight);
+        assert_eq!(harness.debug_render(),"\
+        fn a() {\n    one;\n    two;\n|}\nend");
+
+        // the caret is not on the fold's lines
+        ctx.do_edit(EditNotification::Unfold);
+        ctx.do_edit(EditNotification::MoveUp);
+        assert_eq!(harness.debug_render(),"\
+        |fn a() {\n    one;\n    two;\n}\nend");
+
+        ctx.do_edit(EditNotification::ToggleFold);
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(),"\
+        fn a() {\n|    one;\n    two;\n}\nend");
+    }
+
+    #[test]
+    fn fold_selection_and_reveal() {
+        use crate::rpc::{GestureType::*, SelectionModifier};
+
+        let harness = ContextHarness::new("a\nb\nc\nd\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveDownAndModifySelection);
+        ctx.do_edit(EditNotification::MoveDownAndModifySelection);
+        ctx.do_edit(EditNotification::Fold);
+        // the end of the selection is moved out of the fold
+        assert_eq!(harness.debug_render(), "a|\nb\nc\nd\n");
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(), "a\nb\nc\nd|\n");
+
+        // selecting hidden text unfolds
+        ctx.do_edit(EditNotification::Fold);
+        ctx.do_edit(EditNotification::Find { chars: "c".into(), case_sensitive: false,
+                                             regex: false, whole_words: false });
+        ctx.do_edit(EditNotification::FindNext { wrap_around: true, allow_same: false,
+                                                 modify_selection: SelectionModifier::Set });
+        assert_eq!(harness.debug_render(), "a\nb\n[c|]\nd\n");
+        ctx.do_edit(EditNotification::UnfoldAll);
+        ctx.do_edit(EditNotification::MoveUp);
+        assert_eq!(harness.debug_render(), "a\n|b\nc\nd\n");
+    }
+
+    #[test]
+    fn fold_plugin_ranges() {
+        use crate::plugins::rpc::{PluginNotification, FoldRange};
+        use crate::plugins::PluginPid;
+
+        let harness = ContextHarness::new("a\nb\nc\nd\n");
+        let mut ctx = harness.make_context();
+        let rev = ctx.editor.borrow().get_head_rev_token();
+        let ranges = vec![FoldRange { start: 0, end: 5 }];
+        ctx.do_plugin_cmd(PluginPid(1),
+                          PluginNotification::UpdateFoldRanges { start: 0, len: 8, ranges, rev });
+
+        // the range is shifted by the edit
+        ctx.do_edit(EditNotification::Insert { chars: "x".into() });
+        ctx.do_edit(EditNotification::Fold);
+        ctx.do_edit(EditNotification::MoveDown);
+        assert_eq!(harness.debug_render(), "xa\nb\nc\nd|\n");
+    }
+
+    #[test]
+    fn move_to_matching_bracket() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("fn a(b) {\n    c[0];\n}\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 8, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn a(b) {\n    c[0];\n|}\n");
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn a(b) |{\n    c[0];\n}\n");
+
+        // after a bracket, the cursor stays after the matching one
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 5, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn a(b)| {\n    c[0];\n}\n");
+
+        // no adjacent bracket
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 2, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracket);
+        assert_eq!(harness.debug_render(), "fn a(b) {\n  |  c[0];\n}\n");
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 8, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveToMatchingBracketAndModifySelection);
+        assert_eq!(harness.debug_render(), "fn a(b) [{\n    c[0];\n}|]\n");
+    }
+
+    #[test]
+    fn matched_brackets_after_spans() {
+        use crate::plugins::rpc::{PluginNotification, ScopeSpan};
+        use crate::plugins::PluginPid;
+
+        let harness = ContextHarness::new("(\")\")");
+        let mut ctx = harness.make_context();
+        ctx.render();
+        assert_eq!(harness.view.borrow().matched_brackets(), &[0, 2]);
+
+        // the bracket in the string no longer matches once it is known to be in one
+        let rev = ctx.editor.borrow().get_head_rev_token();
+        let scopes = vec![vec!["string.quoted.double".to_string()]];
+        ctx.do_plugin_cmd(PluginPid(1), PluginNotification::AddScopes { scopes });
+        let spans = vec![ScopeSpan { start: 1, end: 4, scope_id: 0 }];
+        ctx.do_plugin_cmd(PluginPid(1),
+                          PluginNotification::UpdateSpans { start: 0, len: 5, spans, rev });
+        ctx.render();
+        assert_eq!(harness.view.borrow().matched_brackets(), &[0, 4]);
+    }
+
+    #[test]
+    fn subword_movement() {
+        let harness = ContextHarness::new("fooBar_baz.qux");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::MoveSubwordRight);
+        assert_eq!(harness.debug_render(), "foo|Bar_baz.qux");
+        ctx.do_edit(EditNotification::MoveSubwordRight);
+        assert_eq!(harness.debug_render(), "fooBar|_baz.qux");
+        ctx.do_edit(EditNotification::DeleteSubwordForward);
+        assert_eq!(harness.debug_render(), "fooBar|.qux");
+        ctx.do_edit(EditNotification::DeleteSubwordBackward);
+        assert_eq!(harness.debug_render(), "foo|.qux");
+
+        // words are only separated by whitespace without separators
+        harness.view.borrow_mut().set_word_separators("");
+        ctx.do_edit(EditNotification::MoveWordRight);
+        assert_eq!(harness.debug_render(), "foo.qux|");
+    }
+
+    #[test]
+    fn expand_and_shrink_selection() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("a(b, cd) e\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 6, ty: PointSelect });
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "a(b, [cd|]) e\n");
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "a([b, cd|]) e\n");
+        ctx.do_edit(EditNotification::ExpandSelection);
+        assert_eq!(harness.debug_render(), "a[(b, cd)|] e\n");
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "a(b, [cd|]) e\n");
+
+        // a selection changed since the expansion is not shrunk
+        ctx.do_edit(EditNotification::MoveRightAndModifySelection);
+        ctx.do_edit(EditNotification::ShrinkSelection);
+        assert_eq!(harness.debug_render(), "a(b, [cd)|] e\n");
+    }
+
+    #[test]
+    fn block_selection() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("abcd\nab\nabcd\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: BlockSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 2, col: 3, ty: Drag });
+        assert_eq!(harness.debug_render(), "a[bc|]d\na[b|]\na[bc|]d\n");
+        ctx.do_edit(EditNotification::ExtendBlockSelectionLeft);
+        ctx.do_edit(EditNotification::ExtendBlockSelectionLeft);
+        ctx.do_edit(EditNotification::ExtendBlockSelectionLeft);
+        assert_eq!(harness.debug_render(), "[|a]bcd\n[|a]b\n[|a]bcd\n");
+        ctx.do_edit(EditNotification::ExtendBlockSelectionUp);
+        assert_eq!(harness.debug_render(), "[|a]bcd\n[|a]b\nabcd\n");
+
+        // a column of carets past the end of a short line
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 4, ty: BlockSelect });
+        ctx.do_edit(EditNotification::ExtendBlockSelectionDown);
+        assert_eq!(harness.debug_render(), "abcd\nab|\nabcd|\n");
+        ctx.do_edit(EditNotification::ExtendBlockSelectionUp);
+        ctx.do_edit(EditNotification::ExtendBlockSelectionUp);
+        ctx.do_edit(EditNotification::Insert { chars: "!".into() });
+        assert_eq!(harness.debug_render(), "abcd!|\nab!|\nabcd\n");
+
+        // other selections end the block
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: Drag });
+        assert_eq!(harness.debug_render(), "[abcd!\na|]b!\nabcd\n");
+    }
+
+    #[test]
+    fn block_selection_tabs() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("\tx\n    y\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: BlockSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 5, ty: Drag });
+        assert_eq!(harness.debug_render(), "\t[x|]\n    [y|]\n");
+    }
+
+    #[test]
+    fn reorder_lines() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("b10
+B2
+a
+b2
+a
+");
+        let mut ctx = harness.make_context();
+
+        // carets only: the whole buffer
+        ctx.do_edit(EditNotification::SortLines);
+        assert_eq!(harness.debug_render(), "[B2\na\na\nb10\nb2\n|]");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "b10\nB2\na\nb2\na\n|");
+
+        ctx.do_edit(EditNotification::SortLinesCaseInsensitive);
+        assert_eq!(harness.debug_render(), "[a\na\nb10\nB2\nb2\n|]");
+        ctx.do_edit(EditNotification::SortLinesNatural);
+        assert_eq!(harness.debug_render(), "[B2\na\na\nb2\nb10\n|]");
+        ctx.do_edit(EditNotification::UniqueLines);
+        assert_eq!(harness.debug_render(), "[B2\na\nb2\nb10\n|]");
+        ctx.do_edit(EditNotification::ReverseLines);
+        assert_eq!(harness.debug_render(), "[b10\nb2\na\nB2\n|]");
+
+        // each edit is its own undo group
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "B2\na\nb2\nb10\n|");
+
+        // only the lines touched by the selection
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 3, col: 0, ty: Drag });
+        ctx.do_edit(EditNotification::ReverseLines);
+        assert_eq!(harness.debug_render(), "B2\n[b2\na\n|]b10\n");
+        ctx.do_edit(EditNotification::ShuffleLines);
+        let render = harness.debug_render();
+        assert!(render == "B2\n[b2\na\n|]b10\n" || render == "B2\n[a\nb2\n|]b10\n");
+    }
+
+    #[test]
+    fn convert_case() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("let fooBar = parse_HTTPServer;\n  étéChaud xml2Json \n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 5, ty: WordSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 16, ty: MultiWordSelect });
+        ctx.do_edit(EditNotification::ToSnakeCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let [foo_bar|] = [parse_http_server|];\n  étéChaud xml2Json \n"
+        );
+        ctx.do_edit(EditNotification::ToScreamingSnakeCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let [FOO_BAR|] = [PARSE_HTTP_SERVER|];\n  étéChaud xml2Json \n"
+        );
+        ctx.do_edit(EditNotification::ToCamelCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let [fooBar|] = [parseHttpServer|];\n  étéChaud xml2Json \n"
+        );
+        ctx.do_edit(EditNotification::ToKebabCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let [foo-bar|] = [parse-http-server|];\n  étéChaud xml2Json \n"
+        );
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "let fooBar| = parseHttpServer|;\n  étéChaud xml2Json \n");
+
+        // the text between identifiers is kept
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 0, ty: LineSelect });
+        ctx.do_edit(EditNotification::ToPascalCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let fooBar = parseHttpServer;\n[  ÉtéChaud Xml2Json \n|]"
+        );
+        ctx.do_edit(EditNotification::ToTitleCase);
+        assert_eq!(
+            harness.debug_render(),
+            "let fooBar = parseHttpServer;\n[  Été Chaud Xml2 Json \n|]"
+        );
+    }
+
+    #[test]
+    fn reflow_paragraph() {
+        use crate::config::ConfigDomain;
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new(
+            "    // Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+             incididunt ut labore\n    // et dolore.\n    //\n    // magna aliqua.\n",
+        );
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 8, ty: PointSelect });
+        ctx.do_edit(EditNotification::ReflowParagraph);
+        assert_eq!(
+            harness.debug_render(),
+            "    // Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod\n    \
+             // tempor incididunt ut labore et dolore.|\n    //\n    // magna aliqua.\n"
+        );
+
+        let harness = ContextHarness::new(">  quoted\n> text\n\nplain\ntext\n");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::SelectAll);
+        ctx.do_edit(EditNotification::ReflowParagraph);
+        assert_eq!(harness.debug_render(), "[>  quoted text\n\nplain text\n|]");
+
+        // Markdown headings are left alone
+        let mut harness = ContextHarness::new("# Title\ntext\nmore\n## Part\n");
+        let changes = json!({"reflow_markers": [">"], "reflow_skipped_prefixes": ["#"]});
+        let domain = ConfigDomain::UserOverride(BufferId(2));
+        harness.config_manager.set_user_config(domain, changes.as_object().unwrap().clone()).unwrap();
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::SelectAll);
+        ctx.do_edit(EditNotification::ReflowParagraph);
+        assert_eq!(harness.debug_render(), "[# Title\ntext more\n## Part\n|]");
+    }
+
+    #[test]
+    fn clean_up_whitespace_before_save() {
+        use crate::config::ConfigDomain;
+        use crate::rpc::GestureType::*;
+
+        let mut harness = ContextHarness::new("a  \n  b\t\nc\n \n\n");
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 4, ty: PointSelect });
+        // nothing is cleaned up by default
+        ctx.before_save();
+        assert_eq!(harness.debug_render(), "a  \n  b\t|\nc\n \n\n");
+
+        let changes = json!({"trim_trailing_whitespace": true, "ensure_single_final_newline": true});
+        let domain = ConfigDomain::UserOverride(BufferId(2));
+        harness.config_manager.set_user_config(domain, changes.as_object().unwrap().clone()).unwrap();
+        let mut ctx = harness.make_context();
+        ctx.before_save();
+        assert_eq!(harness.debug_render(), "a\n  b|\nc\n");
+        assert_eq!(String::from(ctx.text_for_save()), "a\n  b\nc\n");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "a  \n  b\t|\nc\n \n\n");
+    }
+
+    #[test]
+    fn convert_indentation() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("\ta\n  \tb\n      c\nd\n");
+        let mut ctx = harness.make_context();
+        let text = || String::from(harness.editor.borrow().get_buffer());
+
+        ctx.do_edit(EditNotification::ConvertIndentationToSpaces);
+        assert_eq!(text(), "    a\n    b\n      c\nd\n");
+        ctx.do_edit(EditNotification::ConvertIndentationToTabs);
+        assert_eq!(text(), "\ta\n\tb\n\t  c\nd\n");
+
+        // only the selected lines
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 2, col: 2, ty: Drag });
+        ctx.do_edit(EditNotification::ConvertIndentationToSpaces);
+        assert_eq!(text(), "\ta\n    b\n      c\nd\n");
+    }
+
+    #[test]
+    fn convert_line_endings() {
+        use crate::line_ending::LineEnding;
+
+        let harness = ContextHarness::new("a\r\nb\rc\n\r");
+        let mut ctx = harness.make_context();
+        let text = || String::from(harness.editor.borrow().get_buffer());
+
+        ctx.do_edit(EditNotification::ConvertLineEndings { line_ending: LineEnding::Lf });
+        assert_eq!(text(), "a\nb\nc\n\n");
+        ctx.do_edit(EditNotification::ConvertLineEndings { line_ending: LineEnding::CrLf });
+        assert_eq!(text(), "a\r\nb\r\nc\r\n\r\n");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(text(), "a\nb\nc\n\n");
+    }
+
+    #[test]
+    fn read_only() {
+        use crate::config::ConfigDomain;
+
+        let mut harness = ContextHarness::new("ab");
+        let domain = ConfigDomain::UserOverride(BufferId(2));
+        let changes = json!({"read_only": true});
+        harness.config_manager.set_user_config(domain.clone(), changes.as_object().unwrap().clone()).unwrap();
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "c".into() });
+        ctx.do_edit(EditNotification::DeleteBackward);
+        assert_eq!(String::from(harness.editor.borrow().get_buffer()), "ab");
+
+        // bookmarks and copying still work
+        ctx.do_edit(EditNotification::ToggleBookmark);
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::NextBookmark);
+        assert_eq!(harness.debug_render(), "|ab");
+        ctx.do_edit(EditNotification::Key { key: "y".into() });
+        ctx.do_edit(EditNotification::Key { key: "y".into() });
+        assert_eq!(harness.kill_ring.borrow().to_json(), json!([["ab"]]));
+        assert_eq!(String::from(harness.editor.borrow().get_buffer()), "ab");
+
+        let changes = json!({"read_only": false});
+        harness.config_manager.set_user_config(domain, changes.as_object().unwrap().clone()).unwrap();
+        let mut ctx = harness.make_context();
+        ctx.do_edit(EditNotification::Insert { chars: "c".into() });
+        assert_eq!(String::from(harness.editor.borrow().get_buffer()), "cab");
+    }
+
+    #[test]
+    fn move_lines() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("a\nb\nc\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveLinesUp);
+        assert_eq!(harness.debug_render(), "b|\na\nc\n");
+        ctx.do_edit(EditNotification::MoveLinesUp);
+        assert_eq!(harness.debug_render(), "b|\na\nc\n");
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\nc\nb|\n");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "b|\na\nc\n");
+
+        // selected lines move together, and keep their selection
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 2, col: 0, ty: Drag });
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "c\n[b\na\n|]");
+        ctx.do_edit(EditNotification::MoveLinesUp);
+        assert_eq!(harness.debug_render(), "[b\na\n|]c\n");
+
+        // several carets
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 2, col: 0, ty: ToggleSel });
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\nb|\n\n|c");
+    }
+
+    #[test]
+    fn move_last_line() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("a\r\nb");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::MoveLinesUp);
+        assert_eq!(harness.debug_render(), "b|\r\na");
+        ctx.do_edit(EditNotification::MoveLinesDown);
+        assert_eq!(harness.debug_render(), "a\r\nb|");
+    }
+
+    #[test]
+    fn join_lines() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("a\n   b\n\nc \nd\ne");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::JoinLines);
+        assert_eq!(harness.debug_render(), "|a b\n\nc \nd\ne");
+        ctx.do_edit(EditNotification::JoinLines);
+        assert_eq!(harness.debug_render(), "|a b\nc \nd\ne");
+
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 3, col: 1, ty: Drag });
+        ctx.do_edit(EditNotification::JoinLines);
+        assert_eq!(harness.debug_render(), "a b\n[c d e|]");
+
+        // nothing to join on the last line
+        ctx.do_edit(EditNotification::JoinLines);
+        assert_eq!(harness.debug_render(), "a b\n[c d e|]");
+    }
+
+    #[test]
+    fn undo_tree() {
+        let harness = ContextHarness::new("");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Insert { chars: "a".into() });
+        ctx.do_edit(EditNotification::Undo);
+        ctx.do_edit(EditNotification::Insert { chars: "b".into() });
+        assert_eq!(harness.debug_render(), "b|");
+
+        let tree = ctx.do_edit_sync(EditRequest::UndoTree).unwrap();
+        assert_eq!(tree["current"], json!(2));
+        let nodes = tree["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[1]["parent"], json!(0));
+        assert_eq!(nodes[1]["summary"], json!("insert +1 -0"));
+        assert_eq!(nodes[2]["parent"], json!(0));
+
+        // the undone branch is still there
+        ctx.do_edit(EditNotification::JumpToUndoNode { id: 1 });
+        assert_eq!(harness.debug_render(), "a|");
+        ctx.do_edit(EditNotification::JumpToUndoNode { id: 0 });
+        assert_eq!(harness.debug_render(), "|");
+        // redo follows the most recent branch
+        ctx.do_edit(EditNotification::Redo);
+        assert_eq!(harness.debug_render(), "b|");
+        ctx.do_edit(EditNotification::JumpToUndoNode { id: 1 });
+        assert_eq!(harness.debug_render(), "a|");
+        ctx.do_edit(EditNotification::Undo);
+        assert_eq!(harness.debug_render(), "|");
+    }
+
+    #[test]
+    fn bookmarks() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("a\nb\nc\nd\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::ToggleBookmark);
+        ctx.do_edit(EditNotification::Gesture { line: 3, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::SetBookmark { name: Some("d".into()) });
+        let bookmarks = ctx.do_edit_sync(EditRequest::Bookmarks).unwrap();
+        assert_eq!(bookmarks, json!([{"line": 1, "name": null}, {"line": 3, "name": "d"}]));
+
+        ctx.do_edit(EditNotification::NextBookmark);
+        assert_eq!(harness.debug_render(), "a\n|b\nc\nd\n");
+        ctx.do_edit(EditNotification::PreviousBookmark);
+        assert_eq!(harness.debug_render(), "a\nb\nc\n|d\n");
+
+        // bookmarks stay on their lines
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 0, ty: PointSelect });
+        ctx.do_edit(EditNotification::InsertNewline);
+        ctx.do_edit(EditNotification::GotoBookmark { name: "d".into() });
+        assert_eq!(harness.debug_render(), "\na\nb\nc\n|d\n");
+        ctx.do_edit(EditNotification::ToggleBookmark);
+        ctx.do_edit(EditNotification::NextBookmark);
+        assert_eq!(harness.debug_render(), "\na\n|b\nc\nd\n");
+
+        ctx.do_edit(EditNotification::ClearAllBookmarks);
+        let bookmarks = ctx.do_edit_sync(EditRequest::Bookmarks).unwrap();
+        assert_eq!(bookmarks, json!([]));
+    }
+
+    #[test]
+    fn jump_list() {
+        use crate::rpc::SelectionModifier;
+
+        let harness = ContextHarness::new("a\nb\nfoo\nc\nfoo\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::GotoLine { line: 1 });
+        ctx.do_edit(EditNotification::Find {
+            chars: "foo".into(),
+            case_sensitive: false,
+            regex: false,
+            whole_words: false,
+        });
+        ctx.do_edit(EditNotification::FindNext {
+            wrap_around: false,
+            allow_same: false,
+            modify_selection: SelectionModifier::Set,
+        });
+        assert_eq!(harness.debug_render(), "a\nb\n[foo|]\nc\nfoo\n");
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+
+        ctx.do_edit(EditNotification::JumpBack);
+        assert_eq!(harness.debug_render(), "a\nb\nfoo|\nc\nfoo\n");
+        ctx.do_edit(EditNotification::JumpBack);
+        assert_eq!(harness.debug_render(), "a\n|b\nfoo\nc\nfoo\n");
+
+        // positions are kept through edits
+        ctx.do_edit(EditNotification::Insert { chars: "x\n".into() });
+        ctx.do_edit(EditNotification::JumpForward);
+        assert_eq!(harness.debug_render(), "a\nx\nb\nfoo|\nc\nfoo\n");
+        ctx.do_edit(EditNotification::JumpForward);
+        assert_eq!(harness.debug_render(), "a\nx\nb\nfoo\nc\nfoo\n|");
+        ctx.do_edit(EditNotification::JumpForward);
+        assert_eq!(harness.debug_render(), "a\nx\nb\nfoo\nc\nfoo\n|");
+    }
+
+    #[test]
+    fn kill_ring() {
+        use crate::rpc::GestureType::*;
+
+        let harness = ContextHarness::new("ab\ncd\nef\n");
+        let mut ctx = harness.make_context();
+
+        // consecutive kills are appended
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        assert_eq!(harness.debug_render(), "|cd\nef\n");
+        ctx.do_edit(EditNotification::MoveDown);
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        assert_eq!(harness.debug_render(), "cd\n|\n");
+        assert_eq!(harness.kill_ring.borrow().to_json(), json!([["ef"], ["ab\n"]]));
+        // a kill that deletes nothing leaves the ring alone
+        ctx.do_edit(EditNotification::MoveToEndOfDocument);
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        ctx.do_edit(EditNotification::MoveUp);
+        assert_eq!(harness.debug_render(), "cd\n|\n");
+        assert_eq!(harness.kill_ring.borrow().to_json(), json!([["ef"], ["ab\n"]]));
+
+        ctx.do_edit(EditNotification::Yank);
+        assert_eq!(harness.debug_render(), "cd\nef|\n");
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "cd\nab\n|\n");
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "cd\nef|\n");
+        // yank_pop only follows a yank
+        ctx.do_edit(EditNotification::MoveLeft);
+        ctx.do_edit(EditNotification::YankPop);
+        assert_eq!(harness.debug_render(), "cd\ne|f\n");
+
+        // a kill with several carets is yanked back one piece per caret
+        ctx.do_edit(EditNotification::Gesture { line: 0, col: 1, ty: PointSelect });
+        ctx.do_edit(EditNotification::Gesture { line: 1, col: 1, ty: ToggleSel });
+        ctx.do_edit(EditNotification::DeleteToEndOfParagraph);
+        assert_eq!(harness.debug_render(), "c|\ne|\n");
+        ctx.do_edit(EditNotification::MoveToBeginningOfDocument);
+        ctx.do_edit(EditNotification::AddSelectionBelow);
+        ctx.do_edit(EditNotification::Yank);
+        assert_eq!(harness.debug_render(), "d|c\nf|e\n");
+    }
+
+    #[test]
+    fn registers() {
+        let harness = ContextHarness::new("ab\ncd\n");
+        let mut ctx = harness.make_context();
+
+        ctx.do_edit(EditNotification::MoveToEndOfParagraphAndModifySelection);
+        let copy = EditRequest::Copy { register: Some("a".into()) };
+        assert_eq!(ctx.do_edit_sync(copy), Ok(json!("ab")));
+        ctx.do_edit(EditNotification::MoveDown);
+        ctx.do_edit(EditNotification::MoveToBeginningOfParagraph);
+        ctx.do_edit(EditNotification::MoveToEndOfParagraphAndModifySelection);
+        let cut = EditRequest::Cut { register: Some("A".into()) };
+        assert_eq!(ctx.do_edit_sync(cut), Ok(json!("cd")));
+        assert_eq!(harness.debug_render(), "ab\n|\n");
+        let cut = EditRequest::Cut { register: Some("ab".into()) };
+        assert!(ctx.do_edit_sync(cut).is_err());
+
+        ctx.do_edit(EditNotification::Paste { chars: String::new(), register: Some("a".into()) });
+        assert_eq!(harness.debug_render(), "ab\nabcd|\n");
+
+        // typing and searching fill the special registers
+        ctx.do_edit(EditNotification::Insert { chars: "x".into() });
+        ctx.do_edit(EditNotification::Insert { chars: "y".into() });
+        ctx.do_edit(EditNotification::Find {
+            chars: "b".into(),
+            case_sensitive: false,
+            regex: false,
+            whole_words: false,
+        });
+        assert_eq!(
+            harness.registers.borrow().to_json(),
+            json!({ "a": "abcd", ".": "xy", "/": "b" })
+        );
+        ctx.do_edit(EditNotification::Paste { chars: String::new(), register: Some(".".into()) });
+        assert_eq!(harness.debug_render(), "ab\nabcdxyxy|\n");
+    }
+
+    #[test]
+    fn modal_editing() {
+        use crate::modal::Mode;
+
+        let harness = ContextHarness::new("one two\nthree\nfour\n");
+        let mut ctx = harness.make_context();
+        let mut keys = |keys: &[&str]| {
+            keys.iter().for_each(|&key| ctx.do_edit(EditNotification::Key { key: key.into() }))
+        };
+
+        keys(&["d"]);
+        assert_eq!(harness.view.borrow().mode(), Mode::OperatorPending);
+        keys(&["d"]);
+        assert_eq!(harness.debug_render(), "|three\nfour\n");
+        // whole lines are put below the caret's line
+        keys(&["p"]);
+        assert_eq!(harness.debug_render(), "three\n|one two\nfour\n");
+        // a command is undone at once
+        keys(&["u"]);
+        assert_eq!(harness.debug_render(), "three|\nfour\n");
+        keys(&["2", "k", "u"]);
+        assert_eq!(harness.debug_render(), "one two\n|three\nfour\n");
+        keys(&["<C-r>"]);
+        assert_eq!(harness.debug_render(), "|three\nfour\n");
+
+        keys(&["g", "g", "A", "!", "?"]);
+        assert_eq!(harness.view.borrow().mode(), Mode::Insert);
+        keys(&["<Esc>", "j", "."]);
+        assert_eq!(harness.view.borrow().mode(), Mode::Normal);
+        assert_eq!(harness.debug_render(), "three!?\nfour!?|\n");
+
+        keys(&["g", "g", "2", "x", "c", "w", "T", "<Esc>"]);
+        assert_eq!(harness.debug_render(), "T|!?\nfour!?\n");
+        keys(&["V", "j", "d"]);
+        assert_eq!(harness.debug_render(), "|");
+
+        // `w` moves to the start of the next word, and `dw` deletes up to it
+        keys(&["i", "a", "b", " ", "c", "d", "<Esc>", "g", "g", "d", "w"]);
+        assert_eq!(harness.debug_render(), "|cd");
+        keys(&["u", "g", "g", "w"]);
+        assert_eq!(harness.debug_render(), "ab |cd");
+
+        // line motions after an operator apply to whole lines
+        keys(&["i", "<CR>", "e", "f", "<CR>", "<Esc>", "k", "d", "G"]);
+        assert_eq!(harness.debug_render(), "ab \n|");
+        keys(&["u", "G", "d", "g", "g"]);
+        assert_eq!(harness.debug_render(), "|");
+
+        // including below the last line, which has no newline
+        keys(&["i", "a", "<CR>", "b", "<Esc>", "k", "y", "y", "j", "p"]);
+        assert_eq!(harness.debug_render(), "a\nb\n|a");
+        keys(&["2", "k", "3", "p"]);
+        assert_eq!(harness.debug_render(), "a\n|a\na\na\nb\na");
+    }
 }
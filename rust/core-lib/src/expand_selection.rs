@@ -0,0 +1,166 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expansion of selections to the enclosing syntactic units.
+
+use syntect::parsing::Scope;
+use xi_rope::{Interval, Rope};
+
+use crate::brackets::BracketMatcher;
+use crate::folds::indent_of_line;
+use crate::layers::Layers;
+use crate::selection::SelRegion;
+use crate::word_boundaries::WordCursor;
+
+/// Scopes whose extent is a unit a selection expands to.
+const EXPANSION_SCOPES: &[&str] = &["string", "comment"];
+
+/// Returns the smallest syntactic unit strictly containing `region`: the
+/// word, string or comment, contents of a bracket pair, bracket pair, line
+/// without or with its indentation, indented block, or the whole document.
+/// Words are separated by whitespace and by the characters in
+/// `word_separators`.
+pub(crate) fn expand_region(
+    text: &Rope,
+    region: &SelRegion,
+    word_separators: &str,
+    brackets: &BracketMatcher,
+    layers: &Layers,
+) -> SelRegion {
+    let (start, end) = (region.min(), region.max());
+    let mut units = vec![word(text, start, word_separators), Interval::new(0, text.len())];
+    units.extend(scope(layers, start));
+    if let Some((open, close)) = brackets.enclosing(text, start, end) {
+        let open_end = text.next_codepoint_offset(open).unwrap_or(open);
+        let close_end = text.next_codepoint_offset(close).unwrap_or(close);
+        units.push(Interval::new(open_end, close));
+        units.push(Interval::new(open, close_end));
+    }
+    let (first_line, last_line) = (text.line_of_offset(start), text.line_of_offset(end));
+    let (lines_start, lines_end) = (text.offset_of_line(first_line), line_end(text, last_line));
+    let indent = indent_of_line(text, first_line).unwrap_or(0);
+    units.push(Interval::new(lines_start + indent, lines_end));
+    units.push(Interval::new(lines_start, lines_end));
+    units.extend(block(text, first_line, last_line));
+
+    units
+        .into_iter()
+        .filter(|unit| unit.start <= start && end <= unit.end && unit.size() > end - start)
+        .min_by_key(|unit| unit.size())
+        .map(|unit| SelRegion::new(unit.start, unit.end))
+        .unwrap_or(*region)
+}
+
+fn word(text: &Rope, offset: usize, word_separators: &str) -> Interval {
+    let (start, end) = WordCursor::with_separators(text, offset, word_separators).select_word();
+    Interval::new(start, end)
+}
+
+fn scope(layers: &Layers, offset: usize) -> Option<Interval> {
+    if !layers.has_scopes() {
+        return None;
+    }
+    let scopes = EXPANSION_SCOPES.iter().filter_map(|s| Scope::new(s).ok()).collect::<Vec<_>>();
+    layers.scope_range_at(offset, &scopes)
+}
+
+/// Returns the offset of the end of a line, before its newline.
+fn line_end(text: &Rope, line: usize) -> usize {
+    let next_line = text.offset_of_line(line + 1);
+    if next_line > text.offset_of_line(line) && text.byte_at(next_line - 1) == b'\n' {
+        next_line - 1
+    } else {
+        next_line
+    }
+}
+
+/// Returns the lines around the given ones that are indented at least as much
+/// as the least indented of them, ignoring blank lines.
+fn block(text: &Rope, first_line: usize, last_line: usize) -> Option<Interval> {
+    let indent = (first_line..=last_line).filter_map(|line| indent_of_line(text, line)).min()?;
+    let in_block = |line: usize| indent_of_line(text, line).unwrap_or(indent) >= indent;
+    let n_lines = text.line_of_offset(text.len()) + 1;
+    let mut first = first_line;
+    while first > 0 && in_block(first - 1) {
+        first -= 1;
+    }
+    let mut last = last_line;
+    while last + 1 < n_lines && in_block(last + 1) {
+        last += 1;
+    }
+    // blank lines around the block are not part of it
+    while first < first_line && indent_of_line(text, first).is_none() {
+        first += 1;
+    }
+    while last > last_line && indent_of_line(text, last).is_none() {
+        last -= 1;
+    }
+    Some(Interval::new(text.offset_of_line(first), line_end(text, last)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word_boundaries::DEFAULT_WORD_SEPARATORS;
+
+    fn expand(text: &Rope, start: usize, end: usize) -> (usize, usize) {
+        expand_with_separators(text, start, end, DEFAULT_WORD_SEPARATORS)
+    }
+
+    fn expand_with_separators(
+        text: &Rope,
+        start: usize,
+        end: usize,
+        word_separators: &str,
+    ) -> (usize, usize) {
+        let region = expand_region(
+            text,
+            &SelRegion::new(start, end),
+            word_separators,
+            &BracketMatcher::default(),
+            &Layers::default(),
+        );
+        (region.min(), region.max())
+    }
+
+    #[test]
+    fn expand_to_units() {
+        let text = Rope::from("fn a() {\n    b(cd, e);\n    f;\n}\n\ng\n");
+        let steps = [
+            (16, 16), // caret
+            (15, 17), // word
+            (15, 20), // bracket contents
+            (14, 21), // bracket pair
+            (13, 22), // line without indentation
+            (9, 22),  // line
+            (9, 29),  // block
+            (8, 30),  // bracket contents
+            (7, 31),  // bracket pair
+            (0, 31),  // lines
+            (0, 34),  // block
+            (0, 35),  // document
+            (0, 35),
+        ];
+        for pair in steps.windows(2) {
+            assert_eq!(expand(&text, pair[0].0, pair[0].1), pair[1]);
+        }
+    }
+
+    #[test]
+    fn expand_to_configured_words() {
+        let text = Rope::from("a foo-bar b");
+        assert_eq!(expand(&text, 3, 3), (2, 5));
+        assert_eq!(expand_with_separators(&text, 3, 3, ""), (2, 9));
+    }
+}
@@ -14,7 +14,9 @@
 
 //! Interactions with the file system.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ffi::OsString;
 use std::fmt;
 use std::fs::{self, File};
@@ -23,10 +25,18 @@ use std::path::{Path, PathBuf};
 use std::str;
 use std::time::SystemTime;
 
-use xi_rope::Rope;
+use encoding_rs::{
+    Decoder, DecoderResult, Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252,
+};
+use xi_rope::engine::Engine;
+use xi_rope::tree::TreeBuilder;
+use xi_rope::{Rope, RopeInfo};
 use xi_rpc::RemoteError;
 
+use crate::editor::{Editor, UndoHistory};
+use crate::hex_dump;
 use crate::tabs::BufferId;
+use crate::undo_tree::UndoTree;
 
 #[cfg(feature = "notify")]
 use crate::tabs::OPEN_FILE_EVENT_TOKEN;
@@ -36,6 +46,23 @@ use crate::watcher::FileWatcher;
 use std::{fs::Permissions, os::unix::fs::PermissionsExt};
 
 const UTF8_BOM: &str = "\u{feff}";
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// The number of bytes at the start of a file checked for binary data.
+const BINARY_SAMPLE_SIZE: usize = 8192;
+
+/// The number of bytes of a large file read in each step of loading it.
+const LOAD_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The version of the undo file format. Undo files of other versions are ignored.
+const UNDO_FILE_VERSION: u32 = 2;
+/// Undo histories taking more than this many bytes are neither saved nor loaded.
+const MAX_UNDO_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The version of the recovery file format. Recovery files of other versions
+/// are ignored.
+const RECOVERY_FILE_VERSION: u32 = 1;
 
 /// Tracks all state related to open files.
 pub struct FileManager {
@@ -44,6 +71,10 @@ pub struct FileManager {
     /// A monitor of filesystem events, for things like reloading changed files.
     #[cfg(feature = "notify")]
     watcher: FileWatcher,
+    /// The directory where undo histories are persisted, if any.
+    undo_dir: Option<PathBuf>,
+    /// The directory where unsaved changes are kept for recovery, if any.
+    recovery_dir: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -54,29 +85,100 @@ pub struct FileInfo {
     pub has_changed: bool,
     #[cfg(target_family = "unix")]
     pub permissions: Option<u32>,
+    /// Whether the file is large enough that expensive features, such as
+    /// plugins, are disabled for it.
+    pub large_file: bool,
+}
+
+/// Loads a large file a chunk at a time, so that the core can keep handling
+/// other events while it is read.
+pub struct FileLoader {
+    file: File,
+    path: PathBuf,
+    /// The encoding of the file, guessed from its first chunk.
+    encoding: Option<CharacterEncoding>,
+    /// The decoder for the encoding, or `None` for Latin-1 and hex dumps,
+    /// which aren't supported by `encoding_rs`.
+    decoder: Option<Decoder>,
+    builder: TreeBuilder<RopeInfo>,
+    loaded: u64,
+    total: u64,
+    done: bool,
 }
 
+/// The contents of an undo file: the undo history of a buffer, along with the
+/// path and a hash of the text it was saved with.
+#[derive(Serialize, Deserialize)]
+struct UndoFile<H> {
+    version: u32,
+    path: PathBuf,
+    content_hash: u64,
+    history: H,
+}
+
+/// The contents of a recovery file: the unsaved text of a buffer, along with
+/// the path of its file.
+#[derive(Serialize, Deserialize)]
+struct RecoveryFile<T> {
+    version: u32,
+    path: PathBuf,
+    text: T,
+}
+
+#[derive(Debug)]
 pub enum FileError {
     Io(io::Error, PathBuf),
     UnknownEncoding(PathBuf),
     HasChanged(PathBuf),
 }
 
-#[derive(Debug, Clone, Copy)]
+/// The encodings files can be read and written in. UTF-16 files are always
+/// written with a byte order mark. Binary files are edited as hex dumps,
+/// which are treated as one more encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CharacterEncoding {
+    #[serde(rename = "utf-8")]
     Utf8,
+    #[serde(rename = "utf-8-bom")]
     Utf8WithBom,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "windows-1252")]
+    Windows1252,
+    #[serde(rename = "shift_jis")]
+    ShiftJis,
+    #[serde(rename = "hex")]
+    Hex,
 }
 
 impl FileManager {
     #[cfg(feature = "notify")]
-    pub fn new(watcher: FileWatcher) -> Self {
-        FileManager { open_files: HashMap::new(), file_info: HashMap::new(), watcher }
+    pub fn new(
+        watcher: FileWatcher,
+        undo_dir: Option<PathBuf>,
+        recovery_dir: Option<PathBuf>,
+    ) -> Self {
+        FileManager {
+            open_files: HashMap::new(),
+            file_info: HashMap::new(),
+            watcher,
+            undo_dir,
+            recovery_dir,
+        }
     }
 
     #[cfg(not(feature = "notify"))]
-    pub fn new() -> Self {
-        FileManager { open_files: HashMap::new(), file_info: HashMap::new() }
+    pub fn new(undo_dir: Option<PathBuf>, recovery_dir: Option<PathBuf>) -> Self {
+        FileManager {
+            open_files: HashMap::new(),
+            file_info: HashMap::new(),
+            undo_dir,
+            recovery_dir,
+        }
     }
 
     #[cfg(feature = "notify")]
@@ -110,8 +212,7 @@ impl FileManager {
             return Ok(Rope::from(""));
         }
 
-        let (rope, info) = try_load_file(path)?;
-
+        let (rope, info) = try_load_file(path, None)?;
         self.open_files.insert(path.to_owned(), id);
         if self.file_info.insert(id, info).is_none() {
             #[cfg(feature = "notify")]
@@ -120,6 +221,150 @@ impl FileManager {
         Ok(rope)
     }
 
+    /// Reads the file of the given buffer again, decoding it with `encoding`
+    /// rather than the encoding it was detected to be in. Returns `None` if
+    /// the buffer has no file.
+    pub fn reopen(
+        &mut self,
+        id: BufferId,
+        encoding: CharacterEncoding,
+    ) -> Result<Option<Rope>, FileError> {
+        let path = match self.file_info.get(&id) {
+            Some(info) => info.path.clone(),
+            None => return Ok(None),
+        };
+        let (rope, mut info) = try_load_file(&path, Some(encoding))?;
+        info.large_file = self.file_info[&id].large_file;
+        self.file_info.insert(id, info);
+        Ok(Some(rope))
+    }
+
+    /// Starts loading a large file, which is read by calling `load_chunk`
+    /// on the returned loader until it is done, and then `finish_loading`.
+    pub fn open_large(&mut self, path: &Path, id: BufferId) -> Result<FileLoader, FileError> {
+        let loader = FileLoader::new(path)?;
+        let info = FileInfo {
+            encoding: CharacterEncoding::Utf8,
+            path: path.to_owned(),
+            mod_time: get_mod_time(path),
+            has_changed: false,
+            #[cfg(target_family = "unix")]
+            permissions: get_permissions(path),
+            large_file: true,
+        };
+        self.open_files.insert(path.to_owned(), id);
+        if self.file_info.insert(id, info).is_none() {
+            #[cfg(feature = "notify")]
+            self.watcher.watch(path, false, OPEN_FILE_EVENT_TOKEN);
+        }
+        Ok(loader)
+    }
+
+    /// Returns the text read by a loader which is done, recording the
+    /// encoding it was decoded with.
+    pub fn finish_loading(&mut self, id: BufferId, loader: FileLoader) -> Rope {
+        if let Some(info) = self.file_info.get_mut(&id) {
+            info.encoding = loader.encoding.unwrap_or(CharacterEncoding::Utf8);
+        }
+        loader.builder.build()
+    }
+
+    /// Enables the features disabled for a large file. Returns `false` if
+    /// they were not disabled.
+    pub fn exit_large_file_mode(&mut self, id: BufferId) -> bool {
+        match self.file_info.get_mut(&id) {
+            Some(info) if info.large_file => {
+                info.large_file = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the undo history saved along with the file at `path`, provided
+    /// the file still has the contents it was saved with.
+    pub(crate) fn open_undo_history(
+        &self,
+        path: &Path,
+        text: &Rope,
+    ) -> Option<UndoHistory<Engine, UndoTree>> {
+        let undo_path = self.undo_path(path)?;
+        if !undo_path.exists() {
+            return None;
+        }
+        match try_load_undo_file(&undo_path, path, text) {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("Couldn't load undo history from {:?}: {}", undo_path, e);
+                None
+            }
+        }
+    }
+
+    /// Persists the undo history of `editor`, whose buffer was saved to `path`
+    /// as `text`.
+    pub(crate) fn save_undo_history(&self, path: &Path, text: &Rope, editor: &Editor) {
+        let undo_path = match self.undo_path(path) {
+            Some(undo_path) => undo_path,
+            None => return,
+        };
+        if let Err(e) = try_save_undo_file(&undo_path, path, text, editor.undo_history()) {
+            warn!("Couldn't save undo history to {:?}: {}", undo_path, e);
+        }
+    }
+
+    /// Returns the path of the undo file for the file at `path`.
+    fn undo_path(&self, path: &Path) -> Option<PathBuf> {
+        self.undo_dir.as_ref().map(|dir| state_path(dir, path))
+    }
+
+    /// Returns the unsaved changes to the file at `path` kept for recovery,
+    /// provided they were saved after the file and differ from its `text`.
+    pub(crate) fn open_recovery_file(&self, path: &Path, text: &Rope) -> Option<Rope> {
+        let recovery_path = self.recovery_path(path)?;
+        let recovery_time = get_mod_time(&recovery_path)?;
+        if get_mod_time(path).is_some_and(|mod_time| mod_time > recovery_time) {
+            return None;
+        }
+        match try_load_recovery_file(&recovery_path, path) {
+            Ok(Some(recovered)) if fnv_hash(recovered.bytes()) != content_hash(text) => {
+                Some(Rope::from(recovered))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Couldn't load recovery file {:?}: {}", recovery_path, e);
+                None
+            }
+        }
+    }
+
+    /// Keeps `text`, the unsaved contents of the file at `path`, for recovery.
+    pub(crate) fn save_recovery_file(&self, path: &Path, text: &Rope) {
+        let recovery_path = match self.recovery_path(path) {
+            Some(recovery_path) => recovery_path,
+            None => return,
+        };
+        if let Err(e) = try_save_recovery_file(&recovery_path, path, text) {
+            warn!("Couldn't save recovery file {:?}: {}", recovery_path, e);
+        }
+    }
+
+    /// Removes the unsaved changes to the file at `path` kept for recovery.
+    pub(crate) fn remove_recovery_file(&self, path: &Path) {
+        let recovery_path = match self.recovery_path(path) {
+            Some(recovery_path) if recovery_path.exists() => recovery_path,
+            _ => return,
+        };
+        if let Err(e) = fs::remove_file(&recovery_path) {
+            warn!("Couldn't remove recovery file {:?}: {}", recovery_path, e);
+        }
+    }
+
+    /// Returns the path of the recovery file for the file at `path`.
+    fn recovery_path(&self, path: &Path) -> Option<PathBuf> {
+        self.recovery_dir.as_ref().map(|dir| state_path(dir, path))
+    }
+
     pub fn close(&mut self, id: BufferId) {
         if let Some(info) = self.file_info.remove(&id) {
             self.open_files.remove(&info.path);
@@ -128,25 +373,43 @@ impl FileManager {
         }
     }
 
-    pub fn save(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
+    /// Saves the buffer to `path`, in `encoding` if given, or else in the
+    /// encoding the file was opened or last saved with.
+    pub fn save(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: Option<CharacterEncoding>,
+    ) -> Result<(), FileError> {
+        let encoding = encoding
+            .or_else(|| self.get_info(id).map(|info| info.encoding))
+            .unwrap_or(CharacterEncoding::Utf8);
         let is_existing = self.file_info.contains_key(&id);
         if is_existing {
-            self.save_existing(path, text, id)
+            self.save_existing(path, text, id, encoding)
         } else {
-            self.save_new(path, text, id)
+            self.save_new(path, text, id, encoding)
         }
     }
 
-    fn save_new(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
-        try_save(path, text, CharacterEncoding::Utf8, self.get_info(id))
+    fn save_new(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: CharacterEncoding,
+    ) -> Result<(), FileError> {
+        try_save(path, text, encoding, self.get_info(id))
             .map_err(|e| FileError::Io(e, path.to_owned()))?;
         let info = FileInfo {
-            encoding: CharacterEncoding::Utf8,
+            encoding,
             path: path.to_owned(),
             mod_time: get_mod_time(path),
             has_changed: false,
             #[cfg(target_family = "unix")]
             permissions: get_permissions(path),
+            large_file: self.get_info(id).is_some_and(|info| info.large_file),
         };
         self.open_files.insert(path.to_owned(), id);
         self.file_info.insert(id, info);
@@ -155,37 +418,48 @@ impl FileManager {
         Ok(())
     }
 
-    fn save_existing(&mut self, path: &Path, text: &Rope, id: BufferId) -> Result<(), FileError> {
+    fn save_existing(
+        &mut self,
+        path: &Path,
+        text: &Rope,
+        id: BufferId,
+        encoding: CharacterEncoding,
+    ) -> Result<(), FileError> {
         let prev_path = self.file_info[&id].path.clone();
         if prev_path != path {
-            self.save_new(path, text, id)?;
+            self.save_new(path, text, id, encoding)?;
             self.open_files.remove(&prev_path);
             #[cfg(feature = "notify")]
             self.watcher.unwatch(&prev_path, OPEN_FILE_EVENT_TOKEN);
         } else if self.file_info[&id].has_changed {
             return Err(FileError::HasChanged(path.to_owned()));
         } else {
-            let encoding = self.file_info[&id].encoding;
             try_save(path, text, encoding, self.get_info(id))
                 .map_err(|e| FileError::Io(e, path.to_owned()))?;
-            self.file_info.get_mut(&id).unwrap().mod_time = get_mod_time(path);
+            let info = self.file_info.get_mut(&id).unwrap();
+            info.mod_time = get_mod_time(path);
+            info.encoding = encoding;
         }
         Ok(())
     }
 }
 
-fn try_load_file<P>(path: P) -> Result<(Rope, FileInfo), FileError>
+/// Loads the file at `path`, decoding it with `encoding`, or with the encoding
+/// guessed from its contents if `None`.
+fn try_load_file<P>(
+    path: P,
+    encoding: Option<CharacterEncoding>,
+) -> Result<(Rope, FileInfo), FileError>
 where
     P: AsRef<Path>,
 {
-    // TODO: support for non-utf8
     // it's arguable that the rope crate should have file loading functionality
     let mut f =
         File::open(path.as_ref()).map_err(|e| FileError::Io(e, path.as_ref().to_owned()))?;
     let mut bytes = Vec::new();
     f.read_to_end(&mut bytes).map_err(|e| FileError::Io(e, path.as_ref().to_owned()))?;
 
-    let encoding = CharacterEncoding::guess(&bytes);
+    let encoding = encoding.unwrap_or_else(|| CharacterEncoding::guess(&bytes));
     let rope = try_decode(bytes, encoding, path.as_ref())?;
     let info = FileInfo {
         encoding,
@@ -194,10 +468,84 @@ where
         permissions: get_permissions(&path),
         path: path.as_ref().to_owned(),
         has_changed: false,
+        large_file: false,
     };
     Ok((rope, info))
 }
 
+impl FileLoader {
+    fn new(path: &Path) -> Result<Self, FileError> {
+        let file = File::open(path).map_err(|e| FileError::Io(e, path.to_owned()))?;
+        let total = file.metadata().map_err(|e| FileError::Io(e, path.to_owned()))?.len();
+        Ok(FileLoader {
+            file,
+            path: path.to_owned(),
+            encoding: None,
+            decoder: None,
+            builder: TreeBuilder::new(),
+            loaded: 0,
+            total,
+            done: false,
+        })
+    }
+
+    /// Reads and decodes the next chunk of the file. Returns `true` once the
+    /// whole file has been read.
+    pub fn load_chunk(&mut self) -> Result<bool, FileError> {
+        let mut chunk = Vec::with_capacity(LOAD_CHUNK_SIZE);
+        (&mut self.file)
+            .take(LOAD_CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)
+            .map_err(|e| FileError::Io(e, self.path.clone()))?;
+        self.loaded += chunk.len() as u64;
+        let last = chunk.len() < LOAD_CHUNK_SIZE;
+
+        let offset = self.loaded - chunk.len() as u64;
+        let mut bytes = &chunk[..];
+        if self.encoding.is_none() {
+            // the chunk may end in the middle of a character
+            let sample = match str::from_utf8(bytes) {
+                Err(e) if e.error_len().is_none() => &bytes[..e.valid_up_to()],
+                _ => bytes,
+            };
+            let encoding = CharacterEncoding::guess(sample);
+            self.encoding = Some(encoding);
+            self.decoder = encoding.decoder();
+            bytes = bytes.strip_prefix(encoding.bom()).unwrap_or(bytes);
+        }
+
+        let text = match self.decoder.as_mut() {
+            Some(decoder) => {
+                let capacity = decoder
+                    .max_utf8_buffer_length_without_replacement(bytes.len())
+                    .expect("chunks are small");
+                let mut text = String::with_capacity(capacity);
+                match decoder.decode_to_string_without_replacement(bytes, &mut text, last) {
+                    (DecoderResult::InputEmpty, _) => Cow::Owned(text),
+                    _ => return Err(FileError::UnknownEncoding(self.path.clone())),
+                }
+            }
+            None if self.encoding == Some(CharacterEncoding::Hex) => {
+                Cow::Owned(hex_dump::dump(bytes, offset))
+            }
+            None => encoding_rs::mem::decode_latin1(bytes),
+        };
+        self.builder.push_str(&text);
+        self.done = last;
+        Ok(last)
+    }
+
+    /// Returns `true` if the whole file has been loaded.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns the number of bytes read so far, and the size of the file.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.loaded, self.total)
+    }
+}
+
 #[allow(unused)]
 fn try_save(
     path: &Path,
@@ -216,13 +564,9 @@ fn try_save(
     let tmp_path = &path.with_extension(tmp_extension);
 
     let mut f = File::create(tmp_path)?;
-    match encoding {
-        CharacterEncoding::Utf8WithBom => f.write_all(UTF8_BOM.as_bytes())?,
-        CharacterEncoding::Utf8 => (),
-    }
-
-    for chunk in text.iter_chunks(..text.len()) {
-        f.write_all(chunk.as_bytes())?;
+    if let Err(e) = write_encoded(&mut f, text, encoding) {
+        fs::remove_file(tmp_path)?;
+        return Err(e);
     }
 
     fs::rename(tmp_path, path)?;
@@ -240,29 +584,266 @@ fn try_save(
     Ok(())
 }
 
-fn try_decode(bytes: Vec<u8>, encoding: CharacterEncoding, path: &Path) -> Result<Rope, FileError> {
-    match encoding {
-        CharacterEncoding::Utf8 => Ok(Rope::from(
-            str::from_utf8(&bytes).map_err(|_e| FileError::UnknownEncoding(path.to_owned()))?,
-        )),
-        CharacterEncoding::Utf8WithBom => {
-            let s = String::from_utf8(bytes)
-                .map_err(|_e| FileError::UnknownEncoding(path.to_owned()))?;
-            Ok(Rope::from(&s[UTF8_BOM.len()..]))
+fn write_encoded(f: &mut File, text: &Rope, encoding: CharacterEncoding) -> io::Result<()> {
+    f.write_all(encoding.bom())?;
+    if encoding == CharacterEncoding::Hex {
+        // the lines of a hex dump may be split between chunks
+        return write_encoded_chunk(f, &String::from(text), encoding);
+    }
+    for chunk in text.iter_chunks(..text.len()) {
+        write_encoded_chunk(f, chunk, encoding)?;
+    }
+    Ok(())
+}
+
+fn write_encoded_chunk(f: &mut File, text: &str, encoding: CharacterEncoding) -> io::Result<()> {
+    let bytes = encoding.encode(text).ok_or_else(|| {
+        let message = format!("The text can't be encoded in {:?}", encoding);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
+    f.write_all(&bytes)
+}
+
+/// Loads the undo history in the undo file at `undo_path`, if it belongs to
+/// the file at `path` and was saved with `text`.
+fn try_load_undo_file(
+    undo_path: &Path,
+    path: &Path,
+    text: &Rope,
+) -> io::Result<Option<UndoHistory<Engine, UndoTree>>> {
+    if fs::metadata(undo_path)?.len() > MAX_UNDO_FILE_SIZE {
+        return Ok(None);
+    }
+    let f = File::open(undo_path)?;
+    let undo_file: UndoFile<UndoHistory<Engine, UndoTree>> =
+        serde_json::from_reader(io::BufReader::new(f))?;
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if undo_file.version != UNDO_FILE_VERSION
+        || undo_file.path != path
+        || undo_file.content_hash != content_hash(text)
+    {
+        return Ok(None);
+    }
+    Ok(Some(undo_file.history))
+}
+
+/// Saves an undo history for the file at `path`, saved as `text`. Histories
+/// too large to be saved remove any previous undo file instead.
+fn try_save_undo_file(
+    undo_path: &Path,
+    path: &Path,
+    text: &Rope,
+    history: UndoHistory<&Engine, &UndoTree>,
+) -> io::Result<()> {
+    let undo_file = UndoFile {
+        version: UNDO_FILE_VERSION,
+        path: path.canonicalize().unwrap_or_else(|_| path.to_owned()),
+        content_hash: content_hash(text),
+        history,
+    };
+    let bytes = serde_json::to_vec(&undo_file)?;
+    if bytes.len() as u64 > MAX_UNDO_FILE_SIZE {
+        if undo_path.exists() {
+            fs::remove_file(undo_path)?;
         }
+        return Ok(());
     }
+
+    if let Some(dir) = undo_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = undo_path.with_extension("json.swp");
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(tmp_path, undo_path)
+}
+
+/// Loads the text in the recovery file at `recovery_path`, if it belongs to
+/// the file at `path`.
+fn try_load_recovery_file(recovery_path: &Path, path: &Path) -> io::Result<Option<String>> {
+    let f = File::open(recovery_path)?;
+    let recovery_file: RecoveryFile<String> = serde_json::from_reader(io::BufReader::new(f))?;
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if recovery_file.version != RECOVERY_FILE_VERSION || recovery_file.path != path {
+        return Ok(None);
+    }
+    Ok(Some(recovery_file.text))
+}
+
+/// Saves `text` as the unsaved contents of the file at `path`.
+fn try_save_recovery_file(recovery_path: &Path, path: &Path, text: &Rope) -> io::Result<()> {
+    let recovery_file = RecoveryFile {
+        version: RECOVERY_FILE_VERSION,
+        path: path.canonicalize().unwrap_or_else(|_| path.to_owned()),
+        text: String::from(text),
+    };
+    let bytes = serde_json::to_vec(&recovery_file)?;
+    if let Some(dir) = recovery_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = recovery_path.with_extension("json.swp");
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(tmp_path, recovery_path)
+}
+
+/// Returns the path in `dir` where state about the file at `path`, such as
+/// its undo history, is kept.
+fn state_path(dir: &Path, path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    dir.join(format!("{:016x}.json", fnv_hash(path.to_string_lossy().bytes())))
+}
+
+/// Hashes the contents of a file, for checking that it hasn't changed since
+/// its undo history was saved.
+fn content_hash(text: &Rope) -> u64 {
+    fnv_hash(text.iter_chunks(..).flat_map(str::bytes))
+}
+
+/// The 64 bit FNV-1a hash, which unlike `DefaultHasher` is stable across
+/// releases and thus suitable for data stored on disk.
+fn fnv_hash<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+fn try_decode(bytes: Vec<u8>, encoding: CharacterEncoding, path: &Path) -> Result<Rope, FileError> {
+    let bytes = bytes.strip_prefix(encoding.bom()).unwrap_or(&bytes);
+    let text = match encoding {
+        CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => {
+            str::from_utf8(bytes).ok().map(Cow::Borrowed)
+        }
+        CharacterEncoding::Latin1 => Some(encoding_rs::mem::decode_latin1(bytes)),
+        CharacterEncoding::Hex => Some(Cow::Owned(hex_dump::dump(bytes, 0))),
+        _ => encoding
+            .legacy()
+            .and_then(|enc| enc.decode_without_bom_handling_and_without_replacement(bytes)),
+    };
+    text.map(Rope::from).ok_or_else(|| FileError::UnknownEncoding(path.to_owned()))
 }
 
 impl CharacterEncoding {
+    /// Guesses the encoding of a file from its byte order mark, or else from
+    /// its contents: text with many zero bytes is taken to be UTF-16, other
+    /// files starting with zero bytes or control characters to be binary,
+    /// and text that isn't valid UTF-8 to be Shift-JIS if it looks like
+    /// Japanese text, and otherwise in one of the Western 8-bit encodings.
     fn guess(s: &[u8]) -> Self {
         if s.starts_with(UTF8_BOM.as_bytes()) {
             CharacterEncoding::Utf8WithBom
-        } else {
+        } else if s.starts_with(UTF16LE_BOM) {
+            CharacterEncoding::Utf16Le
+        } else if s.starts_with(UTF16BE_BOM) {
+            CharacterEncoding::Utf16Be
+        } else if let Some(encoding) = CharacterEncoding::guess_utf16(s) {
+            encoding
+        } else if hex_dump::is_binary(&s[..s.len().min(BINARY_SAMPLE_SIZE)]) {
+            CharacterEncoding::Hex
+        } else if str::from_utf8(s).is_ok() {
             CharacterEncoding::Utf8
+        } else if looks_like_shift_jis(s) {
+            CharacterEncoding::ShiftJis
+        } else if s.iter().any(|&b| (0x80..0xa0).contains(&b)) {
+            // these are control characters in Latin-1, but printable in
+            // Windows-1252, which is much more likely
+            CharacterEncoding::Windows1252
+        } else {
+            CharacterEncoding::Latin1
+        }
+    }
+
+    /// Recognizes UTF-16 without a byte order mark by the zero high bytes of
+    /// ASCII characters, which make up most of the text in either byte order.
+    fn guess_utf16(s: &[u8]) -> Option<Self> {
+        let units = s.chunks_exact(2);
+        if !units.remainder().is_empty() {
+            return None;
+        }
+        let n_units = units.len();
+        let even_zeros = units.clone().filter(|unit| unit[0] == 0).count();
+        let odd_zeros = units.filter(|unit| unit[1] == 0).count();
+        match (even_zeros, odd_zeros) {
+            (0, odd) if odd > n_units / 2 => Some(CharacterEncoding::Utf16Le),
+            (even, 0) if even > n_units / 2 => Some(CharacterEncoding::Utf16Be),
+            _ => None,
+        }
+    }
+
+    /// The byte order mark written at the start of files in this encoding.
+    fn bom(self) -> &'static [u8] {
+        match self {
+            CharacterEncoding::Utf8WithBom => UTF8_BOM.as_bytes(),
+            CharacterEncoding::Utf16Le => UTF16LE_BOM,
+            CharacterEncoding::Utf16Be => UTF16BE_BOM,
+            _ => &[],
+        }
+    }
+
+    /// Returns a decoder for this encoding, or `None` for Latin-1 and hex
+    /// dumps.
+    fn decoder(self) -> Option<Decoder> {
+        match self {
+            CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => {
+                Some(UTF_8.new_decoder_without_bom_handling())
+            }
+            _ => self.legacy().map(Encoding::new_decoder_without_bom_handling),
+        }
+    }
+
+    /// The `encoding_rs` decoder for encodings other than UTF-8 and Latin-1.
+    fn legacy(self) -> Option<&'static Encoding> {
+        match self {
+            CharacterEncoding::Utf16Le => Some(UTF_16LE),
+            CharacterEncoding::Utf16Be => Some(UTF_16BE),
+            CharacterEncoding::Windows1252 => Some(WINDOWS_1252),
+            CharacterEncoding::ShiftJis => Some(SHIFT_JIS),
+            _ => None,
+        }
+    }
+
+    /// Encodes `text`, or returns `None` if it has characters this encoding
+    /// can't represent.
+    fn encode(self, text: &str) -> Option<Cow<'_, [u8]>> {
+        match self {
+            CharacterEncoding::Utf8 | CharacterEncoding::Utf8WithBom => {
+                Some(Cow::Borrowed(text.as_bytes()))
+            }
+            // encoding_rs only decodes UTF-16
+            CharacterEncoding::Utf16Le => {
+                Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect())
+            }
+            CharacterEncoding::Utf16Be => {
+                Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect())
+            }
+            CharacterEncoding::Latin1 => {
+                text.chars().map(|c| u8::try_from(u32::from(c)).ok()).collect()
+            }
+            CharacterEncoding::Hex => hex_dump::parse(text).map(Cow::Owned),
+            CharacterEncoding::Windows1252 | CharacterEncoding::ShiftJis => {
+                let (bytes, _, unmappable) = self.legacy()?.encode(text);
+                if unmappable {
+                    None
+                } else {
+                    Some(bytes)
+                }
+            }
         }
     }
 }
 
+/// Returns whether `s` decodes as Shift-JIS into text with a fair share of
+/// hiragana and katakana. Western 8-bit text often decodes as Shift-JIS too,
+/// its accented letters and the ASCII letters following them making up
+/// kanji, but hardly ever into kana.
+fn looks_like_shift_jis(s: &[u8]) -> bool {
+    let text = match SHIFT_JIS.decode_without_bom_handling_and_without_replacement(s) {
+        Some(text) => text,
+        None => return false,
+    };
+    let non_ascii = text.chars().filter(|c| !c.is_ascii()).count();
+    let kana = text.chars().filter(|c| ('\u{3041}'..='\u{30ff}').contains(c)).count();
+    kana > 0 && kana * 4 >= non_ascii
+}
+
 /// Returns the modification timestamp for the file at a given path,
 /// if present.
 fn get_mod_time<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
@@ -310,3 +891,106 @@ impl fmt::Display for FileError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_file_round_trip() {
+        let tmp = tempdir::TempDir::new("xi-test-undo-file").unwrap();
+        let path = tmp.path().join("file.txt");
+        let undo_path = tmp.path().join("undo").join("file.json");
+        let text = Rope::from("hello");
+        let editor = Editor::with_text(text.clone());
+        fs::write(&path, "hello").unwrap();
+
+        try_save_undo_file(&undo_path, &path, &text, editor.undo_history()).unwrap();
+        assert!(try_load_undo_file(&undo_path, &path, &text).unwrap().is_some());
+        let other_text = Rope::from("hello!");
+        assert!(try_load_undo_file(&undo_path, &path, &other_text).unwrap().is_none());
+        let other_path = tmp.path().join("other.txt");
+        assert!(try_load_undo_file(&undo_path, &other_path, &text).unwrap().is_none());
+    }
+
+    #[test]
+    fn recovery_file_round_trip() {
+        let tmp = tempdir::TempDir::new("xi-test-recovery-file").unwrap();
+        let path = tmp.path().join("file.txt");
+        let recovery_path = tmp.path().join("recovery").join("file.json");
+        fs::write(&path, "hello").unwrap();
+
+        try_save_recovery_file(&recovery_path, &path, &Rope::from("hello!")).unwrap();
+        let recovered = try_load_recovery_file(&recovery_path, &path).unwrap();
+        assert_eq!(recovered.as_deref(), Some("hello!"));
+        let other_path = tmp.path().join("other.txt");
+        assert!(try_load_recovery_file(&recovery_path, &other_path).unwrap().is_none());
+    }
+
+    #[test]
+    fn load_in_chunks() {
+        let tmp = tempdir::TempDir::new("xi-test-large-file").unwrap();
+        let path = tmp.path().join("file.txt");
+        // the second character is split between the first two chunks
+        let text =
+            format!("{}\u{e9}{}", "a".repeat(LOAD_CHUNK_SIZE - 1), "b".repeat(LOAD_CHUNK_SIZE));
+        fs::write(&path, &text).unwrap();
+
+        let mut loader = FileLoader::new(&path).unwrap();
+        assert!(!loader.load_chunk().unwrap());
+        assert_eq!(loader.progress(), (LOAD_CHUNK_SIZE as u64, text.len() as u64));
+        while !loader.load_chunk().unwrap() {}
+        assert!(loader.is_done());
+        assert_eq!(loader.encoding, Some(CharacterEncoding::Utf8));
+        assert_eq!(String::from(loader.builder.build()), text);
+    }
+
+    #[test]
+    fn guess_encoding() {
+        use self::CharacterEncoding::*;
+        assert_eq!(CharacterEncoding::guess(b"hello"), Utf8);
+        assert_eq!(CharacterEncoding::guess("\u{feff}h\u{e9}".as_bytes()), Utf8WithBom);
+        assert_eq!(CharacterEncoding::guess(b"\xff\xfeh\x00i\x00"), Utf16Le);
+        assert_eq!(CharacterEncoding::guess(b"h\x00i\x00!\x00"), Utf16Le);
+        assert_eq!(CharacterEncoding::guess(b"\x00h\x00i\x00!"), Utf16Be);
+        assert_eq!(CharacterEncoding::guess(b"caf\xe9 au lait"), Latin1);
+        assert_eq!(CharacterEncoding::guess(b"\x93quoted\x94 text"), Windows1252);
+        assert_eq!(CharacterEncoding::guess(b"\x82\xa0\x82\xa2 abc"), ShiftJis);
+        // accented Latin-1 words that happen to decode as Shift-JIS kanji
+        assert_eq!(CharacterEncoding::guess(b"\xe9l\xe9phant"), Latin1);
+        assert_eq!(CharacterEncoding::guess(b"caf\xe9s r\xe9p\xe9t\xe9s"), Latin1);
+        assert_eq!(CharacterEncoding::guess(b"\x7fELF\x02\x01\x01\0"), Hex);
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        use self::CharacterEncoding::*;
+        let tmp = tempdir::TempDir::new("xi-test-encodings").unwrap();
+        let path = tmp.path().join("file.txt");
+        let text = Rope::from("caf\u{e9}\n");
+        for &encoding in &[Utf8, Utf8WithBom, Utf16Le, Utf16Be, Latin1, Windows1252, ShiftJis] {
+            let text = if encoding == ShiftJis { Rope::from("\u{3042}\n") } else { text.clone() };
+            try_save(&path, &text, encoding, None).unwrap();
+            let (loaded, info) = try_load_file(&path, None).unwrap();
+            assert_eq!(String::from(loaded), String::from(&text));
+            // Latin-1 and Windows-1252 can't be told apart in this text
+            if encoding != Windows1252 {
+                assert_eq!(info.encoding, encoding);
+            }
+        }
+
+        assert!(try_save(&path, &Rope::from("\u{3042}"), Latin1, None).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"\x82\xa0\n");
+        let (loaded, _) = try_load_file(&path, Some(Latin1)).unwrap();
+        assert_eq!(String::from(loaded), "\u{82}\u{a0}\n");
+
+        let bytes = b"\x7fELF\x02\x01\x01\0\xff";
+        fs::write(&path, bytes).unwrap();
+        let (loaded, info) = try_load_file(&path, None).unwrap();
+        assert_eq!(info.encoding, Hex);
+        let dump = String::from(loaded).replace("7f 45", "7f 46");
+        try_save(&path, &Rope::from(dump), Hex, None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\x7fFLF\x02\x01\x01\0\xff");
+        assert!(try_save(&path, &Rope::from("00000000  7g"), Hex, None).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracking of folded (hidden) regions of a view.
+
+use std::collections::HashMap;
+
+use xi_rope::delta::DeltaElement;
+use xi_rope::{Interval, Rope, RopeDelta, Transformer};
+
+use crate::annotations::{AnnotationRange, AnnotationSlice, AnnotationType, ToAnnotation};
+use crate::line_offset::LineOffset;
+use crate::plugins::PluginId;
+use crate::view::View;
+
+/// The set of folded regions in a view.
+///
+/// Each fold is an interval `[start, end)` covering whole logical lines:
+/// `start` is the offset of the first hidden line (and is always preceded
+/// by the newline of the visible "header" line), and `end` is either the
+/// offset of the first line after the fold or the end of the text.
+///
+/// Folds are kept sorted and never overlap.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Folds {
+    ranges: Vec<Interval>,
+}
+
+impl Folds {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Interval> {
+        self.ranges.iter()
+    }
+
+    /// Adds a fold. Any existing folds that overlap or touch the new one
+    /// are merged into it.
+    pub(crate) fn add(&mut self, iv: Interval) {
+        if iv.is_empty() || iv.start == 0 {
+            return;
+        }
+        let mut merged = iv;
+        self.ranges.retain(|fold| {
+            if fold.start <= merged.end && merged.start <= fold.end {
+                merged = merged.union(*fold);
+                false
+            } else {
+                true
+            }
+        });
+        let idx = self.ranges.iter().position(|fold| fold.start > merged.start);
+        self.ranges.insert(idx.unwrap_or(self.ranges.len()), merged);
+    }
+
+    /// Removes all folds whose header line or hidden lines intersect the
+    /// line range `[iv.start, iv.end)`. Returns `true` if anything was removed.
+    pub(crate) fn remove_in_lines(&mut self, iv: Interval) -> bool {
+        let old_len = self.ranges.len();
+        self.ranges.retain(|fold| !(fold.start - 1 < iv.end && fold.end > iv.start));
+        old_len != self.ranges.len()
+    }
+
+    /// Removes all folds. Returns `true` if there were any.
+    pub(crate) fn clear(&mut self) -> bool {
+        let had_folds = !self.is_empty();
+        self.ranges.clear();
+        had_folds
+    }
+
+    /// Returns the fold whose hidden region contains `offset`, if any.
+    pub(crate) fn containing(&self, offset: usize) -> Option<Interval> {
+        self.ranges.iter().find(|fold| fold.start <= offset && offset < fold.end).cloned()
+    }
+
+    /// Returns the fold whose hidden region begins at `offset`, if any.
+    pub(crate) fn starting_at(&self, offset: usize) -> Option<Interval> {
+        self.ranges.iter().find(|fold| fold.start == offset).cloned()
+    }
+
+    /// Updates the folds after an edit. A fold that is touched by one of the
+    /// changes of the edit (including the newline ending its header line) is
+    /// removed, which reveals its contents; other folds are shifted as needed.
+    pub(crate) fn apply_delta(&mut self, delta: &RopeDelta) {
+        if self.is_empty() {
+            return;
+        }
+        let changes = changed_intervals(delta);
+        let base_len = delta.base_len;
+        let mut transformer = Transformer::new(delta);
+        let mut ranges = Vec::with_capacity(self.ranges.len());
+        for fold in &self.ranges {
+            let touched = changes.iter().any(|iv| {
+                let before = iv.end < fold.start;
+                let after = iv.start > fold.end || (iv.start == fold.end && fold.end < base_len);
+                !before && !after
+            });
+            if !touched {
+                let start = transformer.transform(fold.start, true);
+                let end = transformer.transform(fold.end, false);
+                if start < end {
+                    ranges.push(Interval::new(start, end));
+                }
+            }
+        }
+        self.ranges = ranges;
+    }
+}
+
+/// Returns the intervals of the base text replaced by each change of `delta`,
+/// in order. An insertion replaces an empty interval.
+fn changed_intervals(delta: &RopeDelta) -> Vec<Interval> {
+    let mut changes = Vec::new();
+    let mut last_end = 0;
+    let mut inserted = false;
+    for el in &delta.els {
+        match *el {
+            DeltaElement::Copy(start, end) => {
+                if start > last_end || inserted {
+                    changes.push(Interval::new(last_end, start));
+                }
+                last_end = end;
+                inserted = false;
+            }
+            DeltaElement::Insert(_) => inserted = true,
+        }
+    }
+    if delta.base_len > last_end || inserted {
+        changes.push(Interval::new(last_end, delta.base_len));
+    }
+    changes
+}
+
+/// The foldable regions of a buffer, as reported by plugins.
+///
+/// Unlike `Folds`, these are not hidden; they are the regions a client may
+/// offer to fold, and are preferred over indentation when folding at a caret.
+#[derive(Debug, Default)]
+pub struct FoldRanges {
+    ranges: HashMap<PluginId, Vec<Interval>>,
+    /// The ranges from all plugins, sorted and deduplicated; kept in sync
+    /// with `ranges` whenever they change.
+    merged: Vec<Interval>,
+}
+
+impl FoldRanges {
+    /// Replaces the ranges provided by `plugin` that start in `iv` with `ranges`.
+    pub(crate) fn update(&mut self, plugin: PluginId, iv: Interval, ranges: Vec<Interval>) {
+        let entry = self.ranges.entry(plugin).or_default();
+        entry.retain(|range| !iv.contains(range.start));
+        entry.extend(ranges.into_iter().filter(|range| !range.is_empty()));
+        entry.sort_by_key(|range| (range.start, range.end));
+        self.merge();
+    }
+
+    /// Removes the ranges provided by `plugin`. Returns `true` if there were any.
+    pub(crate) fn remove_plugin(&mut self, plugin: PluginId) -> bool {
+        let removed = self.ranges.remove(&plugin).map(|ranges| !ranges.is_empty()).unwrap_or(false);
+        if removed {
+            self.merge();
+        }
+        removed
+    }
+
+    /// Shifts the ranges through an edit. Ranges that become empty are removed.
+    pub(crate) fn apply_delta(&mut self, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        for ranges in self.ranges.values_mut() {
+            *ranges = ranges
+                .iter()
+                .map(|range| {
+                    let start = transformer.transform(range.start, false);
+                    let end = transformer.transform(range.end, false);
+                    Interval::new(start, end)
+                })
+                .filter(|range| !range.is_empty())
+                .collect();
+        }
+        self.merge();
+    }
+
+    /// Returns all ranges, from all plugins, sorted by start offset.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Interval> + '_ {
+        self.merged.iter().cloned()
+    }
+
+    fn merge(&mut self) {
+        let mut merged = self.ranges.values().flatten().cloned().collect::<Vec<_>>();
+        merged.sort_by_key(|range| (range.start, range.end));
+        merged.dedup();
+        self.merged = merged;
+    }
+
+    /// Returns the region to hide when folding at the given logical line:
+    /// the outermost range starting on that line, or else the innermost
+    /// range containing it.
+    pub(crate) fn fold_for_line(&self, text: &Rope, line: usize) -> Option<Interval> {
+        let folds = self
+            .iter()
+            .filter_map(|range| {
+                let fold = line_fold_range(text, range.start, range.end)?;
+                let first_line = text.line_of_offset(range.start);
+                let last_line = text.line_of_offset(fold.end - 1);
+                if first_line <= line && line <= last_line {
+                    Some((first_line, fold))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let starting_here = folds
+            .iter()
+            .filter(|(first_line, _)| *first_line == line)
+            .max_by_key(|(_, fold)| fold.size());
+        let containing = folds.iter().min_by_key(|(_, fold)| fold.size());
+        starting_here.or(containing).map(|(_, fold)| *fold)
+    }
+}
+
+impl ToAnnotation for FoldRanges {
+    fn get_annotations(&self, interval: Interval, view: &View, text: &Rope) -> AnnotationSlice {
+        let folds = view.get_lines().folds();
+        let (ranges, payloads): (Vec<_>, Vec<_>) = self
+            .iter()
+            .filter(|range| range.start <= interval.end && range.end >= interval.start)
+            .map(|range| {
+                let (start_line, start_col) = view.offset_to_line_col(text, range.start);
+                let (end_line, end_col) = view.offset_to_line_col(text, range.end);
+                let folded = line_fold_range(text, range.start, range.end)
+                    .and_then(|fold| folds.starting_at(fold.start))
+                    .is_some();
+                let range = AnnotationRange { start_line, start_col, end_line, end_col };
+                (range, json!({ "folded": folded }))
+            })
+            .unzip();
+        AnnotationSlice::new(AnnotationType::FoldRange, ranges, Some(payloads))
+    }
+}
+
+/// Returns the fold covering the lines after `start`'s line, up to and
+/// including the line containing `end`. A selection ending at the start of
+/// a line does not include that line.
+pub(crate) fn line_fold_range(text: &Rope, start: usize, end: usize) -> Option<Interval> {
+    let first_line = text.line_of_offset(start);
+    let mut last_line = text.line_of_offset(end);
+    if end > start && end == text.offset_of_line(last_line) {
+        last_line = last_line.saturating_sub(1);
+    }
+    if last_line <= first_line {
+        return None;
+    }
+    Some(Interval::new(text.offset_of_line(first_line + 1), text.offset_of_line(last_line + 1)))
+}
+
+/// Returns an indentation-based fold for the given logical line.
+///
+/// If the line is followed by more deeply indented lines, those lines are
+/// folded. Otherwise, the innermost indented block containing the line is
+/// folded. Blank lines inside a block are folded with it; trailing blank
+/// lines are not.
+pub(crate) fn indent_fold_range(text: &Rope, line: usize) -> Option<Interval> {
+    if let Some(iv) = indent_block_below(text, line) {
+        return Some(iv);
+    }
+    let indent = indent_of_line(text, line)?;
+    let header =
+        (0..line).rev().find(|&l| matches!(indent_of_line(text, l), Some(i) if i < indent))?;
+    indent_block_below(text, header)
+}
+
+fn indent_block_below(text: &Rope, line: usize) -> Option<Interval> {
+    let header_indent = indent_of_line(text, line)?;
+    let n_lines = text.line_of_offset(text.len()) + 1;
+    let mut last_line = line;
+    for l in line + 1..n_lines {
+        match indent_of_line(text, l) {
+            None => continue,
+            Some(indent) if indent > header_indent => last_line = l,
+            Some(_) => break,
+        }
+    }
+    if last_line == line {
+        return None;
+    }
+    Some(Interval::new(text.offset_of_line(line + 1), text.offset_of_line(last_line + 1)))
+}
+
+/// Returns the number of leading whitespace characters on the line, or
+/// `None` if the line is blank.
+pub(crate) fn indent_of_line(text: &Rope, line: usize) -> Option<usize> {
+    let start = text.offset_of_line(line);
+    let end = text.offset_of_line(line + 1);
+    let line_text = text.slice_to_cow(start..end);
+    let trimmed = line_text.trim_start();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(line_text.len() - trimmed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xi_rope::DeltaBuilder;
+
+    const TEXT: &str = "fn a() {\n    one;\n\n    two;\n}\n\nfn b() {}\n";
+
+    #[test]
+    fn indent_ranges() {
+        let text = Rope::from(TEXT);
+        assert_eq!(indent_fold_range(&text, 0), Some(Interval::new(9, 28)));
+        // a line inside the block folds the enclosing block
+        assert_eq!(indent_fold_range(&text, 3), Some(Interval::new(9, 28)));
+        assert_eq!(indent_fold_range(&text, 4), None);
+        assert_eq!(indent_fold_range(&text, 5), None);
+        assert_eq!(indent_fold_range(&text, 6), None);
+    }
+
+    #[test]
+    fn line_ranges() {
+        let text = Rope::from(TEXT);
+        assert_eq!(line_fold_range(&text, 2, 20), Some(Interval::new(9, 28)));
+        assert_eq!(line_fold_range(&text, 2, 19), Some(Interval::new(9, 19)));
+        assert_eq!(line_fold_range(&text, 2, 17), Some(Interval::new(9, 18)));
+        assert_eq!(line_fold_range(&text, 2, 8), None);
+    }
+
+    #[test]
+    fn add_merges() {
+        let mut folds = Folds::default();
+        folds.add(Interval::new(20, 30));
+        folds.add(Interval::new(5, 10));
+        folds.add(Interval::new(25, 40));
+        assert_eq!(
+            folds.iter().cloned().collect::<Vec<_>>(),
+            vec![(5..10).into(), (20..40).into()]
+        );
+        assert!(folds.remove_in_lines(Interval::new(0, 5)));
+        assert_eq!(folds.iter().cloned().collect::<Vec<_>>(), vec![(20..40).into()]);
+        assert!(!folds.remove_in_lines(Interval::new(41, 45)));
+        assert!(folds.clear());
+        assert!(folds.is_empty());
+    }
+
+    #[test]
+    fn apply_delta() {
+        let text = Rope::from(TEXT);
+        let mut folds = Folds::default();
+        folds.add(Interval::new(9, 28));
+
+        // insert before the header's newline: fold is shifted
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(Interval::new(8, 8), "xx".into());
+        folds.apply_delta(&builder.build());
+        assert_eq!(folds.containing(11), Some(Interval::new(11, 30)));
+
+        // insert after the fold: fold is unchanged
+        let mut builder = DeltaBuilder::new(text.len() + 2);
+        builder.replace(Interval::new(30, 30), "yy".into());
+        folds.apply_delta(&builder.build());
+        assert_eq!(folds.starting_at(11), Some(Interval::new(11, 30)));
+
+        // edit inside the fold: fold is removed
+        let mut builder = DeltaBuilder::new(text.len() + 4);
+        builder.delete(Interval::new(15, 16));
+        folds.apply_delta(&builder.build());
+        assert!(folds.is_empty());
+    }
+
+    #[test]
+    fn apply_multi_site_delta() {
+        let text = Rope::from(TEXT);
+        let mut folds = Folds::default();
+        folds.add(Interval::new(9, 28));
+        folds.add(Interval::new(38, 40));
+
+        // two carets typing before and after the first fold
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(Interval::new(2, 2), "x".into());
+        builder.replace(Interval::new(30, 30), "y".into());
+        folds.apply_delta(&builder.build());
+        assert_eq!(
+            folds.iter().cloned().collect::<Vec<_>>(),
+            vec![(10..29).into(), (40..42).into()]
+        );
+
+        // only the fold touched by one of the changes is removed
+        let mut builder = DeltaBuilder::new(text.len() + 2);
+        builder.delete(Interval::new(0, 1));
+        builder.delete(Interval::new(40, 41));
+        folds.apply_delta(&builder.build());
+        assert_eq!(folds.iter().cloned().collect::<Vec<_>>(), vec![(9..28).into()]);
+    }
+
+    #[test]
+    fn plugin_ranges() {
+        use crate::plugins::PluginPid;
+
+        let text = Rope::from(TEXT);
+        let mut ranges = FoldRanges::default();
+        ranges.update(PluginPid(1), Interval::new(0, 40), vec![Interval::new(0, 30)]);
+        ranges.update(PluginPid(2), Interval::new(0, 40), vec![Interval::new(14, 27)]);
+        assert_eq!(ranges.fold_for_line(&text, 0), Some(Interval::new(9, 30)));
+        // the innermost range containing the line
+        assert_eq!(ranges.fold_for_line(&text, 2), Some(Interval::new(18, 28)));
+        assert_eq!(ranges.fold_for_line(&text, 6), None);
+
+        // updating an interval replaces the ranges starting in it
+        ranges.update(PluginPid(2), Interval::new(10, 20), vec![]);
+        assert_eq!(ranges.iter().collect::<Vec<_>>(), vec![Interval::new(0, 30)]);
+
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(Interval::new(3, 3), "xx".into());
+        ranges.apply_delta(&builder.build());
+        assert_eq!(ranges.iter().collect::<Vec<_>>(), vec![Interval::new(0, 32)]);
+        assert!(ranges.remove_plugin(PluginPid(1)));
+        assert_eq!(ranges.iter().count(), 0);
+    }
+}
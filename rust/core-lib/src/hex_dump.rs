@@ -0,0 +1,119 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hex dumps, which binary files are edited as.
+//!
+//! Each line of a dump shows the offset of its first byte, up to 16 bytes in
+//! hexadecimal, and those bytes as ASCII:
+//!
+//! ```text
+//! 00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|
+//! ```
+//!
+//! When a dump is turned back into bytes, only the hexadecimal column is
+//! read, so that bytes can be changed, added and removed there; the offsets
+//! and ASCII columns are ignored.
+
+use std::fmt::Write;
+
+/// The number of bytes shown on each line.
+const BYTES_PER_LINE: usize = 16;
+
+/// Returns `true` if `bytes`, the start of a file, look like binary data
+/// rather than text: they contain a NUL, or more than a few control
+/// characters which are not whitespace.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let is_control = |b: u8| (b < 0x20 && !b"\t\n\r\x0c\x1b".contains(&b)) || b == 0x7f;
+    bytes.contains(&0) || bytes.iter().filter(|&&b| is_control(b)).count() * 10 > bytes.len()
+}
+
+/// Returns the hex dump of `bytes`, with offsets starting at `offset`.
+pub fn dump(bytes: &[u8], offset: u64) -> String {
+    let mut text = String::with_capacity(bytes.len() / BYTES_PER_LINE * 80 + 80);
+    for (i, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(text, "{:08x} ", offset + (i * BYTES_PER_LINE) as u64);
+        for j in 0..BYTES_PER_LINE {
+            if j % 8 == 0 {
+                text.push(' ');
+            }
+            match line.get(j) {
+                Some(byte) => {
+                    let _ = write!(text, "{:02x} ", byte);
+                }
+                None => text.push_str("   "),
+            }
+        }
+        text.push_str(" |");
+        let ascii = line.iter().map(|&b| if b == b' ' || b.is_ascii_graphic() { b } else { b'.' });
+        text.extend(ascii.map(char::from));
+        text.push_str("|\n");
+    }
+    text
+}
+
+/// Returns the bytes shown in a hex dump, or `None` if it has something
+/// other than pairs of hexadecimal digits in its hexadecimal column.
+pub fn parse(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4);
+    for line in text.lines() {
+        let hex = line.split('|').next().unwrap_or_default();
+        let mut words = hex.split_whitespace().peekable();
+        // the offset is the only longer word
+        if words.peek().is_some_and(|word| word.len() > 2) {
+            words.next();
+        }
+        for word in words {
+            if word.len() != 2 {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(word, 16).ok()?);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_and_parse() {
+        let bytes = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0a |b";
+        let text = dump(bytes, 0);
+        assert_eq!(
+            text,
+            "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+             00000010  61 20 7c 62                                       |a |b|\n"
+        );
+        assert_eq!(parse(&text).unwrap(), bytes.to_vec());
+        assert_eq!(dump(b"z", 0x20), format!("00000020  7a{}|z|\n", " ".repeat(48)));
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_edits() {
+        let text = "00000000  61 62 ff  |ab.|\n63\n\n00000010  00 0A |ignored|\n";
+        assert_eq!(parse(text).unwrap(), b"ab\xffc\0\n".to_vec());
+        assert_eq!(parse("00000000  6 1"), None);
+        assert_eq!(parse("00000000  zz"), None);
+    }
+
+    #[test]
+    fn binary() {
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\0"));
+        assert!(is_binary(b"\x01\x02\x03abcdef"));
+        assert!(!is_binary(b"some\ttext\r\n"));
+        assert!(!is_binary("caf\u{e9}".as_bytes()));
+    }
+}
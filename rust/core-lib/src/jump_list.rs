@@ -0,0 +1,121 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The caret positions a view jumped from, to be able to jump back to them.
+
+use xi_rope::{Rope, RopeDelta, Transformer};
+
+/// The maximum number of positions kept in a jump list.
+const MAX_JUMPS: usize = 100;
+
+/// The positions of the caret before each large movement in a view.
+///
+/// This works like the history of a web browser: jumping back and then
+/// jumping somewhere else drops the positions that could be jumped forward to.
+#[derive(Debug, Default)]
+pub(crate) struct JumpList {
+    jumps: Vec<usize>,
+    /// The index of the position the caret jumped back or forward to, or the
+    /// length of `jumps` if the caret moved on since.
+    index: usize,
+}
+
+impl JumpList {
+    /// Records the position of the caret before a jump. It replaces the
+    /// previous position if both are on the same line.
+    pub(crate) fn record(&mut self, text: &Rope, offset: usize) {
+        self.jumps.truncate(self.index + 1);
+        if let Some(&last) = self.jumps.last() {
+            if text.line_of_offset(last) == text.line_of_offset(offset) {
+                self.jumps.pop();
+            }
+        }
+        self.jumps.push(offset);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    /// Returns the position to jump back to from the caret at `offset`.
+    pub(crate) fn back(&mut self, text: &Rope, offset: usize) -> Option<usize> {
+        if self.index == self.jumps.len() {
+            // remember the current position, to be able to jump forward to it
+            self.record(text, offset);
+            self.index -= 1;
+        }
+        if self.index == 0 {
+            return None;
+        }
+        self.index -= 1;
+        Some(self.jumps[self.index])
+    }
+
+    /// Returns the position to jump forward to, after jumping back.
+    pub(crate) fn forward(&mut self) -> Option<usize> {
+        if self.index + 1 < self.jumps.len() {
+            self.index += 1;
+            Some(self.jumps[self.index])
+        } else {
+            None
+        }
+    }
+
+    /// Updates the positions after an edit.
+    pub(crate) fn apply_delta(&mut self, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        for jump in &mut self.jumps {
+            *jump = transformer.transform(*jump, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xi_rope::DeltaBuilder;
+
+    #[test]
+    fn back_and_forward() {
+        let text = Rope::from("a\nb\nc\nd\n");
+        let mut jumps = JumpList::default();
+        assert_eq!(jumps.back(&text, 0), None);
+        jumps.record(&text, 0);
+        jumps.record(&text, 2);
+        assert_eq!(jumps.back(&text, 6), Some(2));
+        assert_eq!(jumps.back(&text, 2), Some(0));
+        assert_eq!(jumps.back(&text, 0), None);
+        assert_eq!(jumps.forward(), Some(2));
+        assert_eq!(jumps.forward(), Some(6));
+        assert_eq!(jumps.forward(), None);
+
+        // jumping elsewhere drops the positions after the current one
+        assert_eq!(jumps.back(&text, 6), Some(2));
+        jumps.record(&text, 3);
+        assert_eq!(jumps.forward(), None);
+        assert_eq!(jumps.back(&text, 4), Some(3));
+        assert_eq!(jumps.back(&text, 3), Some(0));
+    }
+
+    #[test]
+    fn track_edits() {
+        let text = Rope::from("a\nb\n");
+        let mut jumps = JumpList::default();
+        jumps.record(&text, 2);
+        let mut builder = DeltaBuilder::new(text.len());
+        builder.replace(0..0, "x\n".into());
+        jumps.apply_delta(&builder.build());
+        assert_eq!(jumps.back(&text, 0), Some(4));
+    }
+}
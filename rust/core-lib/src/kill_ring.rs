@@ -0,0 +1,129 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The kill ring, a local pasteboard keeping the most recently killed text.
+
+use std::collections::VecDeque;
+
+use serde_json::Value;
+
+use xi_rope::Rope;
+
+/// The maximum number of kills kept in the ring.
+const MAX_KILLS: usize = 32;
+
+/// The text removed by kill commands, most recent first.
+///
+/// Each kill keeps one piece of text per caret it was made with, so that it
+/// can be yanked back one piece per caret.
+#[derive(Debug, Default)]
+pub struct KillRing {
+    kills: VecDeque<Vec<Rope>>,
+    /// The kill inserted by the last yank, and the length of each inserted
+    /// piece.
+    yank: Option<(usize, Vec<usize>)>,
+}
+
+impl KillRing {
+    /// Adds a kill to the ring. If `append` is set, and the latest kill has
+    /// the same number of pieces, the pieces are appended to it instead.
+    pub(crate) fn kill(&mut self, pieces: Vec<Rope>, append: bool) {
+        match self.kills.front_mut() {
+            Some(latest) if append && latest.len() == pieces.len() => {
+                for (piece, killed) in latest.iter_mut().zip(pieces) {
+                    piece.edit(piece.len()..piece.len(), killed);
+                }
+            }
+            _ => {
+                self.kills.push_front(pieces);
+                self.kills.truncate(MAX_KILLS);
+            }
+        }
+    }
+
+    /// Returns the text to insert at each of `n_regions` selection regions
+    /// when yanking the kill at `index`. A kill is yanked one piece per
+    /// region when it has as many pieces as there are regions, and otherwise
+    /// as a whole, with its pieces on separate lines, in every region.
+    pub(crate) fn yank(&mut self, index: usize, n_regions: usize) -> Option<Vec<Rope>> {
+        let kill = self.kills.get(index)?;
+        let pieces = if kill.len() == n_regions {
+            kill.clone()
+        } else {
+            let joined = kill.iter().map(String::from).collect::<Vec<_>>().join("\n");
+            vec![Rope::from(joined); n_regions]
+        };
+        self.yank = Some((index, pieces.iter().map(Rope::len).collect()));
+        Some(pieces)
+    }
+
+    /// Returns the index of the kill inserted by the last yank, and the length
+    /// of each of its inserted pieces.
+    pub(crate) fn last_yank(&self) -> Option<(usize, &[usize])> {
+        self.yank.as_ref().map(|(index, lens)| (*index, lens.as_slice()))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.kills.len()
+    }
+
+    /// Describes the kills, most recent first, as lists of pieces.
+    pub(crate) fn to_json(&self) -> Value {
+        let kills = self
+            .kills
+            .iter()
+            .map(|kill| kill.iter().map(String::from).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        json!(kills)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(pieces: &[&str]) -> Vec<Rope> {
+        pieces.iter().map(|&piece| Rope::from(piece)).collect()
+    }
+
+    fn yank(ring: &mut KillRing, index: usize, n_regions: usize) -> Option<Vec<String>> {
+        ring.yank(index, n_regions).map(|pieces| pieces.iter().map(String::from).collect())
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let mut ring = KillRing::default();
+        assert_eq!(yank(&mut ring, 0, 1), None);
+        ring.kill(pieces(&["a"]), false);
+        ring.kill(pieces(&["b"]), true);
+        ring.kill(pieces(&["c", "d"]), true);
+        ring.kill(pieces(&["e", "f"]), true);
+        assert_eq!(ring.to_json(), json!([["ce", "df"], ["ab"]]));
+
+        assert_eq!(yank(&mut ring, 0, 2), Some(vec!["ce".into(), "df".into()]));
+        assert_eq!(yank(&mut ring, 0, 1), Some(vec!["ce\ndf".into()]));
+        assert_eq!(ring.last_yank(), Some((0, &[5][..])));
+        assert_eq!(yank(&mut ring, 1, 2), Some(vec!["ab".into(), "ab".into()]));
+    }
+
+    #[test]
+    fn bounded() {
+        let mut ring = KillRing::default();
+        for i in 0..MAX_KILLS + 1 {
+            ring.kill(pieces(&[&i.to_string()]), false);
+        }
+        assert_eq!(ring.len(), MAX_KILLS);
+        assert_eq!(yank(&mut ring, MAX_KILLS - 1, 1), Some(vec!["1".into()]));
+    }
+}
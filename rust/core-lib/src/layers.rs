@@ -57,6 +57,26 @@ impl Layers {
         &self.merged
     }
 
+    /// Returns `true` if any layer has provided scope information.
+    pub fn has_scopes(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// Returns `true` if, in any layer, the scope stack at `offset` contains
+    /// a scope matched by one of `prefixes`.
+    pub fn has_scope_at(&self, offset: usize, prefixes: &[Scope]) -> bool {
+        self.layers.values().any(|layer| layer.has_scope_at(offset, prefixes))
+    }
+
+    /// Returns the largest range around `offset` whose scope stacks, in some
+    /// layer, all contain a scope matched by one of `prefixes`.
+    pub fn scope_range_at(&self, offset: usize, prefixes: &[Scope]) -> Option<Interval> {
+        self.layers
+            .values()
+            .filter_map(|layer| layer.scope_range_at(offset, prefixes))
+            .max_by_key(|range| range.size())
+    }
+
     /// Adds the provided scopes to the layer's lookup table.
     pub fn add_scopes(
         &mut self,
@@ -179,6 +199,36 @@ impl ScopeLayer {
         }
     }
 
+    fn has_scope_at(&self, offset: usize, prefixes: &[Scope]) -> bool {
+        if offset >= self.scope_spans.len() {
+            return false;
+        }
+        let spans = self.scope_spans.subseq(Interval::new(offset, offset + 1));
+        let stack = spans.iter().next().and_then(|(_, id)| self.stack_lookup.get(*id as usize));
+        stack.is_some_and(|stack| {
+            stack.iter().any(|scope| prefixes.iter().any(|prefix| prefix.is_prefix_of(*scope)))
+        })
+    }
+
+    fn scope_range_at(&self, offset: usize, prefixes: &[Scope]) -> Option<Interval> {
+        let mut range: Option<Interval> = None;
+        for (iv, id) in self.scope_spans.iter() {
+            let stack = self.stack_lookup.get(*id as usize);
+            let matches = stack.is_some_and(|stack| {
+                stack.iter().any(|scope| prefixes.iter().any(|prefix| prefix.is_prefix_of(*scope)))
+            });
+            range = match range {
+                // adjacent matching spans make a single range
+                Some(range) if matches && range.end == iv.start => Some(range.union(iv)),
+                Some(range) if range.contains(offset) => return Some(range),
+                _ if iv.start > offset => return None,
+                _ if matches => Some(iv),
+                _ => None,
+            };
+        }
+        range.filter(|range| range.contains(offset))
+    }
+
     fn theme_changed(&mut self, style_map: &ThemeStyleMap) {
         // recompute styles with the new theme
         let cur_stacks = self.stack_lookup.clone();
@@ -39,6 +39,7 @@ extern crate serde;
 extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
+extern crate encoding_rs;
 extern crate memchr;
 #[cfg(feature = "notify")]
 extern crate notify;
@@ -53,6 +54,8 @@ extern crate xi_unicode;
 
 pub mod annotations;
 pub mod backspace;
+pub mod bookmarks;
+pub mod brackets;
 pub mod client;
 pub mod config;
 pub mod core;
@@ -60,21 +63,30 @@ pub mod edit_ops;
 pub mod edit_types;
 pub mod editor;
 pub mod event_context;
+pub mod expand_selection;
 pub mod file;
 pub mod find;
+pub mod folds;
+pub mod hex_dump;
 pub mod index_set;
+pub mod jump_list;
+pub mod kill_ring;
 pub mod layers;
 pub mod line_cache_shadow;
 pub mod line_ending;
 pub mod line_offset;
 pub mod linewrap;
+pub mod modal;
 pub mod movement;
 pub mod plugins;
 pub mod recorder;
+pub mod registers;
+pub mod revision_log;
 pub mod selection;
 pub mod styles;
 pub mod syntax;
 pub mod tabs;
+pub mod undo_tree;
 pub mod view;
 #[cfg(feature = "notify")]
 pub mod watcher;
@@ -20,10 +20,12 @@ use memchr::memchr2;
 use xi_rope::Rope;
 
 /// An enumeration of valid line endings
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LineEnding {
     CrLf, // DOS style, \r\n
     Lf,   // *nix style, \n
+    Cr,   // classic Mac style, \r
 }
 
 /// A struct representing a mixed line ending error.
@@ -31,40 +33,88 @@ pub enum LineEnding {
 pub struct MixedLineEndingError;
 
 impl LineEnding {
-    /// Breaks a rope down into chunks, and checks each chunk for line endings
+    /// Breaks a rope down into chunks, and checks every line ending of each
+    /// chunk, returning the line ending they all share.
     pub fn parse(rope: &Rope) -> Result<Option<Self>, MixedLineEndingError> {
-        let mut crlf = false;
-        let mut lf = false;
-
+        let mut scanner = Scanner::default();
         for chunk in rope.iter_chunks(..) {
-            match LineEnding::parse_chunk(chunk) {
-                Ok(Some(LineEnding::CrLf)) => crlf = true,
-                Ok(Some(LineEnding::Lf)) => lf = true,
-                Ok(None) => (),
-                Err(e) => return Err(e),
-            }
+            scanner.scan(chunk)?;
         }
+        scanner.finish()
+    }
 
-        match (crlf, lf) {
-            (true, false) => Ok(Some(LineEnding::CrLf)),
-            (false, true) => Ok(Some(LineEnding::Lf)),
-            (false, false) => Ok(None),
-            _ => Err(MixedLineEndingError),
+    /// Checks every line ending of a chunk, returning the one they all share.
+    pub fn parse_chunk(chunk: &str) -> Result<Option<Self>, MixedLineEndingError> {
+        let mut scanner = Scanner::default();
+        scanner.scan(chunk)?;
+        scanner.finish()
+    }
+
+    /// Returns the text of this line ending.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
         }
     }
+}
 
-    /// Checks a chunk for line endings, assuming \n or \r\n
-    pub fn parse_chunk(chunk: &str) -> Result<Option<Self>, MixedLineEndingError> {
+/// Collects the line endings of consecutive chunks of text.
+#[derive(Default)]
+struct Scanner {
+    found: Option<LineEnding>,
+    /// Whether the last chunk ended with a `\r`, which may start a `\r\n`.
+    pending_cr: bool,
+}
+
+impl Scanner {
+    fn scan(&mut self, chunk: &str) -> Result<(), MixedLineEndingError> {
         let bytes = chunk.as_bytes();
-        let newline = memchr2(b'\n', b'\r', bytes);
-        match newline {
-            Some(x) if bytes[x] == b'\r' && bytes.len() > x + 1 && bytes[x + 1] == b'\n' => {
-                Ok(Some(LineEnding::CrLf))
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let mut pos = 0;
+        if self.pending_cr {
+            self.pending_cr = false;
+            if bytes[0] == b'\n' {
+                self.add(LineEnding::CrLf)?;
+                pos = 1;
+            } else {
+                self.add(LineEnding::Cr)?;
+            }
+        }
+        while let Some(i) = memchr2(b'\n', b'\r', &bytes[pos..]) {
+            let i = pos + i;
+            pos = i + 1;
+            match bytes.get(i + 1) {
+                _ if bytes[i] == b'\n' => self.add(LineEnding::Lf)?,
+                Some(b'\n') => {
+                    self.add(LineEnding::CrLf)?;
+                    pos += 1;
+                }
+                Some(_) => self.add(LineEnding::Cr)?,
+                None => self.pending_cr = true,
             }
-            Some(x) if bytes[x] == b'\n' => Ok(Some(LineEnding::Lf)),
-            Some(_) => Err(MixedLineEndingError),
-            _ => Ok(None),
         }
+        Ok(())
+    }
+
+    fn add(&mut self, line_ending: LineEnding) -> Result<(), MixedLineEndingError> {
+        match self.found {
+            Some(found) if found != line_ending => Err(MixedLineEndingError),
+            _ => {
+                self.found = Some(line_ending);
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(mut self) -> Result<Option<LineEnding>, MixedLineEndingError> {
+        if self.pending_cr {
+            self.add(LineEnding::Cr)?;
+        }
+        Ok(self.found)
     }
 }
 
@@ -87,8 +137,23 @@ mod tests {
     }
 
     #[test]
-    fn legacy_mac_errors() {
-        assert!(LineEnding::parse_chunk("\r").is_err());
+    fn legacy_mac() {
+        let result = LineEnding::parse_chunk("\r");
+        assert_eq!(result.unwrap(), Some(LineEnding::Cr));
+    }
+
+    #[test]
+    fn mixed() {
+        assert!(LineEnding::parse_chunk("a\nb\r\n").is_err());
+        assert!(LineEnding::parse_chunk("a\rb\n").is_err());
+    }
+
+    #[test]
+    fn crlf_across_chunks() {
+        let mut scanner = Scanner::default();
+        scanner.scan("a\r").unwrap();
+        scanner.scan("\nb\r\n").unwrap();
+        assert_eq!(scanner.finish().unwrap(), Some(LineEnding::CrLf));
     }
 
     #[test]
@@ -24,6 +24,7 @@ use xi_trace::trace_block;
 use xi_unicode::LineBreakLeafIter;
 
 use crate::client::Client;
+use crate::folds::Folds;
 use crate::styles::{Style, N_RESERVED_STYLES};
 use crate::width_cache::{CodepointMono, Token, WidthCache, WidthMeasure};
 
@@ -68,6 +69,8 @@ pub(crate) struct Lines {
     wrap: WrapWidth,
     /// Aka the 'frontier'; ranges of lines that still need to be wrapped.
     work: Vec<Task>,
+    /// Regions hidden by code folding.
+    folds: Folds,
 }
 
 pub(crate) struct VisualLine {
@@ -159,7 +162,69 @@ impl Lines {
         self.work.iter().any(|t| !t.intersect(iv).is_empty())
     }
 
+    pub(crate) fn folds(&self) -> &Folds {
+        &self.folds
+    }
+
+    pub(crate) fn folds_mut(&mut self) -> &mut Folds {
+        &mut self.folds
+    }
+
+    /// Returns the fold hiding `offset`, if any. The end of a text without
+    /// a trailing newline is hidden if the last line is folded.
+    pub(crate) fn fold_hiding(&self, text: &Rope, offset: usize) -> Option<Interval> {
+        self.folds.containing(offset).or_else(|| {
+            self.folds
+                .iter()
+                .find(|fold| fold.end == offset && offset == text.len() && !ends_with_newline(text))
+                .cloned()
+        })
+    }
+
+    /// Moves `offset` out of any folded region: to the first visible position
+    /// after the fold if `forward` is `true` and such a position exists, and
+    /// otherwise to the end of the fold's header line.
+    pub(crate) fn snap_to_visible(&self, text: &Rope, offset: usize, forward: bool) -> usize {
+        match self.fold_hiding(text, offset) {
+            Some(fold) if forward && self.fold_hiding(text, fold.end).is_none() => fold.end,
+            Some(fold) => fold.start - 1,
+            None => offset,
+        }
+    }
+
+    /// The number of (unfolded) visual lines hidden by `fold`.
+    fn hidden_line_count(&self, text: &Rope, fold: Interval) -> usize {
+        self.unfolded_visual_line_of_offset(text, fold.end - 1)
+            - self.unfolded_visual_line_of_offset(text, fold.start)
+            + 1
+    }
+
+    /// The number of logical lines hidden by the fold that begins at `offset`,
+    /// if there is one.
+    pub(crate) fn folded_lines_at(&self, text: &Rope, offset: usize) -> Option<usize> {
+        self.folds
+            .starting_at(offset)
+            .map(|fold| text.line_of_offset(fold.end - 1) - text.line_of_offset(fold.start) + 1)
+    }
+
     pub(crate) fn visual_line_of_offset(&self, text: &Rope, offset: usize) -> usize {
+        if self.folds.is_empty() {
+            return self.unfolded_visual_line_of_offset(text, offset);
+        }
+        let offset = match self.fold_hiding(text, offset) {
+            Some(fold) => fold.start - 1,
+            None => offset,
+        };
+        let hidden: usize = self
+            .folds
+            .iter()
+            .take_while(|fold| fold.end <= offset)
+            .map(|fold| self.hidden_line_count(text, *fold))
+            .sum();
+        self.unfolded_visual_line_of_offset(text, offset) - hidden
+    }
+
+    fn unfolded_visual_line_of_offset(&self, text: &Rope, offset: usize) -> usize {
         let mut line = text.line_of_offset(offset);
         if self.wrap != WrapWidth::None {
             line += self.breaks.count::<BreaksMetric>(offset)
@@ -169,6 +234,18 @@ impl Lines {
 
     /// Returns the byte offset corresponding to the line `line`.
     pub(crate) fn offset_of_visual_line(&self, text: &Rope, line: usize) -> usize {
+        let mut hidden = 0;
+        for fold in self.folds.iter() {
+            let fold_line = self.unfolded_visual_line_of_offset(text, fold.start) - hidden;
+            if line < fold_line {
+                break;
+            }
+            hidden += self.hidden_line_count(text, *fold);
+        }
+        self.unfolded_offset_of_visual_line(text, line + hidden)
+    }
+
+    fn unfolded_offset_of_visual_line(&self, text: &Rope, line: usize) -> usize {
         match self.wrap {
             WrapWidth::None => {
                 // sanitize input
@@ -190,10 +267,14 @@ impl Lines {
         start_line: usize,
     ) -> impl Iterator<Item = VisualLine> + 'a {
         let mut cursor = MergedBreaks::new(text, &self.breaks);
-        let offset = cursor.offset_of_line(start_line);
+        let offset = if self.folds.is_empty() {
+            cursor.offset_of_line(start_line)
+        } else {
+            self.offset_of_visual_line(text, start_line)
+        };
         let logical_line = text.line_of_offset(offset) + 1;
         cursor.set_offset(offset);
-        VisualLines { offset, cursor, len: text.len(), logical_line, eof: false }
+        VisualLines { offset, cursor, text, folds: &self.folds, logical_line, eof: false }
     }
 
     /// Returns the next task, prioritizing the currently visible region.
@@ -281,8 +362,8 @@ impl Lines {
         }
     }
 
-    /// Updates breaks after an edit. Returns `InvalLines`, for minimal invalidation,
-    /// when possible.
+    /// Updates breaks and folds after an edit. Returns `InvalLines`, for minimal
+    /// invalidation, when possible.
     pub(crate) fn after_edit(
         &mut self,
         text: &Rope,
@@ -291,6 +372,26 @@ impl Lines {
         width_cache: &mut WidthCache,
         client: &Client,
         visible_lines: Range<usize>,
+    ) -> Option<InvalLines> {
+        // invalidation is computed in unfolded lines, so it can't be used with folds.
+        let had_folds = !self.folds.is_empty();
+        self.folds.apply_delta(delta);
+        let inval = self.update_breaks(text, old_text, delta, width_cache, client, visible_lines);
+        if had_folds {
+            None
+        } else {
+            inval
+        }
+    }
+
+    fn update_breaks(
+        &mut self,
+        text: &Rope,
+        old_text: &Rope,
+        delta: &RopeDelta,
+        width_cache: &mut WidthCache,
+        client: &Client,
+        visible_lines: Range<usize>,
     ) -> Option<InvalLines> {
         let (iv, newlen) = delta.summary();
 
@@ -358,8 +459,8 @@ impl Lines {
         // 'line' is a poor unit here; could do some fancy Duration thing?
         const MAX_LINES_PER_BATCH: usize = 500;
 
+        let visible_off = self.offset_of_visual_line(text, visible_lines.start);
         let mut cursor = MergedBreaks::new(text, &self.breaks);
-        let visible_off = cursor.offset_of_line(visible_lines.start);
         let logical_off = text.offset_of_line(text.line_of_offset(visible_off));
 
         // task.start is a hard break; task.end is a boundary or EOF.
@@ -431,8 +532,7 @@ impl Lines {
     }
 
     pub fn logical_line_range(&self, text: &Rope, line: usize) -> (usize, usize) {
-        let mut cursor = MergedBreaks::new(text, &self.breaks);
-        let offset = cursor.offset_of_line(line);
+        let offset = self.offset_of_visual_line(text, line);
         let logical_line = text.line_of_offset(offset);
         let start_logical_line_offset = text.offset_of_line(logical_line);
         let end_logical_line_offset = text.offset_of_line(logical_line + 1);
@@ -604,9 +704,10 @@ impl<'a> LineBreakCursor<'a> {
 struct VisualLines<'a> {
     cursor: MergedBreaks<'a>,
     offset: usize,
+    text: &'a Rope,
+    folds: &'a Folds,
     /// The current logical line number.
     logical_line: usize,
-    len: usize,
     eof: bool,
 }
 
@@ -620,7 +721,7 @@ impl<'a> Iterator for VisualLines<'a> {
             None if self.eof => return None,
             _else => {
                 self.eof = true;
-                self.len
+                self.text.len()
             }
         };
         let result = VisualLine::new(self.offset..next_end_bound, line_num);
@@ -628,10 +729,23 @@ impl<'a> Iterator for VisualLines<'a> {
             self.logical_line += 1;
         }
         self.offset = next_end_bound;
+        if let Some(fold) = self.folds.starting_at(next_end_bound) {
+            // skip over the hidden lines
+            self.offset = fold.end;
+            self.cursor.set_offset(fold.end);
+            self.logical_line = self.text.line_of_offset(fold.end) + 1;
+            if fold.end == self.text.len() && !ends_with_newline(self.text) {
+                self.eof = true;
+            }
+        }
         Some(result)
     }
 }
 
+fn ends_with_newline(text: &Rope) -> bool {
+    !text.is_empty() && text.byte_at(text.len() - 1) == b'\n'
+}
+
 /// A cursor over both hard and soft breaks. Hard breaks are retrieved from
 /// the rope; the soft breaks are stored independently; this interleaves them.
 ///
@@ -1139,6 +1253,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn folded_lines() {
+        let text: Rope = "aaaa\nbb bb cc\ncc dddd eeee ff\nff gggg".into();
+        let mut lines = make_lines(&text, 2.);
+        lines.folds_mut().add(Interval::new(5, 14));
+
+        let r: Vec<_> = lines.iter_lines(&text, 0).map(|l| text.slice_to_cow(l.interval)).collect();
+        assert_eq!(r, vec!["aaaa\n", "cc ", "dddd ", "eeee ", "ff\n", "ff ", "gggg"]);
+        let nums: Vec<_> = lines.iter_lines(&text, 0).map(|l| l.line_num).collect();
+        assert_eq!(nums, vec![Some(1), Some(3), None, None, None, Some(4), None]);
+        let r: Vec<_> =
+            lines.iter_lines(&text, 1).take(2).map(|l| text.slice_to_cow(l.interval)).collect();
+        assert_eq!(r, vec!["cc ", "dddd "]);
+
+        assert_eq!(lines.visual_line_of_offset(&text, 4), 0);
+        assert_eq!(lines.visual_line_of_offset(&text, 7), 0);
+        assert_eq!(lines.visual_line_of_offset(&text, 14), 1);
+        assert_eq!(lines.visual_line_of_offset(&text, 33), 6);
+        assert_eq!(lines.offset_of_visual_line(&text, 1), 14);
+        assert_eq!(lines.offset_of_visual_line(&text, 5), 30);
+        assert_eq!(lines.snap_to_visible(&text, 5, true), 14);
+        assert_eq!(lines.snap_to_visible(&text, 13, false), 4);
+        assert_eq!(lines.folded_lines_at(&text, 5), Some(1));
+
+        // folding the last line, without a trailing newline
+        lines.folds_mut().add(Interval::new(30, text.len()));
+        let r: Vec<_> = lines.iter_lines(&text, 0).map(|l| text.slice_to_cow(l.interval)).collect();
+        assert_eq!(r, vec!["aaaa\n", "cc ", "dddd ", "eeee ", "ff\n"]);
+        assert_eq!(lines.visual_line_of_offset(&text, text.len()), 4);
+        assert_eq!(lines.snap_to_visible(&text, text.len(), true), 29);
+    }
+
     fn make_ranges(ivs: &[Interval]) -> Vec<Range<usize>> {
         ivs.iter().map(|iv| iv.start..iv.end).collect()
     }
@@ -0,0 +1,638 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A Vim-style modal editing layer, interpreting the keys forwarded by the
+//! frontend into the events used by the rest of core.
+
+use std::mem;
+
+use crate::edit_types::{BufferEvent, EventDomain, ViewEvent};
+use crate::movement::Movement;
+use crate::rpc::SelectionModifier;
+
+/// The modes of the modal editing layer.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+    OperatorPending,
+}
+
+/// The operators, applied to the text covered by a motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    Lowercase,
+    Uppercase,
+}
+
+impl Operator {
+    fn from_command(command: &str) -> Option<Operator> {
+        match command {
+            "d" => Some(Operator::Delete),
+            "c" => Some(Operator::Change),
+            "y" => Some(Operator::Yank),
+            ">" => Some(Operator::Indent),
+            "<" => Some(Operator::Outdent),
+            "gu" => Some(Operator::Lowercase),
+            "gU" => Some(Operator::Uppercase),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `command`, typed after this operator, applies it to
+    /// whole lines, as in `dd` or `gUU`.
+    fn is_linewise_command(self, command: &str) -> bool {
+        match self {
+            Operator::Lowercase => command == "u",
+            Operator::Uppercase => command == "U",
+            _ => Operator::from_command(command) == Some(self),
+        }
+    }
+}
+
+/// Where a motion moves the caret.
+enum Motion {
+    Move(Movement),
+    /// A (zero-based) line number.
+    Line(usize),
+}
+
+/// A key sent by the frontend, in Vim notation.
+enum Key {
+    Char(char),
+    Ctrl(char),
+    Escape,
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Any other text, such as text composed with an input method.
+    Text(String),
+}
+
+impl Key {
+    fn parse(key: &str) -> Key {
+        match key {
+            "<Esc>" => Key::Escape,
+            "<CR>" | "<Enter>" => Key::Enter,
+            "<BS>" => Key::Backspace,
+            "<Del>" => Key::Delete,
+            "<Tab>" => Key::Tab,
+            "<Left>" => Key::Left,
+            "<Right>" => Key::Right,
+            "<Up>" => Key::Up,
+            "<Down>" => Key::Down,
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Key::Char(c),
+                    _ if key.starts_with("<C-") && key.ends_with('>') && key.len() == 5 => {
+                        Key::Ctrl(key.as_bytes()[3] as char)
+                    }
+                    _ => Key::Text(key.to_owned()),
+                }
+            }
+        }
+    }
+
+    /// Returns the name of the command bound to this key outside of insert
+    /// mode, where some keys are synonyms of letters.
+    fn command(&self) -> Option<String> {
+        let command = match self {
+            Key::Char(c) => return Some(c.to_string()),
+            Key::Ctrl(c) => return Some(format!("<C-{}>", c)),
+            Key::Left | Key::Backspace => "h",
+            Key::Right => "l",
+            Key::Up => "k",
+            Key::Down | Key::Enter => "j",
+            Key::Delete => "x",
+            Key::Escape | Key::Tab | Key::Text(_) => return None,
+        };
+        Some(command.to_owned())
+    }
+}
+
+/// The state of the modal editing layer of a view.
+///
+/// Keys are interpreted into events, which are dispatched as if they had been
+/// sent by the frontend. Commands are made of an optional count, an optional
+/// operator, and a motion or another command, as in `3dw`.
+#[derive(Debug)]
+pub(crate) struct Modal {
+    mode: Mode,
+    /// The count typed so far.
+    count: Option<usize>,
+    /// The operator waiting for a motion, with the count typed before it.
+    operator: Option<(Operator, usize)>,
+    /// A key waiting for the rest of its command, such as the `g` of `gg`.
+    prefix: Option<char>,
+    /// The events of the change being made in insert mode.
+    change: Vec<EventDomain>,
+    /// The events of the last change, repeated by `.`.
+    last_change: Vec<EventDomain>,
+    /// Whether the text last deleted or yanked was made of whole lines, in
+    /// which case it is put on separate lines.
+    linewise: bool,
+}
+
+impl Default for Modal {
+    fn default() -> Modal {
+        Modal {
+            mode: Mode::Normal,
+            count: None,
+            operator: None,
+            prefix: None,
+            change: Vec::new(),
+            last_change: Vec::new(),
+            linewise: false,
+        }
+    }
+}
+
+impl Modal {
+    pub(crate) fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Interprets a key, returning the events it stands for.
+    pub(crate) fn handle_key(&mut self, key: &str) -> Vec<EventDomain> {
+        let key = Key::parse(key);
+        if self.mode == Mode::Insert {
+            return self.insert_key(key);
+        }
+        if let Key::Escape = key {
+            return self.cancel();
+        }
+        if self.prefix == Some('r') {
+            self.prefix = None;
+            return match key {
+                Key::Char(c) if self.mode == Mode::Normal => self.replace(c),
+                _ => self.cancel(),
+            };
+        }
+        let command = match (self.prefix.take(), key.command()) {
+            (Some(prefix), Some(command)) => format!("{}{}", prefix, command),
+            (None, Some(command)) => command,
+            (_, None) => return self.cancel(),
+        };
+        match command.as_str() {
+            "g" | "r" => {
+                self.prefix = command.chars().next();
+                return Vec::new();
+            }
+            "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
+                return self.push_digit(&command)
+            }
+            "0" if self.count.is_some() => return self.push_digit(&command),
+            _ => (),
+        }
+
+        let count = self.count.take();
+        let events = match self.mode {
+            Mode::Insert => unreachable!(),
+            Mode::Normal => self.normal_command(&command, count),
+            Mode::OperatorPending => self.operator_command(&command, count),
+            Mode::Visual | Mode::VisualLine => self.visual_command(&command, count),
+        };
+        if self.mode == Mode::Insert {
+            self.change = events.clone();
+        } else if self.mode == Mode::Normal && command != "." && events.iter().any(is_change) {
+            self.last_change = events.clone();
+        }
+        events
+    }
+
+    fn insert_key(&mut self, key: Key) -> Vec<EventDomain> {
+        let event: EventDomain = match key {
+            Key::Escape => {
+                self.mode = Mode::Normal;
+                self.last_change = mem::take(&mut self.change);
+                return Vec::new();
+            }
+            Key::Char(c) => BufferEvent::Insert(c.to_string()).into(),
+            Key::Text(text) => BufferEvent::Insert(text).into(),
+            Key::Enter => BufferEvent::InsertNewline.into(),
+            Key::Tab => BufferEvent::InsertTab.into(),
+            Key::Backspace => BufferEvent::Backspace.into(),
+            Key::Delete => BufferEvent::Delete { movement: Movement::Right, kill: false }.into(),
+            Key::Left => ViewEvent::Move(Movement::Left).into(),
+            Key::Right => ViewEvent::Move(Movement::Right).into(),
+            Key::Up => ViewEvent::Move(Movement::Up).into(),
+            Key::Down => ViewEvent::Move(Movement::Down).into(),
+            Key::Ctrl(_) => return Vec::new(),
+        };
+        self.change.push(event.clone());
+        vec![event]
+    }
+
+    fn push_digit(&mut self, digit: &str) -> Vec<EventDomain> {
+        let digit = digit.parse::<usize>().unwrap();
+        self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        Vec::new()
+    }
+
+    /// Drops the pending command, and leaves visual modes.
+    fn cancel(&mut self) -> Vec<EventDomain> {
+        self.count = None;
+        self.operator = None;
+        self.prefix = None;
+        match self.mode {
+            Mode::Visual | Mode::VisualLine => {
+                self.mode = Mode::Normal;
+                vec![ViewEvent::Move(Movement::Left).into()]
+            }
+            _ => {
+                self.mode = Mode::Normal;
+                Vec::new()
+            }
+        }
+    }
+
+    fn normal_command(&mut self, command: &str, count: Option<usize>) -> Vec<EventDomain> {
+        if let Some(motion) = motion(command, count) {
+            return match motion {
+                Motion::Move(movement) => moves(movement, count, false),
+                Motion::Line(line) => vec![ViewEvent::GotoLine { line: line as u64 }.into()],
+            };
+        }
+        if let Some(operator) = Operator::from_command(command) {
+            self.operator = Some((operator, count.unwrap_or(1)));
+            self.mode = Mode::OperatorPending;
+            return Vec::new();
+        }
+        let n = count.unwrap_or(1);
+        match command {
+            "i" => self.insert(Vec::new()),
+            "a" => self.insert(vec![ViewEvent::Move(Movement::Right).into()]),
+            "I" => self.insert(vec![ViewEvent::Move(Movement::LeftOfLine).into()]),
+            "A" => self.insert(vec![ViewEvent::Move(Movement::EndOfParagraph).into()]),
+            "o" => self.insert(vec![
+                ViewEvent::Move(Movement::EndOfParagraph).into(),
+                BufferEvent::InsertNewline.into(),
+            ]),
+            "O" => self.insert(vec![
+                ViewEvent::Move(Movement::LeftOfLine).into(),
+                BufferEvent::InsertNewline.into(),
+                ViewEvent::Move(Movement::Up).into(),
+            ]),
+            "x" => self.apply(Operator::Delete, moves(Movement::Right, count, true), false),
+            "X" => self.apply(Operator::Delete, moves(Movement::Left, count, true), false),
+            "s" => self.apply(Operator::Change, moves(Movement::Right, count, true), false),
+            "D" => self.apply(Operator::Delete, moves(Movement::EndOfParagraph, None, true), false),
+            "C" => self.apply(Operator::Change, moves(Movement::EndOfParagraph, None, true), false),
+            "S" => self.apply_lines(Operator::Change, n),
+            "Y" => self.apply_lines(Operator::Yank, n),
+            // lines are pasted below the caret's line, which may be the last one
+            "p" if self.linewise => {
+                let mut events = repeat(BufferEvent::YankLines, n);
+                events.push(ViewEvent::Move(Movement::Down).into());
+                events.push(ViewEvent::Move(Movement::LeftOfLine).into());
+                events
+            }
+            "p" | "P" => {
+                let mut events: Vec<EventDomain> = match (command, self.linewise) {
+                    ("p", _) => vec![ViewEvent::Move(Movement::Right).into()],
+                    (_, true) => vec![ViewEvent::Move(Movement::LeftOfLine).into()],
+                    (_, false) => Vec::new(),
+                };
+                events.extend(repeat(BufferEvent::Yank, n));
+                events
+            }
+            "J" => repeat(BufferEvent::JoinLines, n),
+            "u" => repeat(BufferEvent::Undo, n),
+            "<C-r>" => repeat(BufferEvent::Redo, n),
+            "n" => repeat(
+                ViewEvent::FindNext {
+                    wrap_around: true,
+                    allow_same: false,
+                    modify_selection: SelectionModifier::Set,
+                },
+                n,
+            ),
+            "N" => repeat(
+                ViewEvent::FindPrevious {
+                    wrap_around: true,
+                    allow_same: false,
+                    modify_selection: SelectionModifier::Set,
+                },
+                n,
+            ),
+            "v" => {
+                self.mode = Mode::Visual;
+                Vec::new()
+            }
+            "V" => {
+                self.mode = Mode::VisualLine;
+                select_lines(1, true)
+            }
+            "." => (0..n).flat_map(|_| self.last_change.clone()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn operator_command(&mut self, command: &str, count: Option<usize>) -> Vec<EventDomain> {
+        let (operator, op_count) = self.operator.take().unwrap();
+        self.mode = Mode::Normal;
+        let n = op_count * count.unwrap_or(1);
+        if operator.is_linewise_command(command) {
+            return self.apply_lines(operator, n);
+        }
+        match motion(command, count) {
+            // line motions apply to whole lines
+            Some(Motion::Line(line)) => self.apply_to_line(operator, line),
+            Some(Motion::Move(Movement::StartOfDocument)) => self.apply_to_line(operator, 0),
+            Some(Motion::Move(Movement::EndOfDocument)) => self.apply_to_line(operator, usize::MAX),
+            // as in Vim, `cw` changes up to the end of the word
+            Some(Motion::Move(Movement::RightWordStart)) if operator == Operator::Change => {
+                self.apply(operator, moves(Movement::RightWord, Some(n), true), false)
+            }
+            Some(Motion::Move(movement)) => {
+                self.apply(operator, moves(movement, Some(n), true), false)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn visual_command(&mut self, command: &str, count: Option<usize>) -> Vec<EventDomain> {
+        let linewise = self.mode == Mode::VisualLine;
+        if let Some(motion) = motion(command, count) {
+            return match motion {
+                // only vertical motions keep whole lines selected
+                Motion::Move(movement) if !linewise || is_vertical(movement) => {
+                    moves(movement, count, true)
+                }
+                _ => Vec::new(),
+            };
+        }
+        let operator = match command {
+            "d" | "x" => Operator::Delete,
+            "c" | "s" => Operator::Change,
+            "y" => Operator::Yank,
+            ">" => Operator::Indent,
+            "<" => Operator::Outdent,
+            "u" => Operator::Lowercase,
+            "U" => Operator::Uppercase,
+            "J" => {
+                self.mode = Mode::Normal;
+                return vec![BufferEvent::JoinLines.into()];
+            }
+            "v" if linewise => {
+                self.mode = Mode::Visual;
+                return Vec::new();
+            }
+            "V" if !linewise => {
+                self.mode = Mode::VisualLine;
+                let mut events = vec![ViewEvent::Move(Movement::Left).into()];
+                events.extend(select_lines(1, true));
+                return events;
+            }
+            "v" | "V" => return self.cancel(),
+            _ => return Vec::new(),
+        };
+        self.mode = Mode::Normal;
+        self.apply(operator, Vec::new(), linewise)
+    }
+
+    /// Enters insert mode, after the given events.
+    fn insert(&mut self, events: Vec<EventDomain>) -> Vec<EventDomain> {
+        self.mode = Mode::Insert;
+        events
+    }
+
+    /// Applies an operator to `n` whole lines, starting with the caret's.
+    fn apply_lines(&mut self, operator: Operator, n: usize) -> Vec<EventDomain> {
+        // changing lines keeps an empty line to insert into
+        let whole_lines = operator != Operator::Change;
+        self.apply(operator, select_lines(n, whole_lines), whole_lines)
+    }
+
+    /// Applies an operator to the whole lines from the caret's to `line`.
+    fn apply_to_line(&mut self, operator: Operator, line: usize) -> Vec<EventDomain> {
+        let whole_lines = operator != Operator::Change;
+        let select = ViewEvent::SelectLinesTo { line: line as u64, newline: whole_lines };
+        self.apply(operator, vec![select.into()], whole_lines)
+    }
+
+    /// Applies an operator to the selection made by the `select` events.
+    fn apply(
+        &mut self,
+        operator: Operator,
+        select: Vec<EventDomain>,
+        linewise: bool,
+    ) -> Vec<EventDomain> {
+        let mut events = select;
+        let operation: BufferEvent = match operator {
+            Operator::Delete | Operator::Change => BufferEvent::KillSelection,
+            Operator::Yank => BufferEvent::CopyToKillRing,
+            Operator::Indent => BufferEvent::Indent,
+            Operator::Outdent => BufferEvent::Outdent,
+            Operator::Lowercase => BufferEvent::Lowercase,
+            Operator::Uppercase => BufferEvent::Uppercase,
+        };
+        events.push(operation.into());
+        match operator {
+            Operator::Delete => self.linewise = linewise,
+            Operator::Change => {
+                self.linewise = linewise;
+                self.mode = Mode::Insert;
+            }
+            _ => {
+                if operator == Operator::Yank {
+                    self.linewise = linewise;
+                }
+                // leave the caret at the start of the text
+                events.push(ViewEvent::Move(Movement::Left).into());
+            }
+        }
+        events
+    }
+
+    /// Replaces the `count` characters after the caret with the typed one.
+    fn replace(&mut self, c: char) -> Vec<EventDomain> {
+        let count = self.count.take();
+        let mut events = moves(Movement::Right, count, true);
+        events.push(BufferEvent::Insert(c.to_string().repeat(count.unwrap_or(1))).into());
+        events.push(ViewEvent::Move(Movement::Left).into());
+        self.last_change = events.clone();
+        events
+    }
+}
+
+fn motion(command: &str, count: Option<usize>) -> Option<Motion> {
+    let movement = match command {
+        "h" => Movement::Left,
+        "l" | " " => Movement::Right,
+        "j" => Movement::Down,
+        "k" => Movement::Up,
+        "w" => Movement::RightWordStart,
+        "e" => Movement::RightWord,
+        "b" => Movement::LeftWord,
+        "0" => Movement::LeftOfLine,
+        "$" => Movement::EndOfParagraph,
+        "%" => Movement::MatchingBracket,
+        "<C-f>" => Movement::DownPage,
+        "<C-b>" => Movement::UpPage,
+        "gg" | "G" => {
+            return Some(match (command, count) {
+                (_, Some(line)) => Motion::Line(line.saturating_sub(1)),
+                ("gg", None) => Motion::Move(Movement::StartOfDocument),
+                _ => Motion::Move(Movement::EndOfDocument),
+            });
+        }
+        _ => return None,
+    };
+    Some(Motion::Move(movement))
+}
+
+fn is_vertical(movement: Movement) -> bool {
+    matches!(
+        movement,
+        Movement::Up
+            | Movement::Down
+            | Movement::UpPage
+            | Movement::DownPage
+            | Movement::StartOfDocument
+            | Movement::EndOfDocument
+    )
+}
+
+/// Returns `true` if an event modifies the buffer.
+pub(crate) fn is_change(event: &EventDomain) -> bool {
+    match event {
+        EventDomain::Buffer(event) => {
+            !matches!(event, BufferEvent::Undo | BufferEvent::Redo | BufferEvent::CopyToKillRing)
+        }
+        _ => false,
+    }
+}
+
+impl BufferEvent {
+    /// Returns `true` if the event may change the text of the buffer, other
+    /// than by moving through its undo history.
+    pub(crate) fn is_mutating(&self) -> bool {
+        use self::BufferEvent::*;
+        !matches!(
+            self,
+            Undo | Redo
+                | JumpToUndoNode(_)
+                | SetBookmark(_)
+                | ClearBookmark
+                | ToggleBookmark
+                | ClearAllBookmarks
+                | NextBookmark
+                | PreviousBookmark
+                | GotoBookmark(_)
+                | CopyToKillRing
+        )
+    }
+}
+
+fn repeat<E: Into<EventDomain> + Clone>(event: E, n: usize) -> Vec<EventDomain> {
+    (0..n).map(|_| event.clone().into()).collect()
+}
+
+fn moves(movement: Movement, count: Option<usize>, modify: bool) -> Vec<EventDomain> {
+    let event =
+        if modify { ViewEvent::ModifySelection(movement) } else { ViewEvent::Move(movement) };
+    repeat(event, count.unwrap_or(1))
+}
+
+/// Selects `n` lines, starting with the caret's, with their final newline if
+/// `newline` is set.
+fn select_lines(n: usize, newline: bool) -> Vec<EventDomain> {
+    let mut events: Vec<EventDomain> = vec![ViewEvent::Move(Movement::LeftOfLine).into()];
+    if newline {
+        events.extend(moves(Movement::Down, Some(n), true));
+    } else {
+        events.extend(moves(Movement::Down, Some(n - 1), true));
+        events.push(ViewEvent::ModifySelection(Movement::EndOfParagraph).into());
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(modal: &mut Modal, keys: &str) -> Vec<EventDomain> {
+        keys.chars().flat_map(|c| modal.handle_key(&c.to_string())).collect()
+    }
+
+    #[test]
+    fn counts_and_motions() {
+        let mut modal = Modal::default();
+        let left = ViewEvent::Move(Movement::Left);
+        assert_eq!(keys(&mut modal, "3h"), repeat(left, 3));
+        assert_eq!(keys(&mut modal, "10G"), vec![ViewEvent::GotoLine { line: 9 }.into()]);
+        assert_eq!(keys(&mut modal, "gg"), vec![ViewEvent::Move(Movement::StartOfDocument).into()]);
+        assert_eq!(modal.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn operators() {
+        let mut modal = Modal::default();
+        assert!(keys(&mut modal, "2d").is_empty());
+        assert_eq!(modal.mode(), Mode::OperatorPending);
+        let mut expected = moves(Movement::RightWordStart, Some(6), true);
+        expected.push(BufferEvent::KillSelection.into());
+        assert_eq!(keys(&mut modal, "3w"), expected);
+        assert_eq!(modal.mode(), Mode::Normal);
+
+        assert_eq!(keys(&mut modal, "."), expected);
+        assert!(keys(&mut modal, "d").is_empty());
+        assert_eq!(modal.handle_key("<Esc>"), vec![]);
+        assert_eq!(modal.mode(), Mode::Normal);
+
+        let mut expected = select_lines(2, true);
+        expected.push(BufferEvent::Uppercase.into());
+        expected.push(ViewEvent::Move(Movement::Left).into());
+        assert_eq!(keys(&mut modal, "gU2U"), expected);
+
+        let expected: Vec<EventDomain> = vec![
+            ViewEvent::SelectLinesTo { line: 2, newline: true }.into(),
+            BufferEvent::KillSelection.into(),
+        ];
+        assert_eq!(keys(&mut modal, "d3G"), expected);
+    }
+
+    #[test]
+    fn insert_and_repeat() {
+        let mut modal = Modal::default();
+        assert!(keys(&mut modal, "i").is_empty());
+        assert_eq!(modal.mode(), Mode::Insert);
+        keys(&mut modal, "ab");
+        modal.handle_key("<BS>");
+        modal.handle_key("<Esc>");
+        assert_eq!(modal.mode(), Mode::Normal);
+        let expected: Vec<EventDomain> = vec![
+            BufferEvent::Insert("a".into()).into(),
+            BufferEvent::Insert("b".into()).into(),
+            BufferEvent::Backspace.into(),
+        ];
+        assert_eq!(keys(&mut modal, "."), expected);
+        assert_eq!(keys(&mut modal, "2.").len(), 6);
+    }
+}
@@ -16,9 +16,10 @@
 
 use std::cmp::max;
 
+use crate::brackets::BracketMatcher;
 use crate::line_offset::LineOffset;
 use crate::selection::{HorizPos, SelRegion, Selection};
-use crate::word_boundaries::WordCursor;
+use crate::word_boundaries::{WordCursor, DEFAULT_WORD_SEPARATORS};
 use xi_rope::{Cursor, LinesMetric, Rope};
 
 /// The specification of a movement.
@@ -32,6 +33,14 @@ pub enum Movement {
     LeftWord,
     /// Move to the right by one word.
     RightWord,
+    /// Move to the right, to the start of the next word, or to the end of
+    /// the line if there is no next word on it.
+    RightWordStart,
+    /// Move to the left by one subword, a part of a word delimited by
+    /// underscores or camelCase humps.
+    LeftSubword,
+    /// Move to the right by one subword.
+    RightSubword,
     /// Move to left end of visible line.
     LeftOfLine,
     /// Move to right end of visible line.
@@ -58,6 +67,8 @@ pub enum Movement {
     StartOfDocument,
     /// Move to the end of the document
     EndOfDocument,
+    /// Move to the bracket matching the one adjacent to the cursor.
+    MatchingBracket,
 }
 
 /// Compute movement based on vertical motion by the given number of lines.
@@ -157,6 +168,37 @@ fn selection_position(
     (col, line)
 }
 
+/// Compute movement to the bracket matching the one adjacent to the active
+/// end of the region.
+///
+/// The cursor keeps the same side of the matching bracket as it had of the
+/// original one, so repeating the movement returns to the start. When
+/// modifying the selection, it is extended to include the matching bracket.
+fn matching_bracket_motion(
+    r: SelRegion,
+    text: &Rope,
+    brackets: &BracketMatcher,
+    modify: bool,
+) -> (usize, Option<HorizPos>) {
+    let (bracket, other) = match brackets.match_adjacent(text, r.end) {
+        Some(pair) => pair,
+        None => return (r.end, r.horiz),
+    };
+    let after_other = text.next_codepoint_offset(other).unwrap_or(other);
+    let offset = if modify {
+        if other > bracket {
+            after_other
+        } else {
+            other
+        }
+    } else if bracket == r.end {
+        other
+    } else {
+        after_other
+    };
+    (offset, None)
+}
+
 /// When paging through a file, the number of lines from the previous page
 /// that will also be visible in the next.
 const SCROLL_OVERLAP: isize = 2;
@@ -179,6 +221,28 @@ pub fn region_movement(
     height: usize,
     text: &Rope,
     modify: bool,
+) -> SelRegion {
+    let brackets = BracketMatcher::default();
+    region_movement_with_config(m, r, lo, height, text, DEFAULT_WORD_SEPARATORS, &brackets, modify)
+}
+
+/// Compute the result of movement on one selection region, where words are
+/// separated by whitespace and by the characters in `word_separators`, and
+/// brackets are matched by `brackets`.
+///
+/// # Arguments
+///
+/// * `height` - viewport height
+/// * `word_separators` - the characters separating words, besides whitespace
+pub fn region_movement_with_config(
+    m: Movement,
+    r: SelRegion,
+    lo: &dyn LineOffset,
+    height: usize,
+    text: &Rope,
+    word_separators: &str,
+    brackets: &BracketMatcher,
+    modify: bool,
 ) -> SelRegion {
     let (offset, horiz) = match m {
         Movement::Left => {
@@ -204,15 +268,30 @@ pub fn region_movement(
             }
         }
         Movement::LeftWord => {
-            let mut word_cursor = WordCursor::new(text, r.end);
+            let mut word_cursor = WordCursor::with_separators(text, r.end, word_separators);
             let offset = word_cursor.prev_boundary().unwrap_or(0);
             (offset, None)
         }
         Movement::RightWord => {
-            let mut word_cursor = WordCursor::new(text, r.end);
+            let mut word_cursor = WordCursor::with_separators(text, r.end, word_separators);
             let offset = word_cursor.next_boundary().unwrap_or_else(|| text.len());
             (offset, None)
         }
+        Movement::RightWordStart => {
+            let mut word_cursor = WordCursor::with_separators(text, r.end, word_separators);
+            let offset = word_cursor.next_word_start().unwrap_or_else(|| text.len());
+            (offset, None)
+        }
+        Movement::LeftSubword => {
+            let mut word_cursor = WordCursor::with_separators(text, r.end, word_separators);
+            let offset = word_cursor.prev_subword_boundary().unwrap_or(0);
+            (offset, None)
+        }
+        Movement::RightSubword => {
+            let mut word_cursor = WordCursor::with_separators(text, r.end, word_separators);
+            let offset = word_cursor.next_subword_boundary().unwrap_or_else(|| text.len());
+            (offset, None)
+        }
         Movement::LeftOfLine => {
             let line = lo.line_of_offset(text, r.end);
             let offset = lo.offset_of_line(text, line);
@@ -279,6 +358,7 @@ pub fn region_movement(
         Movement::DownPage => vertical_motion(r, lo, text, scroll_height(height), modify),
         Movement::StartOfDocument => (0, None),
         Movement::EndOfDocument => (text.len(), None),
+        Movement::MatchingBracket => matching_bracket_motion(r, text, brackets, modify),
     };
     SelRegion::new(if modify { r.start } else { offset }, offset).with_horiz(horiz)
 }
@@ -301,10 +381,42 @@ pub fn selection_movement(
     height: usize,
     text: &Rope,
     modify: bool,
+) -> Selection {
+    let brackets = BracketMatcher::default();
+    selection_movement_with_config(
+        m,
+        s,
+        lo,
+        height,
+        text,
+        DEFAULT_WORD_SEPARATORS,
+        &brackets,
+        modify,
+    )
+}
+
+/// Compute a new selection by applying a movement to an existing selection,
+/// where words are separated by whitespace and by the characters in
+/// `word_separators`, and brackets are matched by `brackets`.
+///
+/// # Arguments
+///
+/// * `height` - viewport height
+/// * `word_separators` - the characters separating words, besides whitespace
+pub fn selection_movement_with_config(
+    m: Movement,
+    s: &Selection,
+    lo: &dyn LineOffset,
+    height: usize,
+    text: &Rope,
+    word_separators: &str,
+    brackets: &BracketMatcher,
+    modify: bool,
 ) -> Selection {
     let mut result = Selection::new();
     for &r in s.iter() {
-        let new_region = region_movement(m, r, lo, height, text, modify);
+        let new_region =
+            region_movement_with_config(m, r, lo, height, text, word_separators, brackets, modify);
         result.add_region(new_region);
     }
     result
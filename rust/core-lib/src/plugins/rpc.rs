@@ -151,6 +151,14 @@ pub struct DataSpan {
     pub data: Value,
 }
 
+/// A foldable region. When folded, the lines after the line containing
+/// `start`, up to and including the line containing `end`, are hidden.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct FoldRange {
+    pub start: usize,
+    pub end: usize,
+}
+
 /// The object returned by the `get_data` RPC.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetDataResponse {
@@ -225,6 +233,13 @@ pub enum PluginNotification {
         annotation_type: AnnotationType,
         rev: u64,
     },
+    /// Replaces this plugin's fold ranges that start in the given region.
+    UpdateFoldRanges {
+        start: usize,
+        len: usize,
+        ranges: Vec<FoldRange>,
+        rev: u64,
+    },
 }
 
 /// Range expressed in terms of PluginPosition. Meant to be sent from
@@ -408,4 +423,18 @@ mod tests {
             _ => panic!("{:?}", de.cmd),
         }
     }
+
+    #[test]
+    fn test_de_update_fold_ranges() {
+        let json = r#"{"method": "update_fold_ranges", "params": {"view_id": "view-id-1",
+            "plugin_id": 42, "start": 0, "len": 20, "rev": 3,
+            "ranges": [{"start": 1, "end": 12}]}}"#;
+        let de: PluginCommand<PluginNotification> = serde_json::from_str(json).unwrap();
+        match de.cmd {
+            PluginNotification::UpdateFoldRanges { ref ranges, rev: 3, .. } => {
+                assert_eq!((ranges[0].start, ranges[0].end), (1, 12))
+            }
+            _ => panic!("{:?}", de.cmd),
+        }
+    }
 }
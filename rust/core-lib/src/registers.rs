@@ -0,0 +1,135 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named registers, holding text to paste independently of the system
+//! pasteboard.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+/// The register holding the text inserted by the last run of typing.
+pub(crate) const LAST_INSERT: char = '.';
+/// The register holding the last search query.
+pub(crate) const LAST_SEARCH: char = '/';
+
+/// The registers shared by all buffers.
+///
+/// Registers are named by a single character: `a` to `z` for the registers
+/// filled by the user, where writing to `A` to `Z` appends to the matching
+/// lowercase register, plus the special registers `.` and `/`, which core
+/// fills with the last inserted text and the last search query.
+#[derive(Debug, Default)]
+pub struct Registers {
+    registers: BTreeMap<char, String>,
+    /// Whether the next inserted text continues the last inserted text.
+    inserting: bool,
+}
+
+impl Registers {
+    /// Returns the contents of the register with the given name, or `None`
+    /// if the register is empty.
+    pub(crate) fn get(&self, name: &str) -> Result<Option<&str>, String> {
+        let (name, _) = parse_name(name)?;
+        Ok(self.registers.get(&name).map(String::as_str))
+    }
+
+    /// Sets the contents of the register with the given name, or appends to
+    /// them if the name is an uppercase letter.
+    pub(crate) fn set(&mut self, name: &str, text: &str) -> Result<(), String> {
+        let (name, append) = parse_name(name)?;
+        let register = self.registers.entry(name).or_default();
+        if !append {
+            register.clear();
+        }
+        register.push_str(text);
+        Ok(())
+    }
+
+    /// Records text inserted by typing. Consecutive insertions are collected
+    /// in the same register, until `end_insert` is called.
+    pub(crate) fn record_insert(&mut self, chars: &str) {
+        let register = self.registers.entry(LAST_INSERT).or_default();
+        if !self.inserting {
+            register.clear();
+            self.inserting = true;
+        }
+        register.push_str(chars);
+    }
+
+    /// Marks the end of a run of typing.
+    pub(crate) fn end_insert(&mut self) {
+        self.inserting = false;
+    }
+
+    pub(crate) fn record_search(&mut self, query: &str) {
+        self.registers.insert(LAST_SEARCH, query.to_owned());
+    }
+
+    /// Describes the non-empty registers, as an object mapping their names
+    /// to their contents.
+    pub(crate) fn to_json(&self) -> Value {
+        let registers = self
+            .registers
+            .iter()
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(name, text)| (name.to_string(), Value::String(text.clone())))
+            .collect::<serde_json::Map<_, _>>();
+        Value::Object(registers)
+    }
+}
+
+/// Returns the register with the given name, and whether writing to it
+/// should append.
+fn parse_name(name: &str) -> Result<(char, bool), String> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() || c == LAST_INSERT || c == LAST_SEARCH => {
+            Ok((c, false))
+        }
+        (Some(c), None) if c.is_ascii_uppercase() => Ok((c.to_ascii_lowercase(), true)),
+        _ => Err(format!("invalid register name {:?}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_append() {
+        let mut registers = Registers::default();
+        assert_eq!(registers.get("a"), Ok(None));
+        registers.set("a", "hello").unwrap();
+        registers.set("A", " world").unwrap();
+        assert_eq!(registers.get("a"), Ok(Some("hello world")));
+        registers.set("a", "bye").unwrap();
+        assert_eq!(registers.get("A"), Ok(Some("bye")));
+        assert!(registers.set("ab", "").is_err());
+        assert!(registers.get("1").is_err());
+        assert_eq!(registers.to_json(), json!({ "a": "bye" }));
+    }
+
+    #[test]
+    fn special_registers() {
+        let mut registers = Registers::default();
+        registers.record_insert("a");
+        registers.record_insert("b");
+        registers.end_insert();
+        assert_eq!(registers.get("."), Ok(Some("ab")));
+        registers.record_insert("c");
+        registers.record_search("needle");
+        assert_eq!(registers.to_json(), json!({ ".": "c", "/": "needle" }));
+    }
+}
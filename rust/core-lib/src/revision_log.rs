@@ -0,0 +1,99 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A log of the revisions of a buffer, recording when and by whom each
+//! revision was made.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use xi_rope::engine::RevToken;
+use xi_rope::RopeDelta;
+
+/// The author of the edits made by core.
+pub(crate) const CORE_AUTHOR: &str = "core";
+
+/// The revisions of a buffer still known to its engine, oldest first.
+#[derive(Debug)]
+pub(crate) struct RevisionLog {
+    revisions: Vec<RevisionEntry>,
+}
+
+#[derive(Debug)]
+struct RevisionEntry {
+    rev: RevToken,
+    time: SystemTime,
+    author: String,
+    inserted: usize,
+    deleted: usize,
+}
+
+impl RevisionLog {
+    /// Creates a log starting with `rev`, the revision the buffer was opened
+    /// at, so that it can be reverted to.
+    pub(crate) fn new(rev: RevToken) -> Self {
+        let mut log = RevisionLog { revisions: Vec::new() };
+        log.record(rev, CORE_AUTHOR, 0, 0);
+        log
+    }
+
+    /// Records the revision `rev`, which inserted and deleted the given
+    /// number of bytes.
+    pub(crate) fn record(&mut self, rev: RevToken, author: &str, inserted: usize, deleted: usize) {
+        let entry = RevisionEntry {
+            rev,
+            time: SystemTime::now(),
+            author: author.to_owned(),
+            inserted,
+            deleted,
+        };
+        self.revisions.push(entry);
+    }
+
+    /// Forgets the revisions for which `f` returns `false`, typically the
+    /// ones that were garbage collected.
+    pub(crate) fn retain<F: FnMut(RevToken) -> bool>(&mut self, mut f: F) {
+        self.revisions.retain(|entry| f(entry.rev));
+    }
+
+    /// Describes the revisions, for the frontend.
+    pub(crate) fn to_json(&self) -> Value {
+        let revisions = self
+            .revisions
+            .iter()
+            .map(|entry| {
+                json!({
+                    "rev": entry.rev,
+                    "timestamp": timestamp(entry.time),
+                    "author": entry.author,
+                    "inserted": entry.inserted,
+                    "deleted": entry.deleted,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!(revisions)
+    }
+}
+
+/// Returns the number of bytes inserted and deleted by a delta.
+pub(crate) fn delta_size(delta: &RopeDelta) -> (usize, usize) {
+    let inserted = delta.inserts_len();
+    (inserted, delta.base_len + inserted - delta.new_document_len())
+}
+
+/// Returns a time in milliseconds since the Unix epoch.
+pub(crate) fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+}
@@ -26,6 +26,8 @@ use serde::ser::{self, Serialize, Serializer};
 use serde_json::{self, Value};
 
 use crate::config::{ConfigDomainExternal, Table};
+use crate::file::CharacterEncoding;
+use crate::line_ending::LineEnding;
 use crate::plugins::PlaceholderRpc;
 use crate::syntax::LanguageId;
 use crate::tabs::ViewId;
@@ -173,8 +175,29 @@ pub enum CoreNotification {
     /// Tells `xi-core` to close the specified view.
     CloseView { view_id: ViewId },
     /// Tells `xi-core` to save the contents of the specified view's
-    /// buffer to the specified path.
-    Save { view_id: ViewId, file_path: String },
+    /// buffer to the specified path, in the given encoding, or else in
+    /// the encoding the file was opened with.
+    Save {
+        view_id: ViewId,
+        file_path: String,
+        #[serde(default)]
+        encoding: Option<CharacterEncoding>,
+    },
+    /// Tells `xi-core` to read the file of the specified view again,
+    /// decoding it with the given encoding.
+    ReopenWithEncoding { view_id: ViewId, encoding: CharacterEncoding },
+    /// Tells `xi-core` to enable the features disabled for the large file
+    /// shown in the specified view.
+    ExitLargeFileMode { view_id: ViewId },
+    /// Tells `xi-core` to replace the contents of the specified view with
+    /// the unsaved changes to its file recovered after a crash.
+    RestoreRecovery { view_id: ViewId },
+    /// Tells `xi-core` to delete the unsaved changes to the file of the
+    /// specified view recovered after a crash.
+    DiscardRecovery { view_id: ViewId },
+    /// Notifies `xi-core` that the client lost focus, so that files can be
+    /// saved if `autosave_on_focus_loss` is set.
+    FocusLost {},
     /// Tells `xi-core` to set the theme.
     SetTheme { theme_name: String },
     /// Notifies `xi-core` that the client has started.
@@ -204,6 +227,9 @@ pub enum CoreNotification {
     SaveTrace { destination: PathBuf, frontend_samples: Value },
     /// Tells `xi-core` to set the language id for the view.
     SetLanguage { view_id: ViewId, language_id: LanguageId },
+    /// Sets the contents of a register. Writing to an uppercase register
+    /// appends to the matching lowercase one.
+    SetRegister { name: String, text: String },
 }
 
 /// The requests which make up the base of the protocol.
@@ -249,6 +275,16 @@ pub enum CoreRequest {
     /// In the future this might also be used to return structured data (such
     /// as for printing).
     DebugGetContents { view_id: ViewId },
+    /// Returns the revisions of the buffer for a given `ViewId`, oldest
+    /// first, with the time they were made, their author and the number
+    /// of bytes they inserted and deleted.
+    GetRevisionHistory { view_id: ViewId },
+    /// Returns the contents of the kill ring, most recent first. Each kill is
+    /// a list of pieces, one per caret it was made with.
+    GetKillRing,
+    /// Returns the contents of the non-empty registers, as an object
+    /// mapping their names to their contents.
+    GetRegisters,
 }
 
 /// A helper type, which extracts the `view_id` field from edit
@@ -302,6 +338,7 @@ pub enum SelectionGranularity {
 pub enum GestureType {
     Select { granularity: SelectionGranularity, multi: bool },
     SelectExtend { granularity: SelectionGranularity },
+    BlockSelect,
     Drag,
 
     // Deprecated
@@ -384,13 +421,19 @@ pub enum EditNotification {
     Insert {
         chars: String,
     },
+    /// Pastes `chars`, or the contents of `register` if it is present.
     Paste {
+        #[serde(default)]
         chars: String,
+        #[serde(default)]
+        register: Option<String>,
     },
     DeleteForward,
     DeleteBackward,
     DeleteWordForward,
     DeleteWordBackward,
+    DeleteSubwordForward,
+    DeleteSubwordBackward,
     DeleteToEndOfParagraph,
     DeleteToBeginningOfLine,
     InsertNewline,
@@ -411,6 +454,10 @@ pub enum EditNotification {
     MoveWordLeftAndModifySelection,
     MoveWordRight,
     MoveWordRightAndModifySelection,
+    MoveSubwordLeft,
+    MoveSubwordLeftAndModifySelection,
+    MoveSubwordRight,
+    MoveSubwordRightAndModifySelection,
     MoveToBeginningOfParagraph,
     MoveToBeginningOfParagraphAndModifySelection,
     MoveToEndOfParagraph,
@@ -423,6 +470,8 @@ pub enum EditNotification {
     MoveToBeginningOfDocumentAndModifySelection,
     MoveToEndOfDocument,
     MoveToEndOfDocumentAndModifySelection,
+    MoveToMatchingBracket,
+    MoveToMatchingBracketAndModifySelection,
     ScrollPageUp,
     PageUpAndModifySelection,
     ScrollPageDown,
@@ -430,6 +479,10 @@ pub enum EditNotification {
     SelectAll,
     AddSelectionAbove,
     AddSelectionBelow,
+    ExtendBlockSelectionUp,
+    ExtendBlockSelectionDown,
+    ExtendBlockSelectionLeft,
+    ExtendBlockSelectionRight,
     Scroll(LineRange),
     Resize(Size),
     GotoLine {
@@ -437,6 +490,9 @@ pub enum EditNotification {
     },
     RequestLines(LineRange),
     Yank,
+    /// Replaces the text inserted by `yank` or `yank_pop` with the kill
+    /// before it in the kill ring.
+    YankPop,
     Transpose,
     Click(MouseAction),
     Drag(MouseAction),
@@ -447,6 +503,27 @@ pub enum EditNotification {
     },
     Undo,
     Redo,
+    JumpToUndoNode {
+        id: usize,
+    },
+    RevertToRevision {
+        rev: u64,
+    },
+    /// Bookmarks the lines touched by the selection. A named bookmark is
+    /// only set on the line of the last selection.
+    SetBookmark {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// Removes the bookmarks on the lines touched by the selection.
+    ClearBookmark,
+    ToggleBookmark,
+    ClearAllBookmarks,
+    NextBookmark,
+    PreviousBookmark,
+    GotoBookmark {
+        name: String,
+    },
     Find {
         chars: String,
         case_sensitive: bool,
@@ -483,6 +560,18 @@ pub enum EditNotification {
     Uppercase,
     Lowercase,
     Capitalize,
+    /// Converts each selection to `snake_case`.
+    ToSnakeCase,
+    /// Converts each selection to `camelCase`.
+    ToCamelCase,
+    /// Converts each selection to `PascalCase`.
+    ToPascalCase,
+    /// Converts each selection to `kebab-case`.
+    ToKebabCase,
+    /// Converts each selection to `SCREAMING_SNAKE_CASE`.
+    ToScreamingSnakeCase,
+    /// Converts each selection to `Title Case`.
+    ToTitleCase,
     Reindent,
     Indent,
     Outdent,
@@ -510,6 +599,29 @@ pub enum EditNotification {
     DuplicateLine,
     IncreaseNumber,
     DecreaseNumber,
+    SortLines,
+    SortLinesCaseInsensitive,
+    SortLinesNatural,
+    UniqueLines,
+    ReverseLines,
+    ShuffleLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    /// Rewraps the paragraphs touched by each selection to the
+    /// `reflow_column` config key.
+    ReflowParagraph,
+    /// Converts the leading tabs of the selected lines, or of the whole
+    /// buffer, to spaces.
+    ConvertIndentationToSpaces,
+    /// Converts the leading spaces of the selected lines, or of the whole
+    /// buffer, to tabs.
+    ConvertIndentationToTabs,
+    /// Converts every line ending of the buffer to `line_ending`, which new
+    /// lines then use as well.
+    ConvertLineEndings {
+        line_ending: LineEnding,
+    },
     ToggleRecording {
         recording_name: Option<String>,
     },
@@ -520,6 +632,30 @@ pub enum EditNotification {
         recording_name: String,
     },
     CollapseSelections,
+    /// Expands each selection to the smallest enclosing syntactic unit: word,
+    /// string or comment, bracket contents, bracket pair, line, indented
+    /// block, or the whole document.
+    ExpandSelection,
+    /// Shrinks the selection back to what it was before the last
+    /// `expand_selection`.
+    ShrinkSelection,
+    /// Folds the lines below each caret's line, or the lines spanned by
+    /// each selection.
+    Fold,
+    /// Unfolds any folds on the lines touched by the selection.
+    Unfold,
+    ToggleFold,
+    UnfoldAll,
+    /// Moves the caret back to where it was before the last large movement,
+    /// such as `goto_line` or `find_next`.
+    JumpBack,
+    /// Moves the caret forward again after `jump_back`.
+    JumpForward,
+    /// A key pressed in the view, in Vim notation (such as `d`, `<Esc>` or
+    /// `<C-r>`), interpreted by the modal editing layer.
+    Key {
+        key: String,
+    },
 }
 
 /// The edit related requests.
@@ -528,11 +664,24 @@ pub enum EditNotification {
 #[serde(tag = "method", content = "params")]
 pub enum EditRequest {
     /// Cuts the active selection, returning their contents,
-    /// or `Null` if the selection was empty.
-    Cut,
+    /// or `Null` if the selection was empty. The contents are also
+    /// stored in `register` if it is present.
+    Cut {
+        #[serde(default)]
+        register: Option<String>,
+    },
     /// Copies the active selection, returning their contents or
-    /// or `Null` if the selection was empty.
-    Copy,
+    /// or `Null` if the selection was empty. The contents are also
+    /// stored in `register` if it is present.
+    Copy {
+        #[serde(default)]
+        register: Option<String>,
+    },
+    /// Returns the nodes of the undo tree, including the undone branches,
+    /// and the id of the current node.
+    UndoTree,
+    /// Returns the bookmarks of the buffer, as their lines and names.
+    Bookmarks,
 }
 
 /// The plugin related notifications.
@@ -572,20 +721,23 @@ impl<'de, T: Deserialize<'de>> Deserialize<'de> for EditCommand<T> {
         let helper = InnerId::deserialize(&v).map_err(de::Error::custom)?;
         let InnerId { view_id } = helper;
 
-        // if params are empty, remove them
-        let remove_params = match v.get("params") {
-            Some(&Value::Object(ref obj)) => obj.is_empty() && T::deserialize(v.clone()).is_err(),
-            Some(&Value::Array(ref arr)) => arr.is_empty() && T::deserialize(v.clone()).is_err(),
+        // if params are empty, remove them, unless they are all optional
+        let params_empty = match v.get("params") {
+            Some(&Value::Object(ref obj)) => obj.is_empty(),
+            Some(&Value::Array(ref arr)) => arr.is_empty(),
             Some(_) => {
                 return Err(de::Error::custom(
                     "'params' field, if present, must be object or array.",
                 ));
             }
-            None => false,
+            None => true,
         };
 
-        if remove_params {
-            v.as_object_mut().map(|v| v.remove("params"));
+        if params_empty && T::deserialize(v.clone()).is_err() {
+            v["params"] = json!({});
+            if T::deserialize(v.clone()).is_err() {
+                v.as_object_mut().map(|v| v.remove("params"));
+            }
         }
 
         let cmd = T::deserialize(v).map_err(de::Error::custom)?;
@@ -660,4 +812,18 @@ mod tests {
             assert_eq!(chars, message);
         }
     }
+
+    #[test]
+    fn test_deserialize_optional_params() {
+        // Commands whose params are all optional accept empty or missing params.
+        let copy = EditRequest::Copy { register: None };
+        for params in &[r#","params":[]"#, r#","params":{}"#, ""] {
+            let json = format!(r#"{{"view_id":"view-id-1","method":"copy"{}}}"#, params);
+            let cmd: EditCommand<EditRequest> = serde_json::from_str(&json).unwrap();
+            assert_eq!(cmd.cmd, copy);
+        }
+        let json = r#"{"view_id":"view-id-1","method":"copy","params":{"register":"a"}}"#;
+        let cmd: EditCommand<EditRequest> = serde_json::from_str(json).unwrap();
+        assert_eq!(cmd.cmd, EditRequest::Copy { register: Some("a".into()) });
+    }
 }
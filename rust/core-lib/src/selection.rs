@@ -31,13 +31,15 @@ pub type HorizPos = usize;
 
 /// Indicates if an edit should try to drift inside or outside nearby selections. If the selection
 /// is zero width, that is, it is a caret, this value will be ignored, the equivalent of the
-/// `Default` value.
+/// `Default` value, except for `Before`.
 #[derive(Copy, Clone)]
 pub enum InsertDrift {
     /// Indicates this edit should happen within any (non-caret) selections if possible.
     Inside,
     /// Indicates this edit should happen outside any selections if possible.
     Outside,
+    /// Indicates all selections and carets should stay before this edit.
+    Before,
     /// Indicates to do whatever the `after` bool says to do
     Default,
 }
@@ -213,6 +215,7 @@ impl Selection {
             let (start_after, end_after) = match (drift, is_caret) {
                 (InsertDrift::Inside, false) => (!is_region_forward, is_region_forward),
                 (InsertDrift::Outside, false) => (is_region_forward, !is_region_forward),
+                (InsertDrift::Before, _) => (false, false),
                 _ => (after, after),
             };
 
@@ -590,6 +593,25 @@ mod tests {
         assert_eq!(s2.deref(), &[r(1, 4)]);
     }
 
+    #[test]
+    fn apply_delta_before_drift() {
+        let mut s = Selection::new();
+        s.add_region(r(0, 2));
+        s.add_region(r(3, 3));
+
+        // "ab\nc" -> "ab\nc\nd", nothing should drift after the insertion
+        let mut builder = DeltaBuilder::new("ab\nc".len());
+        builder.replace(Interval::new(4, 4), "\nd".into());
+        let s2 = s.apply_delta(&builder.build(), true, InsertDrift::Before);
+        assert_eq!(s2.deref(), &[r(0, 2), r(3, 3)]);
+
+        let mut builder = DeltaBuilder::new("ab\nc".len());
+        builder.replace(Interval::new(2, 2), "!".into());
+        builder.replace(Interval::new(3, 3), "!".into());
+        let s3 = s.apply_delta(&builder.build(), true, InsertDrift::Before);
+        assert_eq!(s3.deref(), &[r(0, 2), r(4, 4)]);
+    }
+
     #[test]
     fn apply_delta_drift_ignored_for_carets() {
         let mut s = Selection::new();
@@ -20,12 +20,13 @@
 //! be renamed.
 
 use std::cell::{Cell, RefCell};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io;
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use serde::de::{self, Deserialize, Deserializer, Unexpected};
 use serde::ser::{Serialize, Serializer};
@@ -39,12 +40,14 @@ use crate::client::Client;
 use crate::config::{self, ConfigDomain, ConfigDomainExternal, ConfigManager, Table};
 use crate::editor::Editor;
 use crate::event_context::EventContext;
-use crate::file::FileManager;
+use crate::file::{CharacterEncoding, FileLoader, FileManager};
+use crate::kill_ring::KillRing;
 use crate::line_ending::LineEnding;
 use crate::plugin_rpc::{PluginNotification, PluginRequest};
 use crate::plugins::rpc::ClientPluginInfo;
 use crate::plugins::{start_plugin_process, Plugin, PluginCatalog, PluginPid};
 use crate::recorder::Recorder;
+use crate::registers::Registers;
 use crate::rpc::{
     CoreNotification, CoreRequest, EditNotification, EditRequest,
     PluginNotification as CorePluginNotification,
@@ -81,12 +84,17 @@ pub type BufferIdentifier = BufferId;
 pub(crate) const RENDER_VIEW_IDLE_MASK: usize = 1 << 25;
 pub(crate) const REWRAP_VIEW_IDLE_MASK: usize = 1 << 26;
 pub(crate) const FIND_VIEW_IDLE_MASK: usize = 1 << 27;
+pub(crate) const RECOVERY_VIEW_IDLE_MASK: usize = 1 << 28;
+pub(crate) const AUTOSAVE_VIEW_IDLE_MASK: usize = 1 << 29;
 
 const NEW_VIEW_IDLE_TOKEN: usize = 1001;
 
 /// xi_rpc idle Token for watcher related idle scheduling.
 pub(crate) const WATCH_IDLE_TOKEN: usize = 1002;
 
+/// Token for loading the next chunk of large files.
+const LOAD_FILE_IDLE_TOKEN: usize = 1003;
+
 #[cfg(feature = "notify")]
 const CONFIG_EVENT_TOKEN: WatchToken = WatchToken(1);
 
@@ -106,7 +114,9 @@ pub struct CoreState {
     views: BTreeMap<ViewId, RefCell<View>>,
     file_manager: FileManager,
     /// A local pasteboard.
-    kill_ring: RefCell<Rope>,
+    kill_ring: RefCell<KillRing>,
+    /// Named registers, shared by all buffers.
+    registers: RefCell<Registers>,
     /// Theme and style state.
     style_map: RefCell<ThemeStyleMap>,
     width_cache: RefCell<WidthCache>,
@@ -119,6 +129,16 @@ pub struct CoreState {
     self_ref: Option<WeakXiCore>,
     /// Views which need to have setup finished.
     pending_views: Vec<(ViewId, Table)>,
+    /// Views whose large files are still being loaded, with their config,
+    /// or `None` if the file is reloaded after changing on disk.
+    loading: BTreeMap<ViewId, (FileLoader, Option<Table>)>,
+    /// Views with a timer scheduled for keeping the unsaved changes of
+    /// their buffer for recovery.
+    recovery_timers: BTreeSet<ViewId>,
+    /// The revisions of buffers whose unsaved changes are kept for recovery.
+    recovered_revs: BTreeMap<BufferId, u64>,
+    /// When views are saved, unless they are edited again before then.
+    autosave_deadlines: BTreeMap<ViewId, Instant>,
     peer: Client,
     id_counter: Counter,
     plugins: PluginCatalog,
@@ -160,6 +180,9 @@ impl CoreState {
             });
         }
 
+        let undo_dir = config_manager.get_undo_dir();
+        let recovery_dir = config_manager.get_recovery_dir();
+
         let plugins_dir = config_manager.get_plugins_dir();
         if let Some(p) = plugins_dir.as_ref() {
             #[cfg(feature = "notify")]
@@ -170,16 +193,21 @@ impl CoreState {
             views: BTreeMap::new(),
             editors: BTreeMap::new(),
             #[cfg(feature = "notify")]
-            file_manager: FileManager::new(watcher),
+            file_manager: FileManager::new(watcher, undo_dir, recovery_dir),
             #[cfg(not(feature = "notify"))]
-            file_manager: FileManager::new(),
-            kill_ring: RefCell::new(Rope::from("")),
+            file_manager: FileManager::new(undo_dir, recovery_dir),
+            kill_ring: RefCell::new(KillRing::default()),
+            registers: RefCell::new(Registers::default()),
             style_map: RefCell::new(ThemeStyleMap::new(themes_dir)),
             width_cache: RefCell::new(WidthCache::new()),
             config_manager,
             recorder: RefCell::new(Recorder::new()),
             self_ref: None,
             pending_views: Vec::new(),
+            loading: BTreeMap::new(),
+            recovery_timers: BTreeSet::new(),
+            recovered_revs: BTreeMap::new(),
+            autosave_deadlines: BTreeMap::new(),
             peer: Client::new(peer.clone()),
             id_counter: Counter::default(),
             plugins: PluginCatalog::default(),
@@ -278,7 +306,11 @@ impl CoreState {
 
             let editor = &self.editors[&buffer_id];
             let info = self.file_manager.get_info(buffer_id);
-            let plugins = self.running_plugins.iter().collect::<Vec<_>>();
+            // plugins are only started for large files once requested
+            let plugins = match info {
+                Some(info) if info.large_file => Vec::new(),
+                _ => self.running_plugins.iter().collect::<Vec<_>>(),
+            };
             let config = self.config_manager.get_buffer_config(buffer_id);
             let language = self.config_manager.get_buffer_language(buffer_id);
 
@@ -297,6 +329,7 @@ impl CoreState {
                 style_map: &self.style_map,
                 width_cache: &self.width_cache,
                 kill_ring: &self.kill_ring,
+                registers: &self.registers,
                 weak_core: self.self_ref.as_ref().unwrap(),
             }
         })
@@ -313,7 +346,14 @@ impl CoreState {
         use self::CorePluginNotification as PN;
         match cmd {
             Edit(crate::rpc::EditCommand { view_id, cmd }) => self.do_edit(view_id, cmd),
-            Save { view_id, file_path } => self.do_save(view_id, file_path),
+            Save { view_id, file_path, encoding } => self.do_save(view_id, file_path, encoding),
+            ReopenWithEncoding { view_id, encoding } => {
+                self.do_reopen_with_encoding(view_id, encoding)
+            }
+            ExitLargeFileMode { view_id } => self.do_exit_large_file_mode(view_id),
+            RestoreRecovery { view_id } => self.do_restore_recovery(view_id),
+            DiscardRecovery { view_id } => self.do_discard_recovery(view_id),
+            FocusLost {} => self.do_focus_lost(),
             CloseView { view_id } => self.do_close_view(view_id),
             ModifyUserConfig { domain, changes } => self.do_modify_user_config(domain, changes),
             SetTheme { theme_name } => self.do_set_theme(&theme_name),
@@ -331,6 +371,11 @@ impl CoreState {
             // handled at the top level
             ClientStarted { .. } => (),
             SetLanguage { view_id, language_id } => self.do_set_language(view_id, language_id),
+            SetRegister { name, text } => {
+                if let Err(err) = self.registers.borrow_mut().set(&name, &text) {
+                    warn!("{}", err);
+                }
+            }
         }
     }
 
@@ -344,18 +389,72 @@ impl CoreState {
             //TODO: why is this a request?? make a notification?
             GetConfig { view_id } => self.do_get_config(view_id).map(|c| json!(c)),
             DebugGetContents { view_id } => self.do_get_contents(view_id).map(|c| json!(c)),
+            GetRevisionHistory { view_id } => self.do_get_revision_history(view_id),
+            GetKillRing => Ok(self.kill_ring.borrow().to_json()),
+            GetRegisters => Ok(self.registers.borrow().to_json()),
         }
     }
 
     fn do_edit(&mut self, view_id: ViewId, cmd: EditNotification) {
+        let converted_line_ending = match cmd {
+            // core breaks lines at `\n` only, so a lone `\r` can't end lines
+            EditNotification::ConvertLineEndings { line_ending: LineEnding::Cr } => {
+                warn!("converting line endings to cr is not supported");
+                return;
+            }
+            EditNotification::ConvertLineEndings { line_ending } => Some(line_ending),
+            _ => None,
+        };
+        // the buffer of a view is replaced once its file is loaded, so only
+        // the view itself can change until then
+        if self.loading.contains_key(&view_id)
+            && !matches!(cmd, EditNotification::Scroll(_) | EditNotification::Resize(_))
+        {
+            return;
+        }
+        let rev = self.head_rev(view_id);
         if let Some(mut edit_ctx) = self.make_context(view_id) {
             edit_ctx.do_edit(cmd);
         }
+        if let Some(line_ending) = converted_line_ending {
+            self.set_line_ending(view_id, line_ending);
+        }
+        if self.head_rev(view_id) != rev {
+            self.schedule_autosave(view_id);
+        }
+    }
+
+    /// Returns the revision of the buffer of a view.
+    fn head_rev(&self, view_id: ViewId) -> Option<u64> {
+        let buffer_id = self.views.get(&view_id)?.borrow().get_buffer_id();
+        Some(self.editors[&buffer_id].borrow().get_head_rev_token())
+    }
+
+    /// Makes the buffer of the given view use `line_ending` for new lines,
+    /// after its line endings were converted.
+    fn set_line_ending(&mut self, view_id: ViewId, line_ending: LineEnding) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        let mut changes = Table::new();
+        changes.insert("line_ending".into(), line_ending.as_str().into());
+        let domain = ConfigDomain::SysOverride(buffer_id);
+        let table = self.config_manager.table_for_update(domain.clone(), changes);
+        self.set_config(domain, table);
+        self.peer.line_ending_changed(view_id, Some(line_ending));
     }
 
     fn do_edit_sync(&mut self, view_id: ViewId, cmd: EditRequest) -> Result<Value, RemoteError> {
-        if let Some(mut edit_ctx) = self.make_context(view_id) {
-            edit_ctx.do_edit_sync(cmd)
+        if self.loading.contains_key(&view_id) {
+            Err(RemoteError::custom(409, format!("view {:?} is loading", view_id), None))
+        } else if let Some(mut edit_ctx) = self.make_context(view_id) {
+            let rev = self.head_rev(view_id);
+            let result = edit_ctx.do_edit_sync(cmd);
+            if self.head_rev(view_id) != rev {
+                self.schedule_autosave(view_id);
+            }
+            result
         } else {
             // TODO: some custom error tpye that can Into<RemoteError>
             Err(RemoteError::custom(404, format!("missing view {:?}", view_id), None))
@@ -366,35 +465,72 @@ impl CoreState {
         let view_id = self.next_view_id();
         let buffer_id = self.next_buffer_id();
 
-        let rope = match path.as_ref() {
-            Some(p) => self.file_manager.open(p, buffer_id)?,
-            None => Rope::from(""),
+        let config = self.config_manager.add_buffer(buffer_id, path.as_deref());
+        let items = &self.config_manager.get_buffer_config(buffer_id).items;
+        let (persistent_undo, large_file_threshold) =
+            (items.persistent_undo, items.large_file_threshold);
+
+        let is_large = path
+            .as_ref()
+            .and_then(|p| p.metadata().ok())
+            .is_some_and(|meta| meta.len() >= large_file_threshold);
+        let opened = match path.as_ref() {
+            Some(p) if is_large => self
+                .file_manager
+                .open_large(p, buffer_id)
+                .map(|loader| (Rope::from(""), Some(loader))),
+            Some(p) => self.file_manager.open(p, buffer_id).map(|rope| (rope, None)),
+            None => Ok((Rope::from(""), None)),
+        };
+        let (rope, loader) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.config_manager.remove_buffer(buffer_id);
+                return Err(e.into());
+            }
         };
 
-        let editor = RefCell::new(Editor::with_text(rope));
+        let history = match path.as_ref() {
+            Some(p) if persistent_undo && !is_large => {
+                self.file_manager.open_undo_history(p, &rope)
+            }
+            _ => None,
+        };
+        let editor = match history {
+            Some(history) => Editor::with_undo_history(history, rope),
+            None => Editor::with_text(rope),
+        };
+        let editor = RefCell::new(editor);
         let view = RefCell::new(View::new(view_id, buffer_id));
 
         self.editors.insert(buffer_id, editor);
         self.views.insert(view_id, view);
 
-        let config = self.config_manager.add_buffer(buffer_id, path.as_deref());
-
         // NOTE: because this is a synchronous call, we have to initialize the
         // view and return the view_id before we can send any events to this
         // view. We call view_init(), mark the view as pending and schedule the
         // idle handler so that we can finish setting up this view on the next
-        // runloop pass, in finalize_new_views.
+        // runloop pass, in finalize_new_views. Views of large files are only
+        // marked as pending once their file is loaded, in load_files.
 
         let mut edit_ctx = self.make_context(view_id).unwrap();
         edit_ctx.view_init();
 
-        self.pending_views.push((view_id, config));
-        self.peer.schedule_idle(NEW_VIEW_IDLE_TOKEN);
+        match loader {
+            Some(loader) => {
+                self.loading.insert(view_id, (loader, Some(config)));
+                self.peer.schedule_idle(LOAD_FILE_IDLE_TOKEN);
+            }
+            None => {
+                self.pending_views.push((view_id, config));
+                self.peer.schedule_idle(NEW_VIEW_IDLE_TOKEN);
+            }
+        }
 
         Ok(json!(view_id))
     }
 
-    fn do_save<P>(&mut self, view_id: ViewId, path: P)
+    fn do_save<P>(&mut self, view_id: ViewId, path: P, encoding: Option<CharacterEncoding>)
     where
         P: AsRef<Path>,
     {
@@ -405,20 +541,45 @@ impl CoreState {
             Some(id) => id,
             None => return,
         };
+        if self.loading.contains_key(&view_id) {
+            self.peer.alert(format!("{:?} can't be saved while it is loading", path));
+            return;
+        }
 
-        let mut save_ctx = self.make_context(view_id).unwrap();
-        let fin_text = save_ctx.text_for_save();
+        self.make_context(view_id).unwrap().before_save();
+        self.save_buffer(view_id, buffer_id, path, encoding);
+    }
+
+    /// Saves the buffer of a view to `path`, without first cleaning up its
+    /// whitespace.
+    fn save_buffer(
+        &mut self,
+        view_id: ViewId,
+        buffer_id: BufferId,
+        path: &Path,
+        encoding: Option<CharacterEncoding>,
+    ) {
+        let fin_text = self.make_context(view_id).unwrap().text_for_save();
+        let prev_path = self.file_manager.get_info(buffer_id).map(|info| info.path.clone());
 
-        if let Err(e) = self.file_manager.save(path, &fin_text, buffer_id) {
+        if let Err(e) = self.file_manager.save(path, &fin_text, buffer_id, encoding) {
             let error_message = e.to_string();
             error!("File error: {:?}", error_message);
             self.peer.alert(error_message);
             return;
         }
 
+        self.recovered_revs.remove(&buffer_id);
+        self.file_manager.remove_recovery_file(prev_path.as_deref().unwrap_or(path));
+
         let changes = self.config_manager.update_buffer_path(buffer_id, path);
         let language = self.config_manager.get_buffer_language(buffer_id);
 
+        if self.config_manager.get_buffer_config(buffer_id).items.persistent_undo {
+            let editor = self.editors[&buffer_id].borrow();
+            self.file_manager.save_undo_history(path, &fin_text, &editor);
+        }
+
         self.make_context(view_id).unwrap().after_save(path);
         self.make_context(view_id).unwrap().language_changed(&language);
 
@@ -428,13 +589,156 @@ impl CoreState {
         }
     }
 
+    fn do_reopen_with_encoding(&mut self, view_id: ViewId, encoding: CharacterEncoding) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) if !self.loading.contains_key(&view_id) => view.borrow().get_buffer_id(),
+            _ => return,
+        };
+        match self.file_manager.reopen(buffer_id, encoding) {
+            Ok(None) => warn!("view {:?} has no file to reopen", view_id),
+            Ok(Some(text)) => {
+                self.make_context(view_id).unwrap().reload(text);
+                // hex dumps are read-only until the user allows editing them
+                let mut changes = Table::new();
+                changes.insert("read_only".into(), (encoding == CharacterEncoding::Hex).into());
+                let domain = ConfigDomain::SysOverride(buffer_id);
+                let table = self.config_manager.table_for_update(domain.clone(), changes);
+                self.set_config(domain, table);
+            }
+            Err(e) => {
+                let error_message = e.to_string();
+                error!("File error: {:?}", error_message);
+                self.peer.alert(error_message);
+            }
+        }
+    }
+
+    fn do_exit_large_file_mode(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) if !self.loading.contains_key(&view_id) => view.borrow().get_buffer_id(),
+            _ => return,
+        };
+        if self.file_manager.exit_large_file_mode(buffer_id) {
+            self.make_context(view_id).unwrap().exit_large_file_mode();
+        }
+    }
+
+    /// Replaces the contents of a view with the unsaved changes to its file
+    /// kept for recovery.
+    fn do_restore_recovery(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) if !self.loading.contains_key(&view_id) => view.borrow().get_buffer_id(),
+            _ => return,
+        };
+        let text = match self.file_manager.get_info(buffer_id) {
+            Some(info) => {
+                let editor = self.editors[&buffer_id].borrow();
+                self.file_manager.open_recovery_file(&info.path, editor.get_buffer())
+            }
+            None => None,
+        };
+        if let Some(text) = text {
+            self.make_context(view_id).unwrap().restore(text);
+            self.schedule_autosave(view_id);
+        }
+    }
+
+    fn do_discard_recovery(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        self.recovered_revs.remove(&buffer_id);
+        if let Some(info) = self.file_manager.get_info(buffer_id) {
+            self.file_manager.remove_recovery_file(&info.path);
+        }
+    }
+
+    /// Saves the files with unsaved changes which are configured to be saved
+    /// when the client loses focus.
+    fn do_focus_lost(&mut self) {
+        let mut buffers = HashSet::new();
+        let view_ids = self
+            .views
+            .iter()
+            .map(|(view_id, view)| (*view_id, view.borrow().get_buffer_id()))
+            .filter(|(_, buffer_id)| buffers.insert(*buffer_id))
+            .filter(|(_, buffer_id)| {
+                self.config_manager.get_buffer_config(*buffer_id).items.autosave_on_focus_loss
+            })
+            .map(|(view_id, _)| view_id)
+            .collect::<Vec<_>>();
+        view_ids.into_iter().for_each(|view_id| self.autosave(view_id));
+    }
+
+    /// Schedules keeping the unsaved changes of the buffer of a view for
+    /// recovery, and saving it if autosave is enabled, after it was edited.
+    fn schedule_autosave(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        // buffers without a file can't be recovered, as they can't be found
+        // again when their file is opened
+        match self.file_manager.get_info(buffer_id) {
+            Some(info) if !info.large_file => (),
+            _ => return,
+        }
+        let items = &self.config_manager.get_buffer_config(buffer_id).items;
+        let (recovery_interval, autosave_delay) = (items.recovery_interval, items.autosave_delay);
+
+        let now = Instant::now();
+        let token: usize = view_id.into();
+        if recovery_interval > 0 && self.recovery_timers.insert(view_id) {
+            let timeout = now + Duration::from_millis(recovery_interval);
+            self.peer.schedule_timer(timeout, RECOVERY_VIEW_IDLE_MASK | token);
+        }
+        if autosave_delay > 0 {
+            let deadline = now + Duration::from_millis(autosave_delay);
+            self.autosave_deadlines.insert(view_id, deadline);
+            self.peer.schedule_timer(deadline, AUTOSAVE_VIEW_IDLE_MASK | token);
+        }
+    }
+
+    /// Saves the file shown in a view if it has unsaved changes. Unlike
+    /// explicit saves, this doesn't clean up whitespace, as the user may
+    /// still be typing. Files which changed on disk are left alone, rather
+    /// than alerting on every autosave that they can't be saved.
+    fn autosave(&mut self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) if !self.loading.contains_key(&view_id) => view.borrow().get_buffer_id(),
+            _ => return,
+        };
+        if self.editors[&buffer_id].borrow().is_pristine() {
+            return;
+        }
+        let path = match self.file_manager.get_info(buffer_id) {
+            Some(info) if info.has_changed => {
+                info!("not autosaving {:?}, which has changed on disk", info.path);
+                return;
+            }
+            Some(info) => info.path.clone(),
+            None => return,
+        };
+        self.save_buffer(view_id, buffer_id, &path, None);
+    }
+
     fn do_close_view(&mut self, view_id: ViewId) {
+        self.loading.remove(&view_id);
+        self.recovery_timers.remove(&view_id);
+        self.autosave_deadlines.remove(&view_id);
         let close_buffer = self.make_context(view_id).map(|ctx| ctx.close_view()).unwrap_or(true);
 
         let buffer_id = self.views.remove(&view_id).map(|v| v.borrow().get_buffer_id());
 
         if let Some(buffer_id) = buffer_id {
             if close_buffer {
+                // closing a buffer discards its unsaved changes
+                if let Some(info) = self.file_manager.get_info(buffer_id) {
+                    if self.recovered_revs.remove(&buffer_id).is_some() {
+                        self.file_manager.remove_recovery_file(&info.path);
+                    }
+                }
                 self.editors.remove(&buffer_id);
                 self.file_manager.close(buffer_id);
                 self.config_manager.remove_buffer(buffer_id);
@@ -500,6 +804,12 @@ impl CoreState {
             .ok_or_else(|| RemoteError::custom(404, format!("No view for id {}", view_id), None))
     }
 
+    fn do_get_revision_history(&self, view_id: ViewId) -> Result<Value, RemoteError> {
+        self.make_context(view_id)
+            .map(|ctx| ctx.editor.borrow().revision_history_json())
+            .ok_or_else(|| RemoteError::custom(404, format!("No view for id {}", view_id), None))
+    }
+
     fn do_set_language(&mut self, view_id: ViewId, language_id: LanguageId) {
         if let Some(view) = self.views.get(&view_id) {
             let buffer_id = view.borrow().get_buffer_id();
@@ -562,6 +872,7 @@ impl CoreState {
     pub(crate) fn handle_idle(&mut self, token: usize) {
         match token {
             NEW_VIEW_IDLE_TOKEN => self.finalize_new_views(),
+            LOAD_FILE_IDLE_TOKEN => self.load_files(),
             WATCH_IDLE_TOKEN => self.handle_fs_events(),
             other if (other & RENDER_VIEW_IDLE_MASK) != 0 => {
                 self.handle_render_timer(other ^ RENDER_VIEW_IDLE_MASK)
@@ -572,6 +883,12 @@ impl CoreState {
             other if (other & FIND_VIEW_IDLE_MASK) != 0 => {
                 self.handle_find_callback(other ^ FIND_VIEW_IDLE_MASK)
             }
+            other if (other & RECOVERY_VIEW_IDLE_MASK) != 0 => {
+                self.handle_recovery_timer(other ^ RECOVERY_VIEW_IDLE_MASK)
+            }
+            other if (other & AUTOSAVE_VIEW_IDLE_MASK) != 0 => {
+                self.handle_autosave_timer(other ^ AUTOSAVE_VIEW_IDLE_MASK)
+            }
             other => panic!("unexpected idle token {}", other),
         };
     }
@@ -580,13 +897,112 @@ impl CoreState {
         let to_start = mem::take(&mut self.pending_views);
 
         to_start.iter().for_each(|(id, config)| {
+            let binary = self.detect_binary(*id, config);
+            let config = binary.as_ref().unwrap_or(config);
             let modified = self.detect_whitespace(*id, config);
             let config = modified.as_ref().unwrap_or(config);
             let mut edit_ctx = self.make_context(*id).unwrap();
             edit_ctx.finish_init(config);
+            self.detect_recovery(*id);
         });
     }
 
+    /// Tells the client if unsaved changes to the file of a view were kept
+    /// for recovery, when the core crashed or was killed.
+    fn detect_recovery(&self, view_id: ViewId) {
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        let info = match self.file_manager.get_info(buffer_id) {
+            Some(info) if !info.large_file => info,
+            _ => return,
+        };
+        let editor = self.editors[&buffer_id].borrow();
+        if self.file_manager.open_recovery_file(&info.path, editor.get_buffer()).is_some() {
+            self.peer.recovery_available(view_id);
+        }
+    }
+
+    /// Loads the next chunk of every large file being opened, and finishes
+    /// setting up the views of those which are then completely loaded.
+    fn load_files(&mut self) {
+        let view_ids = self.loading.keys().cloned().collect::<Vec<_>>();
+        for view_id in view_ids {
+            let (loader, _) = self.loading.get_mut(&view_id).unwrap();
+            let result = loader.load_chunk();
+            let (loaded, total) = loader.progress();
+            self.peer.load_progress(view_id, loaded, total);
+            match result {
+                Ok(false) => (),
+                Ok(true) => self.finish_loading(view_id),
+                Err(e) => {
+                    let error_message = e.to_string();
+                    error!("File error: {:?}", error_message);
+                    self.peer.alert(error_message);
+                    self.finish_loading(view_id);
+                }
+            }
+        }
+        if !self.loading.is_empty() {
+            self.peer.schedule_idle(LOAD_FILE_IDLE_TOKEN);
+        }
+    }
+
+    /// Replaces the empty buffer of a view with the contents of its loaded
+    /// file, and schedules the rest of the view's setup. A reloaded file
+    /// replaces the contents of the buffer as an edit instead.
+    fn finish_loading(&mut self, view_id: ViewId) {
+        let (loader, config) = self.loading.remove(&view_id).unwrap();
+        let buffer_id = self.views[&view_id].borrow().get_buffer_id();
+        let config = match config {
+            Some(config) => config,
+            None => {
+                if loader.is_done() {
+                    let rope = self.file_manager.finish_loading(buffer_id, loader);
+                    self.make_context(view_id).unwrap().reload(rope);
+                }
+                return;
+            }
+        };
+        if loader.is_done() {
+            let rope = self.file_manager.finish_loading(buffer_id, loader);
+            self.editors.insert(buffer_id, RefCell::new(Editor::with_text(rope)));
+            self.make_context(view_id).unwrap().view_init();
+            self.peer.large_file_mode(view_id, true);
+        } else {
+            // the file couldn't be loaded, leave the buffer empty and unsaved
+            self.file_manager.close(buffer_id);
+        }
+        self.pending_views.push((view_id, config));
+        self.peer.schedule_idle(NEW_VIEW_IDLE_TOKEN);
+    }
+
+    /// Makes the buffer of a view read-only if its file is binary, and thus
+    /// shown as a hex dump, and merges that with the config.
+    fn detect_binary(&mut self, id: ViewId, config: &Table) -> Option<Table> {
+        let buffer_id = self.views.get(&id).map(|v| v.borrow().get_buffer_id())?;
+        if self.file_manager.get_info(buffer_id)?.encoding != CharacterEncoding::Hex {
+            return None;
+        }
+
+        let mut changes = Table::new();
+        changes.insert("read_only".into(), true.into());
+        let domain = ConfigDomain::SysOverride(buffer_id);
+        let config_delta = self.config_manager.table_for_update(domain.clone(), changes);
+        match self.config_manager.set_user_config(domain, config_delta) {
+            Ok(items) => {
+                let mut config = config.clone();
+                items.into_iter().for_each(|(_, table)| config.extend(table));
+                Some(config)
+            }
+            Err(err) => {
+                warn!("detect_binary failed to update config: {:?}", err);
+                None
+            }
+        }
+    }
+
     // Detects whitespace settings from the file and merges them with the config
     fn detect_whitespace(&mut self, id: ViewId, config: &Table) -> Option<Table> {
         let buffer_id = self.views.get(&id).map(|v| v.borrow().get_buffer_id())?;
@@ -599,6 +1015,11 @@ impl CoreState {
             return None;
         }
 
+        // scanning all of a large file would take too long
+        if self.file_manager.get_info(buffer_id).is_some_and(|info| info.large_file) {
+            return None;
+        }
+
         let autodetect_whitespace =
             self.config_manager.get_buffer_config(buffer_id).items.autodetect_whitespace;
         if !autodetect_whitespace {
@@ -615,19 +1036,27 @@ impl CoreState {
                 changes.insert("translate_tabs_to_spaces".into(), true.into());
                 changes.insert("tab_size".into(), n.into());
             }
-            Err(_) => info!("detected mixed indentation"),
+            Err(_) => {
+                info!("detected mixed indentation");
+                self.peer.mixed_indentation(id);
+            }
             Ok(None) => info!("file contains no indentation"),
         }
 
         let line_ending = LineEnding::parse(editor.borrow().get_buffer());
         match line_ending {
-            Ok(Some(LineEnding::CrLf)) => {
-                changes.insert("line_ending".into(), "\r\n".into());
+            Ok(Some(line_ending)) => {
+                // classic Mac line endings are only reported, as core breaks
+                // lines at `\n` only
+                if line_ending != LineEnding::Cr {
+                    changes.insert("line_ending".into(), line_ending.as_str().into());
+                }
+                self.peer.line_ending_changed(id, Some(line_ending));
             }
-            Ok(Some(LineEnding::Lf)) => {
-                changes.insert("line_ending".into(), "\n".into());
+            Err(_) => {
+                info!("detected mixed line endings");
+                self.peer.line_ending_changed(id, None);
             }
-            Err(_) => info!("detected mixed line endings"),
             Ok(None) => info!("file contains no supported line endings"),
         }
 
@@ -682,6 +1111,46 @@ impl CoreState {
         }
     }
 
+    /// Keeps the unsaved changes of the buffer of a view for recovery, or
+    /// removes those kept before once they are saved or undone.
+    fn handle_recovery_timer(&mut self, token: usize) {
+        let view_id: ViewId = token.into();
+        if !self.recovery_timers.remove(&view_id) {
+            return;
+        }
+        let buffer_id = match self.views.get(&view_id) {
+            Some(view) => view.borrow().get_buffer_id(),
+            None => return,
+        };
+        let info = match self.file_manager.get_info(buffer_id) {
+            Some(info) => info,
+            None => return,
+        };
+        let editor = self.editors[&buffer_id].borrow();
+        let rev = editor.get_head_rev_token();
+        if editor.is_pristine() {
+            if self.recovered_revs.remove(&buffer_id).is_some() {
+                self.file_manager.remove_recovery_file(&info.path);
+            }
+        } else if self.recovered_revs.get(&buffer_id) != Some(&rev) {
+            self.file_manager.save_recovery_file(&info.path, editor.get_buffer());
+            self.recovered_revs.insert(buffer_id, rev);
+        }
+    }
+
+    /// Saves the file of a view, unless it was edited again after the timer
+    /// was scheduled.
+    fn handle_autosave_timer(&mut self, token: usize) {
+        let view_id: ViewId = token.into();
+        match self.autosave_deadlines.get(&view_id) {
+            Some(deadline) if *deadline <= Instant::now() => {
+                self.autosave_deadlines.remove(&view_id);
+                self.autosave(view_id);
+            }
+            _ => (),
+        }
+    }
+
     #[cfg(feature = "notify")]
     fn handle_fs_events(&mut self) {
         let _t = trace_block("CoreState::handle_fs_events", &["core"]);
@@ -719,6 +1188,18 @@ impl CoreState {
             Some(id) => id,
             None => return,
         };
+        // this is ugly; we don't map buffer_id -> view_id anywhere
+        // but we know we must have a view.
+        let view_id = self
+            .views
+            .values()
+            .find(|v| v.borrow().get_buffer_id() == buffer_id)
+            .map(|v| v.borrow().get_view_id())
+            .unwrap();
+        // the buffer of a loading view is replaced once its file is loaded
+        if self.loading.contains_key(&view_id) {
+            return;
+        }
 
         let has_changes = self.file_manager.check_file(path, buffer_id);
         let is_pristine = self.editors.get(&buffer_id).map(|ed| ed.borrow().is_pristine()).unwrap();
@@ -726,18 +1207,21 @@ impl CoreState {
         // determining if a file has been changed by another process.
         // A more robust solution would also hash the file's contents.
 
-        if has_changes && is_pristine {
-            if let Ok(text) = self.file_manager.open(path, buffer_id) {
-                // this is ugly; we don't map buffer_id -> view_id anywhere
-                // but we know we must have a view.
-                let view_id = self
-                    .views
-                    .values()
-                    .find(|v| v.borrow().get_buffer_id() == buffer_id)
-                    .map(|v| v.borrow().get_view_id())
-                    .unwrap();
-                self.make_context(view_id).unwrap().reload(text);
+        if !has_changes || !is_pristine {
+            return;
+        }
+        let large_file = self.file_manager.get_info(buffer_id).is_some_and(|info| info.large_file);
+        if large_file {
+            // large files are read again in chunks, as when they are opened
+            match self.file_manager.open_large(path, buffer_id) {
+                Ok(loader) => {
+                    self.loading.insert(view_id, (loader, None));
+                    self.peer.schedule_idle(LOAD_FILE_IDLE_TOKEN);
+                }
+                Err(e) => error!("File error: {:?}", e.to_string()),
             }
+        } else if let Ok(text) = self.file_manager.open(path, buffer_id) {
+            self.make_context(view_id).unwrap().reload(text);
         }
     }
 
@@ -924,8 +1408,11 @@ impl CoreState {
     pub(crate) fn plugin_connect(&mut self, plugin: Result<Plugin, io::Error>) {
         match plugin {
             Ok(plugin) => {
-                let init_info =
-                    self.iter_groups().map(|mut ctx| ctx.plugin_info()).collect::<Vec<_>>();
+                let init_info = self
+                    .iter_groups()
+                    .filter(|ctx| !ctx.is_large_file())
+                    .map(|mut ctx| ctx.plugin_info())
+                    .collect::<Vec<_>>();
                 plugin.initialize(init_info);
                 self.running_plugins.push(plugin);
             }
@@ -0,0 +1,185 @@
+// Copyright 2020 The xi-editor Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The tree of undo groups, which keeps the edits that were undone before
+//! making new ones as separate branches.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use xi_rope::RopeDelta;
+
+use crate::editor::EditType;
+use crate::revision_log::{delta_size, timestamp};
+
+/// The undo groups of a buffer, each of which is a node whose parent is the
+/// group that was current when it was created.
+///
+/// Undo group ids increase over time, so the most recent child of a node is
+/// the one with the largest id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct UndoTree {
+    nodes: BTreeMap<usize, UndoNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UndoNode {
+    parent: Option<usize>,
+    time: SystemTime,
+    edit_type: EditType,
+    inserted: usize,
+    deleted: usize,
+}
+
+impl UndoTree {
+    /// Creates a tree whose root is the given undo group.
+    pub(crate) fn new(root: usize) -> Self {
+        let mut tree = UndoTree::default();
+        tree.add(root, None, EditType::Other);
+        tree
+    }
+
+    /// Adds a new undo group as a child of `parent`.
+    pub(crate) fn add(&mut self, group: usize, parent: Option<usize>, edit_type: EditType) {
+        let node = UndoNode { parent, time: SystemTime::now(), edit_type, inserted: 0, deleted: 0 };
+        self.nodes.insert(group, node);
+    }
+
+    /// Accounts for an edit made in the given undo group.
+    pub(crate) fn record_edit(&mut self, group: usize, delta: &RopeDelta) {
+        if let Some(node) = self.nodes.get_mut(&group) {
+            let (inserted, deleted) = delta_size(delta);
+            node.inserted += inserted;
+            node.deleted += deleted;
+        }
+    }
+
+    pub(crate) fn contains(&self, group: usize) -> bool {
+        self.nodes.contains_key(&group)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn groups(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.keys().cloned()
+    }
+
+    /// Returns the undo groups from the root of the tree to `group`.
+    pub(crate) fn path_to(&self, group: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut next = Some(group);
+        while let Some(group) = next {
+            path.push(group);
+            next = self.nodes.get(&group).and_then(|node| node.parent);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Returns the undo groups that can be redone from `group`, following the
+    /// most recent child of each node.
+    pub(crate) fn latest_descendants(&self, group: usize) -> Vec<usize> {
+        let mut descendants = Vec::new();
+        let mut next = self.latest_child(group);
+        while let Some(group) = next {
+            descendants.push(group);
+            next = self.latest_child(group);
+        }
+        descendants
+    }
+
+    fn latest_child(&self, group: usize) -> Option<usize> {
+        self.children(group).last()
+    }
+
+    fn children(&self, group: usize) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().filter(move |(_, node)| node.parent == Some(group)).map(|(&id, _)| id)
+    }
+
+    /// Removes the root of the tree, which must be `root`, keeping the subtree
+    /// of its child `kept`. Returns the removed undo groups.
+    pub(crate) fn remove_root(&mut self, root: usize, kept: usize) -> BTreeSet<usize> {
+        let mut removed = BTreeSet::new();
+        let mut to_remove = vec![root];
+        while let Some(group) = to_remove.pop() {
+            to_remove.extend(self.children(group).filter(|&child| child != kept));
+            self.nodes.remove(&group);
+            removed.insert(group);
+        }
+        if let Some(node) = self.nodes.get_mut(&kept) {
+            node.parent = None;
+        }
+        removed
+    }
+
+    /// Removes the oldest leaf that isn't one of `excluded`, returning it.
+    pub(crate) fn remove_oldest_leaf(&mut self, excluded: &[usize]) -> Option<usize> {
+        let leaf = self
+            .groups()
+            .find(|&group| !excluded.contains(&group) && self.children(group).next().is_none())?;
+        self.nodes.remove(&leaf);
+        Some(leaf)
+    }
+
+    /// Describes the nodes of the tree, for the frontend.
+    pub(crate) fn to_json(&self, current: usize) -> Value {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(id, node)| {
+                let edit_type = serde_json::to_value(node.edit_type).unwrap();
+                let summary = format!(
+                    "{} +{} -{}",
+                    edit_type.as_str().unwrap_or_default(),
+                    node.inserted,
+                    node.deleted
+                );
+                json!({
+                    "id": id,
+                    "parent": node.parent,
+                    "timestamp": timestamp(node.time),
+                    "summary": summary,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "current": current, "nodes": nodes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let mut tree = UndoTree::new(0);
+        tree.add(1, Some(0), EditType::InsertChars);
+        tree.add(2, Some(1), EditType::InsertChars);
+        tree.add(3, Some(1), EditType::Delete);
+        tree.add(4, Some(2), EditType::InsertChars);
+        assert_eq!(tree.path_to(4), vec![0, 1, 2, 4]);
+        assert_eq!(tree.path_to(3), vec![0, 1, 3]);
+        assert_eq!(tree.latest_descendants(0), vec![1, 3]);
+        assert_eq!(tree.latest_descendants(2), vec![4]);
+
+        assert_eq!(tree.remove_oldest_leaf(&[0, 1, 3]), Some(4));
+        assert_eq!(tree.remove_root(0, 1), [0].iter().cloned().collect());
+        assert_eq!(tree.remove_root(1, 3), [1, 2].iter().cloned().collect());
+        assert_eq!(tree.path_to(3), vec![3]);
+    }
+}
@@ -20,20 +20,27 @@ use std::ops::Range;
 use serde_json::Value;
 
 use crate::annotations::{AnnotationStore, Annotations, ToAnnotation};
+use crate::bookmarks::Bookmarks;
+use crate::brackets::{BracketMatcher, MatchedBrackets, MATCH_SCAN_LINES};
 use crate::client::{Client, Update, UpdateOp};
-use crate::edit_types::ViewEvent;
+use crate::edit_types::{EventDomain, ViewEvent};
+use crate::expand_selection::expand_region;
 use crate::find::{Find, FindStatus};
+use crate::folds::{indent_fold_range, line_fold_range, FoldRanges};
+use crate::jump_list::JumpList;
+use crate::layers::Layers;
 use crate::line_cache_shadow::{self, LineCacheShadow, RenderPlan, RenderTactic};
 use crate::line_offset::LineOffset;
 use crate::linewrap::{InvalLines, Lines, VisualLine, WrapWidth};
-use crate::movement::{region_movement, selection_movement, Movement};
+use crate::modal::{Modal, Mode};
+use crate::movement::{region_movement_with_config, selection_movement_with_config, Movement};
 use crate::plugins::PluginId;
 use crate::rpc::{FindQuery, GestureType, MouseAction, SelectionGranularity, SelectionModifier};
 use crate::selection::{Affinity, InsertDrift, SelRegion, Selection};
 use crate::styles::{Style, ThemeStyleMap};
 use crate::tabs::{BufferId, Counter, ViewId};
-use crate::width_cache::WidthCache;
-use crate::word_boundaries::WordCursor;
+use crate::width_cache::{ColumnMeasure, WidthCache};
+use crate::word_boundaries::{WordCursor, DEFAULT_WORD_SEPARATORS};
 use xi_rope::spans::Spans;
 use xi_rope::{Cursor, Interval, LinesMetric, Rope, RopeDelta};
 use xi_trace::trace_block;
@@ -46,6 +53,10 @@ const FLAG_SELECT: u64 = 2;
 /// Size of batches as number of bytes used during incremental find.
 const FIND_BATCH_SIZE: usize = 500000;
 
+/// Clicks at least this many lines away from the caret are recorded in the
+/// jump list.
+const FAR_CLICK_LINES: usize = 20;
+
 /// A view to a buffer. It is the buffer plus additional information
 /// like line breaks and selection state.
 pub struct View {
@@ -62,6 +73,9 @@ pub struct View {
 
     drag_state: Option<DragState>,
 
+    /// The state of a rectangular selection, if the selection is one.
+    block: Option<BlockState>,
+
     /// vertical scroll position
     first_line: usize,
     /// height of visible portion
@@ -101,6 +115,22 @@ pub struct View {
 
     /// Annotations provided by plugins.
     annotations: AnnotationStore,
+
+    /// The positions to jump back to after large movements.
+    jump_list: JumpList,
+
+    /// The state of the modal editing layer.
+    modal: Modal,
+
+    /// The selections before and after each expansion, to shrink them back.
+    expansions: Vec<(Selection, Selection)>,
+
+    /// The characters separating words, besides whitespace.
+    word_separators: String,
+
+    /// The brackets matched at the carets for the last update, which are
+    /// reused until the text, selection or visible lines change.
+    matched_brackets: Option<MatchedBrackets>,
 }
 
 /// Indicates what changed in the find state.
@@ -159,6 +189,16 @@ struct DragState {
     granularity: SelectionGranularity,
 }
 
+/// State of a rectangular selection: the visual lines and horizontal
+/// positions of the corner where it was started and of the opposite corner.
+#[derive(Clone, Copy, Debug)]
+struct BlockState {
+    anchor_line: usize,
+    anchor_x: f64,
+    head_line: usize,
+    head_x: f64,
+}
+
 impl View {
     pub fn new(view_id: ViewId, buffer_id: BufferId) -> View {
         View {
@@ -169,6 +209,7 @@ impl View {
             scroll_to: Some(0),
             size: Size::default(),
             drag_state: None,
+            block: None,
             first_line: 0,
             height: 10,
             lines: Lines::default(),
@@ -181,6 +222,11 @@ impl View {
             replace: None,
             replace_changed: false,
             annotations: AnnotationStore::new(),
+            jump_list: JumpList::default(),
+            modal: Modal::default(),
+            expansions: Vec::new(),
+            word_separators: DEFAULT_WORD_SEPARATORS.to_owned(),
+            matched_brackets: None,
         }
     }
 
@@ -217,6 +263,27 @@ impl View {
         self.lines.set_wrap_width(text, wrap_width);
     }
 
+    pub(crate) fn set_word_separators(&mut self, word_separators: &str) {
+        self.word_separators = word_separators.to_owned();
+    }
+
+    pub(crate) fn word_separators(&self) -> &str {
+        &self.word_separators
+    }
+
+    /// Forgets the brackets matched at the last render, so that they are
+    /// matched again even if the text and carets haven't changed.
+    pub(crate) fn clear_matched_brackets(&mut self) {
+        self.matched_brackets = None;
+    }
+
+    /// Returns the offsets of the brackets next to carets and of the brackets
+    /// matching them, as of the last render.
+    #[cfg(test)]
+    pub(crate) fn matched_brackets(&self) -> &[usize] {
+        self.matched_brackets.as_ref().map(|matched| matched.offsets()).unwrap_or(&[])
+    }
+
     pub(crate) fn needs_more_wrap(&self) -> bool {
         !self.lines.is_converged()
     }
@@ -237,14 +304,21 @@ impl View {
     pub(crate) fn do_edit(&mut self, text: &Rope, cmd: ViewEvent) {
         use self::ViewEvent::*;
         match cmd {
-            Move(movement) => self.do_move(text, movement, false),
-            ModifySelection(movement) => self.do_move(text, movement, true),
+            Move(movement) => self.do_move(text, movement, &BracketMatcher::default(), false),
+            ModifySelection(movement) => {
+                self.do_move(text, movement, &BracketMatcher::default(), true)
+            }
             SelectAll => self.select_all(text),
             Scroll(range) => self.set_scroll(range.first, range.last),
             AddSelectionAbove => self.add_selection_by_movement(text, Movement::UpExactPosition),
             AddSelectionBelow => self.add_selection_by_movement(text, Movement::DownExactPosition),
+            ExtendBlockSelection(_) => warn!("block selection requires column measurement"),
             Gesture { line, col, ty } => self.do_gesture(text, line, col, ty),
-            GotoLine { line } => self.goto_line(text, line),
+            GotoLine { line } => {
+                self.record_jump(text);
+                self.goto_line(text, line)
+            }
+            SelectLinesTo { line, newline } => self.select_lines_to(text, line, newline),
             Find { chars, case_sensitive, regex, whole_words } => {
                 let id = self.find.first().map(|q| q.id());
                 let query_changes = FindQuery { id, chars, case_sensitive, regex, whole_words };
@@ -252,9 +326,11 @@ impl View {
             }
             MultiFind { queries } => self.set_find(text, queries),
             FindNext { wrap_around, allow_same, modify_selection } => {
+                self.record_jump(text);
                 self.do_find_next(text, false, wrap_around, allow_same, &modify_selection)
             }
             FindPrevious { wrap_around, allow_same, modify_selection } => {
+                self.record_jump(text);
                 self.do_find_next(text, true, wrap_around, allow_same, &modify_selection)
             }
             FindAll => self.do_find_all(text),
@@ -282,6 +358,10 @@ impl View {
                 self.do_gesture(text, line, column, GestureType::Drag)
             }
             CollapseSelections => self.collapse_selections(text),
+            ExpandSelection => {
+                self.expand_selection(text, &BracketMatcher::default(), &Layers::default())
+            }
+            ShrinkSelection => self.shrink_selection(text),
             HighlightFind { visible } => {
                 self.highlight_find = visible;
                 self.find_changed = FindStatusChange::All;
@@ -291,21 +371,64 @@ impl View {
             Replace { chars, preserve_case } => self.do_set_replace(chars, preserve_case),
             SelectionForReplace => self.do_selection_for_replace(text),
             SelectionIntoLines => self.do_split_selection_into_lines(text),
+            Fold => self.fold(text, &FoldRanges::default()),
+            Unfold => self.do_unfold(text),
+            ToggleFold => self.toggle_fold(text, &FoldRanges::default()),
+            UnfoldAll => {
+                if self.lines.folds_mut().clear() {
+                    self.set_dirty(text);
+                }
+            }
+            JumpBack => self.jump(text, true),
+            JumpForward => self.jump(text, false),
+        }
+    }
+
+    /// Records the position of the caret in the jump list, before a large
+    /// movement.
+    pub(crate) fn record_jump(&mut self, text: &Rope) {
+        let offset = self.selection.last().unwrap().end;
+        self.jump_list.record(text, offset);
+    }
+
+    fn jump(&mut self, text: &Rope, back: bool) {
+        self.drag_state = None;
+        let offset = self.selection.last().unwrap().end;
+        let jump = if back { self.jump_list.back(text, offset) } else { self.jump_list.forward() };
+        if let Some(jump) = jump {
+            let jump = self.lines.snap_to_visible(text, jump.min(text.len()), false);
+            self.set_selection(text, SelRegion::caret(jump));
         }
     }
 
+    /// Interprets a key with the modal editing layer, returning the events
+    /// it stands for.
+    pub(crate) fn handle_key(&mut self, key: &str) -> Vec<EventDomain> {
+        self.modal.handle_key(key)
+    }
+
+    pub(crate) fn mode(&self) -> Mode {
+        self.modal.mode()
+    }
+
     fn do_gesture(&mut self, text: &Rope, line: u64, col: u64, ty: GestureType) {
         let line = line as usize;
         let col = col as usize;
         let offset = self.line_col_to_offset(text, line, col);
+        let offset = self.lines.snap_to_visible(text, offset, false);
         match ty {
             GestureType::Select { granularity, multi } => {
+                let caret_line = self.line_of_offset(text, self.selection.last().unwrap().end);
+                if !multi && caret_line.abs_diff(line) >= FAR_CLICK_LINES {
+                    self.record_jump(text);
+                }
                 self.select(text, offset, granularity, multi)
             }
             GestureType::SelectExtend { granularity } => {
                 self.extend_selection(text, offset, granularity)
             }
             GestureType::Drag => self.do_drag(text, offset, Affinity::default()),
+            GestureType::BlockSelect => warn!("block selection requires column measurement"),
 
             _ => {
                 warn!("Deprecated gesture type sent to do_gesture method");
@@ -318,6 +441,21 @@ impl View {
         self.set_selection(text, SelRegion::caret(offset));
     }
 
+    /// Selects the whole lines from the caret's line to `line`, with the
+    /// final newline if `newline` is set. A `line` past the end selects up
+    /// to the last line.
+    fn select_lines_to(&mut self, text: &Rope, line: u64, newline: bool) {
+        let last_line = text.line_of_offset(text.len());
+        let line = min(line, last_line as u64) as usize;
+        let caret_line = text.line_of_offset(self.selection.last().unwrap().end);
+        let start = text.offset_of_line(min(line, caret_line));
+        let mut end = text.offset_of_line(max(line, caret_line) + 1);
+        if !newline && end > start && text.byte_at(end - 1) == b'\n' {
+            end -= 1;
+        }
+        self.set_selection(text, SelRegion::new(start, end));
+    }
+
     pub fn set_size(&mut self, size: Size) {
         self.size = size;
     }
@@ -366,22 +504,54 @@ impl View {
     /// a point that should be scrolled into view.
     ///
     /// If `modify` is `true`, the selections are modified, otherwise the results
-    /// of individual region movements become carets.
-    pub fn do_move(&mut self, text: &Rope, movement: Movement, modify: bool) {
+    /// of individual region movements become carets. Brackets are matched
+    /// using the pairs of `brackets`.
+    pub fn do_move(
+        &mut self,
+        text: &Rope,
+        movement: Movement,
+        brackets: &BracketMatcher,
+        modify: bool,
+    ) {
         self.drag_state = None;
-        let new_sel =
-            selection_movement(movement, &self.selection, self, self.scroll_height(), text, modify);
+        if matches!(movement, Movement::StartOfDocument | Movement::EndOfDocument) {
+            self.record_jump(text);
+        }
+        let new_sel = selection_movement_with_config(
+            movement,
+            &self.selection,
+            self,
+            self.scroll_height(),
+            text,
+            &self.word_separators,
+            brackets,
+            modify,
+        );
+        let forward = matches!(
+            movement,
+            Movement::Right
+                | Movement::RightWord
+                | Movement::RightWordStart
+                | Movement::RightSubword
+                | Movement::EndOfParagraph
+                | Movement::EndOfParagraphKill
+        );
+        let new_sel = self.snap_selection(text, &new_sel, forward);
         self.set_selection(text, new_sel);
     }
 
-    /// Set the selection to a new value.
+    /// Set the selection to a new value. Any folds hiding the edges of the
+    /// new selection are unfolded.
     pub fn set_selection<S: Into<Selection>>(&mut self, text: &Rope, sel: S) {
-        self.set_selection_raw(text, sel.into());
+        let sel = sel.into();
+        self.reveal_selection(text, &sel);
+        self.set_selection_raw(text, sel);
         self.scroll_to_cursor(text);
     }
 
     /// Sets the selection to a new value, without invalidating.
     fn set_selection_for_edit(&mut self, text: &Rope, sel: Selection) {
+        self.block = None;
         self.selection = sel;
         self.scroll_to_cursor(text);
     }
@@ -389,6 +559,7 @@ impl View {
     /// Sets the selection to a new value, invalidating the line cache as needed.
     /// This function does not perform any scrolling.
     fn set_selection_raw(&mut self, text: &Rope, sel: Selection) {
+        self.block = None;
         self.invalidate_selection(text);
         self.selection = sel;
         self.invalidate_selection(text);
@@ -414,10 +585,19 @@ impl View {
         let mut sel = Selection::new();
         for &region in self.sel_regions() {
             sel.add_region(region);
-            let new_region =
-                region_movement(movement, region, self, self.scroll_height(), text, false);
+            let new_region = region_movement_with_config(
+                movement,
+                region,
+                self,
+                self.scroll_height(),
+                text,
+                &self.word_separators,
+                &BracketMatcher::default(),
+                false,
+            );
             sel.add_region(new_region);
         }
+        let sel = self.snap_selection(text, &sel, false);
         self.set_selection(text, sel);
     }
 
@@ -453,7 +633,8 @@ impl View {
         match granularity {
             SelectionGranularity::Point => Interval::new(offset, offset),
             SelectionGranularity::Word => {
-                let mut word_cursor = WordCursor::new(text, offset);
+                let mut word_cursor =
+                    WordCursor::with_separators(text, offset, &self.word_separators);
                 let (start, end) = word_cursor.select_word();
                 Interval::new(start, end)
             }
@@ -572,6 +753,118 @@ impl View {
         }
     }
 
+    /// Returns `true` if the selection is a rectangular block, which `Drag`
+    /// gestures extend.
+    pub(crate) fn is_block_selecting(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Starts a rectangular selection at the given visual line and column or,
+    /// if `extend` is `true`, moves the corner opposite to where the current
+    /// one was started. Columns past the end of the line count as spaces.
+    pub(crate) fn block_select(
+        &mut self,
+        text: &Rope,
+        line: usize,
+        col: usize,
+        extend: bool,
+        columns: &mut ColumnMeasure,
+    ) {
+        let line = min(line, self.line_of_offset(text, text.len()));
+        let positions = columns.positions(text, self.visual_line_interval(text, line));
+        let (start, _) = positions[0];
+        let (end, end_x) = positions[positions.len() - 1];
+        let x = if start + col > end {
+            end_x + (start + col - end) as f64 * columns.space_width()
+        } else {
+            let offset = self.line_col_to_offset(text, line, col);
+            x_of_offset(&positions, offset)
+        };
+        let block = match self.block {
+            Some(block) if extend => BlockState { head_line: line, head_x: x, ..block },
+            _ => BlockState { anchor_line: line, anchor_x: x, head_line: line, head_x: x },
+        };
+        self.set_block_selection(text, block, columns);
+    }
+
+    /// Extends a rectangular selection by one line or column. If the selection
+    /// is not a block, a new one is started at the last cursor.
+    pub(crate) fn extend_block_selection(
+        &mut self,
+        text: &Rope,
+        movement: Movement,
+        columns: &mut ColumnMeasure,
+    ) {
+        let mut block = match self.block {
+            Some(block) => block,
+            None => {
+                let offset = self.selection.last().unwrap().end;
+                let line = self.line_of_offset(text, offset);
+                let positions = columns.positions(text, self.visual_line_interval(text, line));
+                let x = x_of_offset(&positions, offset);
+                BlockState { anchor_line: line, anchor_x: x, head_line: line, head_x: x }
+            }
+        };
+        let positions = columns.positions(text, self.visual_line_interval(text, block.head_line));
+        let end_x = positions[positions.len() - 1].1;
+        match movement {
+            Movement::Up => block.head_line = block.head_line.saturating_sub(1),
+            Movement::Down => {
+                block.head_line = min(block.head_line + 1, self.line_of_offset(text, text.len()))
+            }
+            Movement::Left if block.head_x > end_x => {
+                block.head_x = (block.head_x - columns.space_width()).max(end_x)
+            }
+            Movement::Left => {
+                let prev = positions.iter().rev().find(|(_, x)| *x < block.head_x);
+                block.head_x = prev.map(|(_, x)| *x).unwrap_or(0.0);
+            }
+            Movement::Right => {
+                let next = positions.iter().find(|(_, x)| *x > block.head_x);
+                block.head_x = match next {
+                    Some((_, x)) => *x,
+                    None => block.head_x + columns.space_width(),
+                };
+            }
+            _ => warn!("unsupported movement for block selection: {:?}", movement),
+        }
+        self.set_block_selection(text, block, columns);
+    }
+
+    /// Selects, on each visual line spanned by `block`, the text between its
+    /// horizontal positions. Lines shorter than the block get a caret at
+    /// their end.
+    fn set_block_selection(&mut self, text: &Rope, block: BlockState, columns: &mut ColumnMeasure) {
+        self.drag_state = None;
+        let first_line = min(block.anchor_line, block.head_line);
+        let last_line = max(block.anchor_line, block.head_line);
+        let mut selection = Selection::new();
+        for line in first_line..=last_line {
+            let positions = columns.positions(text, self.visual_line_interval(text, line));
+            let start = offset_of_x(&positions, block.anchor_x);
+            let end = offset_of_x(&positions, block.head_x);
+            selection.add_region(SelRegion::new(start, end));
+        }
+        self.set_selection(text, selection);
+        self.block = Some(block);
+    }
+
+    /// Returns the interval of a visual line, excluding its line break.
+    fn visual_line_interval(&self, text: &Rope, line: usize) -> Interval {
+        let iv = match self.lines.iter_lines(text, line).next() {
+            Some(visual_line) => visual_line.interval,
+            None => Interval::new(text.len(), text.len()),
+        };
+        let mut end = iv.end;
+        if end > iv.start && text.byte_at(end - 1) == b'\n' {
+            end -= 1;
+            if end > iv.start && text.byte_at(end - 1) == b'\r' {
+                end -= 1;
+            }
+        }
+        Interval::new(iv.start, end)
+    }
+
     /// Creates a `SelRegion` for range select or drag operations.
     pub fn range_region(
         &self,
@@ -595,6 +888,47 @@ impl View {
         &self.selection
     }
 
+    /// Expands each selection region to the smallest syntactic unit
+    /// containing it, remembering the selection to shrink back to it.
+    pub(crate) fn expand_selection(
+        &mut self,
+        text: &Rope,
+        brackets: &BracketMatcher,
+        layers: &Layers,
+    ) {
+        let mut expanded = Selection::new();
+        for region in self.selection.iter() {
+            expanded.add_region(expand_region(
+                text,
+                region,
+                &self.word_separators,
+                brackets,
+                layers,
+            ));
+        }
+        if expanded[..] == self.selection[..] {
+            return;
+        }
+        // expanding a selection made otherwise starts over
+        match self.expansions.last() {
+            Some((_, last)) if last[..] == self.selection[..] => (),
+            _ => self.expansions.clear(),
+        }
+        let previous = self.selection.clone();
+        self.set_selection(text, expanded);
+        self.expansions.push((previous, self.selection.clone()));
+    }
+
+    /// Restores the selection as it was before the last expansion.
+    pub(crate) fn shrink_selection(&mut self, text: &Rope) {
+        match self.expansions.pop() {
+            Some((previous, expanded)) if expanded[..] == self.selection[..] => {
+                self.set_selection(text, previous)
+            }
+            _ => self.expansions.clear(),
+        }
+    }
+
     /// Collapse all selections in this view into a single caret
     pub fn collapse_selections(&mut self, text: &Rope) {
         let mut sel = self.selection.clone();
@@ -602,6 +936,103 @@ impl View {
         self.set_selection(text, sel);
     }
 
+    /// Returns a copy of `sel` with the edges of its regions moved out of any
+    /// folded regions, in the given direction.
+    fn snap_selection(&self, text: &Rope, sel: &Selection, forward: bool) -> Selection {
+        if self.lines.folds().is_empty() {
+            return sel.clone();
+        }
+        let mut result = Selection::new();
+        for region in sel.iter() {
+            let start = self.lines.snap_to_visible(text, region.start, forward);
+            let end = self.lines.snap_to_visible(text, region.end, forward);
+            result.add_region(SelRegion { start, end, ..*region });
+        }
+        result
+    }
+
+    /// Unfolds any folds hiding the edges of the regions in `sel`.
+    fn reveal_selection(&mut self, text: &Rope, sel: &Selection) {
+        if self.lines.folds().is_empty() {
+            return;
+        }
+        let mut changed = false;
+        for region in sel.iter() {
+            for &offset in &[region.start, region.end] {
+                if let Some(fold) = self.lines.fold_hiding(text, offset) {
+                    changed |= self.lines.folds_mut().remove_in_lines(fold);
+                }
+            }
+        }
+        if changed {
+            self.set_dirty(text);
+        }
+    }
+
+    /// Returns the range that should be folded for the given region: the
+    /// lines spanned by a selection or, for a caret, the fold range for its
+    /// line if there is one, and otherwise the indented block below it.
+    fn fold_range_for_region(
+        &self,
+        text: &Rope,
+        fold_ranges: &FoldRanges,
+        region: &SelRegion,
+    ) -> Option<Interval> {
+        if region.is_caret() {
+            let line = text.line_of_offset(region.end);
+            fold_ranges.fold_for_line(text, line).or_else(|| indent_fold_range(text, line))
+        } else {
+            line_fold_range(text, region.min(), region.max())
+        }
+    }
+
+    pub(crate) fn fold(&mut self, text: &Rope, fold_ranges: &FoldRanges) {
+        let ranges = self
+            .selection
+            .iter()
+            .filter_map(|region| self.fold_range_for_region(text, fold_ranges, region))
+            .collect::<Vec<_>>();
+        if ranges.is_empty() {
+            return;
+        }
+        for iv in ranges {
+            self.lines.folds_mut().add(iv);
+        }
+        self.after_folds_changed(text);
+    }
+
+    fn do_unfold(&mut self, text: &Rope) {
+        let mut changed = false;
+        for region in self.selection.iter() {
+            let lines = logical_lines_of_region(text, region);
+            changed |= self.lines.folds_mut().remove_in_lines(lines);
+        }
+        if changed {
+            self.set_dirty(text);
+        }
+    }
+
+    /// Unfolds the folds on each region's lines, or folds them if there are none.
+    pub(crate) fn toggle_fold(&mut self, text: &Rope, fold_ranges: &FoldRanges) {
+        let regions = self.selection.iter().cloned().collect::<Vec<_>>();
+        for region in &regions {
+            let lines = logical_lines_of_region(text, region);
+            if !self.lines.folds_mut().remove_in_lines(lines) {
+                if let Some(iv) = self.fold_range_for_region(text, fold_ranges, region) {
+                    self.lines.folds_mut().add(iv);
+                }
+            }
+        }
+        self.after_folds_changed(text);
+    }
+
+    /// Moves the selection out of any newly hidden regions, and invalidates
+    /// the line cache.
+    fn after_folds_changed(&mut self, text: &Rope) {
+        self.selection = self.snap_selection(text, &self.selection, false);
+        self.set_dirty(text);
+    }
+
     /// Determines whether the offset is in any selection (counting carets and
     /// selection edges).
     pub fn is_point_in_selection(&self, offset: usize) -> bool {
@@ -664,6 +1095,9 @@ impl View {
 
         if let Some(text) = text {
             result["text"] = json!(text.slice_to_cow(start_pos..pos));
+            if let Some(n_lines) = self.lines.folded_lines_at(text, pos) {
+                result["folded"] = json!(n_lines);
+            }
         }
         if let Some(style_spans) = style_spans {
             result["styles"] = json!(self.encode_styles(
@@ -744,6 +1178,9 @@ impl View {
         client: &Client,
         styles: &StyleMap,
         style_spans: &Spans<Style>,
+        fold_ranges: &FoldRanges,
+        bookmarks: &Bookmarks,
+        brackets: &BracketMatcher,
         plan: &RenderPlan,
         pristine: bool,
     ) {
@@ -751,16 +1188,33 @@ impl View {
         let start_off = self.offset_of_line(text, self.first_line);
         let end_off = self.offset_of_line(text, self.first_line + self.height + 2);
         let visible_range = Interval::new(start_off, end_off);
+        let carets = self.selection.iter().filter(|r| r.is_caret()).map(|r| r.end).collect();
+        let scan_start =
+            self.offset_of_line(text, self.first_line.saturating_sub(MATCH_SCAN_LINES));
+        let scan_end = self.offset_of_line(text, self.first_line + self.height + MATCH_SCAN_LINES);
+        let scan_range = Interval::new(scan_start, scan_end);
+        let cached = self.matched_brackets.take();
+        let matched = brackets.match_carets(text, carets, scan_range, cached);
+        self.matched_brackets = Some(matched);
         let selection_annotations =
             self.selection.get_annotations(visible_range, self, text).to_json();
         let find_annotations =
             self.find.iter().map(|f| f.get_annotations(visible_range, self, text).to_json());
         let plugin_annotations =
             self.annotations.iter_range(self, text, visible_range).map(|a| a.to_json());
+        let fold_annotations = fold_ranges.get_annotations(visible_range, self, text).to_json();
+        let bookmark_annotations = bookmarks.get_annotations(visible_range, self, text).to_json();
+        let bracket_annotations = self
+            .matched_brackets
+            .as_ref()
+            .map(|matched| matched.get_annotations(visible_range, self, text).to_json());
 
         let annotations = iter::once(selection_annotations)
             .chain(find_annotations)
             .chain(plugin_annotations)
+            .chain(iter::once(fold_annotations))
+            .chain(iter::once(bookmark_annotations))
+            .chain(bracket_annotations)
             .collect::<Vec<_>>();
 
         if !self.lc_shadow.needs_render(plan) {
@@ -903,11 +1357,24 @@ impl View {
         client: &Client,
         styles: &StyleMap,
         style_spans: &Spans<Style>,
+        fold_ranges: &FoldRanges,
+        bookmarks: &Bookmarks,
+        brackets: &BracketMatcher,
         pristine: bool,
     ) {
         let height = self.line_of_offset(text, text.len()) + 1;
         let plan = RenderPlan::create(height, self.first_line, self.height);
-        self.send_update_for_plan(text, client, styles, style_spans, &plan, pristine);
+        self.send_update_for_plan(
+            text,
+            client,
+            styles,
+            style_spans,
+            fold_ranges,
+            bookmarks,
+            brackets,
+            &plan,
+            pristine,
+        );
         if let Some(new_scroll_pos) = self.scroll_to.take() {
             let (line, col) = self.offset_to_line_col(text, new_scroll_pos);
             client.scroll_to(self.view_id, line, col);
@@ -921,6 +1388,9 @@ impl View {
         client: &Client,
         styles: &StyleMap,
         style_spans: &Spans<Style>,
+        fold_ranges: &FoldRanges,
+        bookmarks: &Bookmarks,
+        brackets: &BracketMatcher,
         first_line: usize,
         last_line: usize,
         pristine: bool,
@@ -928,7 +1398,17 @@ impl View {
         let height = self.line_of_offset(text, text.len()) + 1;
         let mut plan = RenderPlan::create(height, self.first_line, self.height);
         plan.request_lines(first_line, last_line);
-        self.send_update_for_plan(text, client, styles, style_spans, &plan, pristine);
+        self.send_update_for_plan(
+            text,
+            client,
+            styles,
+            style_spans,
+            fold_ranges,
+            bookmarks,
+            brackets,
+            &plan,
+            pristine,
+        );
     }
 
     /// Invalidates front-end's entire line cache, forcing a full render at the next
@@ -961,8 +1441,13 @@ impl View {
         let _t = trace_block("View::rewrap", &["core"]);
         let visible = self.first_line..self.first_line + self.height;
         let inval = self.lines.rewrap_chunk(text, width_cache, client, spans, visible);
-        if let Some(InvalLines { start_line, inval_count, new_count }) = inval {
-            self.lc_shadow.edit(start_line, start_line + inval_count, new_count);
+        match inval {
+            // invalidation is in unfolded lines, which doesn't work with folds
+            Some(_) if !self.lines.folds().is_empty() => self.set_dirty(text),
+            Some(InvalLines { start_line, inval_count, new_count }) => {
+                self.lc_shadow.edit(start_line, start_line + inval_count, new_count);
+            }
+            None => (),
         }
     }
 
@@ -994,6 +1479,9 @@ impl View {
         let (iv, _) = delta.summary();
         self.annotations.invalidate(iv);
 
+        self.jump_list.apply_delta(delta);
+        self.expansions.clear();
+
         // update only find highlights affected by change
         for find in &mut self.find {
             find.update_highlights(text, delta);
@@ -1291,6 +1779,32 @@ impl LineOffset for View {
     }
 }
 
+/// Returns the interval from the start of the first logical line touched by
+/// `region` to the start of the line after the last one.
+fn logical_lines_of_region(text: &Rope, region: &SelRegion) -> Interval {
+    let start = text.offset_of_line(text.line_of_offset(region.min()));
+    let end = text.offset_of_line(text.line_of_offset(region.max()) + 1);
+    Interval::new(start, end)
+}
+
+/// Returns the horizontal position of `offset`, given the positions of the
+/// grapheme boundaries of its line.
+fn x_of_offset(positions: &[(usize, f64)], offset: usize) -> f64 {
+    positions.iter().take_while(|(pos, _)| *pos <= offset).last().map(|(_, x)| *x).unwrap_or(0.0)
+}
+
+/// Returns the grapheme boundary closest to the horizontal position `x`,
+/// preferring the leftmost one in case of a tie.
+fn offset_of_x(positions: &[(usize, f64)], x: f64) -> usize {
+    let mut best = positions[0];
+    for &(offset, pos_x) in positions {
+        if (pos_x - x).abs() < (best.1 - x).abs() {
+            best = (offset, pos_x);
+        }
+    }
+    best.0
+}
+
 // utility function to clamp a value within the given range
 fn clamp(x: usize, min: usize, max: usize) -> usize {
     if x < min {
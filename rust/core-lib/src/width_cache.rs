@@ -17,7 +17,10 @@
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 
+use xi_rope::{Interval, Rope};
+
 use crate::client::Client;
+use crate::styles::N_RESERVED_STYLES;
 
 /// A token which can be used to retrieve an actual width value when the
 /// batch request is submitted.
@@ -165,3 +168,90 @@ impl<'a> WidthBatchReq<'a> {
         Ok(())
     }
 }
+
+/// Measures horizontal positions within a line of text, for operations that
+/// work on columns, such as rectangular selection.
+///
+/// Positions are in the units of the `WidthMeasure`; a tab extends to the
+/// next multiple of `tab_size` times the width of a space.
+pub(crate) struct ColumnMeasure<'a> {
+    width_cache: &'a mut WidthCache,
+    measure: &'a dyn WidthMeasure,
+    tab_size: usize,
+}
+
+impl<'a> ColumnMeasure<'a> {
+    pub(crate) fn new(
+        width_cache: &'a mut WidthCache,
+        measure: &'a dyn WidthMeasure,
+        tab_size: usize,
+    ) -> Self {
+        ColumnMeasure { width_cache, measure, tab_size }
+    }
+
+    /// Returns the width of a space, the unit used for positions past the
+    /// end of a line.
+    pub(crate) fn space_width(&mut self) -> Width {
+        let mut req = self.width_cache.batch_req();
+        let tok = req.request(N_RESERVED_STYLES, " ");
+        if let Err(e) = req.resolve_pending(self.measure) {
+            warn!("failed to measure width: {:?}", e);
+        }
+        self.width_cache.resolve(tok)
+    }
+
+    /// Returns the offset and horizontal position of each grapheme boundary
+    /// in `iv`, which should not contain a line break.
+    pub(crate) fn positions(&mut self, text: &Rope, iv: Interval) -> Vec<(usize, Width)> {
+        let mut req = self.width_cache.batch_req();
+        let space = req.request(N_RESERVED_STYLES, " ");
+        let mut graphemes = Vec::new();
+        let mut pos = iv.start;
+        while pos < iv.end {
+            let next = text.next_grapheme_offset(pos).unwrap_or(iv.end).min(iv.end);
+            let grapheme = text.slice_to_cow(pos..next);
+            let tok = if grapheme == "\t" {
+                None
+            } else {
+                Some(req.request(N_RESERVED_STYLES, &grapheme))
+            };
+            graphemes.push((next, tok));
+            pos = next;
+        }
+        if let Err(e) = req.resolve_pending(self.measure) {
+            warn!("failed to measure width: {:?}", e);
+        }
+
+        let tab_width = self.width_cache.resolve(space) * self.tab_size as Width;
+        let mut x = 0.0;
+        let mut result = Vec::with_capacity(graphemes.len() + 1);
+        result.push((iv.start, x));
+        for (offset, tok) in graphemes {
+            x += match tok {
+                Some(tok) => self.width_cache.resolve(tok),
+                None if tab_width > 0.0 => tab_width - x % tab_width,
+                None => 0.0,
+            };
+            result.push((offset, x));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_positions() {
+        let text = Rope::from("a\tbc\té");
+        let mut width_cache = WidthCache::new();
+        let mut columns = ColumnMeasure::new(&mut width_cache, &CodepointMono, 4);
+        let positions = columns.positions(&text, Interval::new(0, text.len()));
+        assert_eq!(
+            positions,
+            vec![(0, 0.0), (1, 1.0), (2, 4.0), (3, 5.0), (4, 6.0), (5, 8.0), (7, 9.0)]
+        );
+        assert_eq!(columns.space_width(), 1.0);
+    }
+}
@@ -17,27 +17,75 @@
 
 use xi_rope::{Cursor, Rope, RopeInfo};
 
+/// The characters separating words by default, the ASCII punctuation except
+/// for `_`.
+pub const DEFAULT_WORD_SEPARATORS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^`{|}~";
+
 pub struct WordCursor<'a> {
     inner: Cursor<'a, RopeInfo>,
+    separators: &'a str,
 }
 
 impl<'a> WordCursor<'a> {
     pub fn new(text: &'a Rope, pos: usize) -> WordCursor<'a> {
+        WordCursor::with_separators(text, pos, DEFAULT_WORD_SEPARATORS)
+    }
+
+    /// Creates a cursor where words are separated by whitespace and by the
+    /// characters in `separators`, typically the `word_separators` config key.
+    pub fn with_separators(text: &'a Rope, pos: usize, separators: &'a str) -> WordCursor<'a> {
         let inner = Cursor::new(text, pos);
-        WordCursor { inner }
+        WordCursor { inner, separators }
     }
 
     /// Get previous boundary, and set the cursor at the boundary found.
     pub fn prev_boundary(&mut self) -> Option<usize> {
-        if let Some(ch) = self.inner.prev_codepoint() {
-            let mut prop = get_word_property(ch);
+        self.prev_boundary_impl(false)
+    }
+
+    /// Get next boundary, and set the cursor at the boundary found.
+    pub fn next_boundary(&mut self) -> Option<usize> {
+        self.next_boundary_impl(false)
+    }
+
+    /// Get the start of the next word, or the end of the line if the
+    /// cursor is in its last word, and set the cursor there.
+    pub fn next_word_start(&mut self) -> Option<usize> {
+        if let Some(mut ch) = self.inner.next_codepoint() {
+            let mut candidate = self.inner.pos();
+            while let Some(next) = self.inner.next_codepoint() {
+                if self.classify(ch, next, false).is_start() {
+                    break;
+                }
+                ch = next;
+                candidate = self.inner.pos();
+            }
+            self.inner.set(candidate);
+            return Some(candidate);
+        }
+        None
+    }
+
+    /// Get previous subword boundary, and set the cursor at the boundary
+    /// found. Subwords are the parts of words separated by underscores or
+    /// by camelCase humps.
+    pub fn prev_subword_boundary(&mut self) -> Option<usize> {
+        self.prev_boundary_impl(true)
+    }
+
+    /// Get next subword boundary, and set the cursor at the boundary found.
+    pub fn next_subword_boundary(&mut self) -> Option<usize> {
+        self.next_boundary_impl(true)
+    }
+
+    fn prev_boundary_impl(&mut self, subword: bool) -> Option<usize> {
+        if let Some(mut ch) = self.inner.prev_codepoint() {
             let mut candidate = self.inner.pos();
             while let Some(prev) = self.inner.prev_codepoint() {
-                let prop_prev = get_word_property(prev);
-                if classify_boundary(prop_prev, prop).is_start() {
+                if self.classify(prev, ch, subword).is_start() {
                     break;
                 }
-                prop = prop_prev;
+                ch = prev;
                 candidate = self.inner.pos();
             }
             self.inner.set(candidate);
@@ -46,17 +94,14 @@ impl<'a> WordCursor<'a> {
         None
     }
 
-    /// Get next boundary, and set the cursor at the boundary found.
-    pub fn next_boundary(&mut self) -> Option<usize> {
-        if let Some(ch) = self.inner.next_codepoint() {
-            let mut prop = get_word_property(ch);
+    fn next_boundary_impl(&mut self, subword: bool) -> Option<usize> {
+        if let Some(mut ch) = self.inner.next_codepoint() {
             let mut candidate = self.inner.pos();
             while let Some(next) = self.inner.next_codepoint() {
-                let prop_next = get_word_property(next);
-                if classify_boundary(prop, prop_next).is_end() {
+                if self.classify(ch, next, subword).is_end() {
                     break;
                 }
-                prop = prop_next;
+                ch = next;
                 candidate = self.inner.pos();
             }
             self.inner.set(candidate);
@@ -65,9 +110,22 @@ impl<'a> WordCursor<'a> {
         None
     }
 
+    fn classify(&self, prev: char, next: char, subword: bool) -> WordBoundary {
+        let prop_prev = get_word_property(prev, self.separators);
+        let prop_next = get_word_property(next, self.separators);
+        match (prop_prev, prop_next) {
+            (WordProperty::Other, WordProperty::Other) if subword => {
+                classify_subword_boundary(prev, next)
+            }
+            _ => classify_boundary(prop_prev, prop_next),
+        }
+    }
+
     /// Return the selection for the word containing the current cursor. The
     /// cursor is moved to the end of that selection.
     pub fn select_word(&mut self) -> (usize, usize) {
+        let separators = self.separators;
+        let get_word_property = |codepoint| get_word_property(codepoint, separators);
         let initial = self.inner.pos();
         let init_prop_after = self.inner.next_codepoint().map(get_word_property);
         self.inner.set(initial);
@@ -185,6 +243,21 @@ fn classify_boundary_initial(prev: WordProperty, next: WordProperty) -> WordBoun
     }
 }
 
+/// Classifies the boundary between two characters of the same word, where
+/// a subword starts after underscores, and at an uppercase letter following
+/// a lowercase letter or a digit.
+fn classify_subword_boundary(prev: char, next: char) -> WordBoundary {
+    match (prev, next) {
+        ('_', '_') => WordBoundary::Interior,
+        (_, '_') => WordBoundary::End,
+        ('_', _) => WordBoundary::Start,
+        _ if (prev.is_lowercase() || prev.is_ascii_digit()) && next.is_uppercase() => {
+            WordBoundary::Both
+        }
+        _ => WordBoundary::Interior,
+    }
+}
+
 #[derive(Copy, Clone)]
 enum WordProperty {
     Lf,
@@ -193,23 +266,66 @@ enum WordProperty {
     Other, // includes letters and all of non-ascii unicode
 }
 
-fn get_word_property(codepoint: char) -> WordProperty {
+fn get_word_property(codepoint: char, separators: &str) -> WordProperty {
     if codepoint <= ' ' {
         // TODO: deal with \r
         if codepoint == '\n' {
             return WordProperty::Lf;
         }
         return WordProperty::Space;
-    } else if codepoint <= '\u{3f}' {
-        // Hardcoded: !"#$%&'()*+,-./:;<=>?
-        if (0xfc00fffe00000000u64 >> (codepoint as u32)) & 1 != 0 {
-            return WordProperty::Punctuation;
+    } else if separators.contains(codepoint) {
+        return WordProperty::Punctuation;
+    }
+    WordProperty::Other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boundaries(text: &str, separators: &str, subword: bool) -> (Vec<usize>, Vec<usize>) {
+        let rope = Rope::from(text);
+        let mut cursor = WordCursor::with_separators(&rope, 0, separators);
+        let mut next = Vec::new();
+        while let Some(offset) = cursor.next_boundary_impl(subword) {
+            next.push(offset);
         }
-    } else if codepoint <= '\u{7f}' {
-        // Hardcoded: @[\]^`{|}~
-        if (0x7800000178000001u64 >> ((codepoint as u32) & 0x3f)) & 1 != 0 {
-            return WordProperty::Punctuation;
+        let mut cursor = WordCursor::with_separators(&rope, rope.len(), separators);
+        let mut prev = Vec::new();
+        while let Some(offset) = cursor.prev_boundary_impl(subword) {
+            prev.push(offset);
         }
+        (next, prev)
+    }
+
+    #[test]
+    fn word_separators() {
+        let text = "a.b-c d";
+        assert_eq!(
+            boundaries(text, DEFAULT_WORD_SEPARATORS, false),
+            (vec![1, 3, 5, 7], vec![6, 4, 2, 0])
+        );
+        assert_eq!(boundaries(text, ".", false), (vec![1, 5, 7], vec![6, 2, 0]));
+    }
+
+    #[test]
+    fn word_starts() {
+        let rope = Rope::from("one  two.three\nfour");
+        let mut cursor = WordCursor::new(&rope, 0);
+        let mut starts = Vec::new();
+        while let Some(offset) = cursor.next_word_start() {
+            starts.push(offset);
+        }
+        assert_eq!(starts, vec![5, 9, 14, 15, 19]);
+    }
+
+    #[test]
+    fn subwords() {
+        let text = "fooBar_baz2Qux __x";
+        assert_eq!(
+            boundaries(text, DEFAULT_WORD_SEPARATORS, true),
+            (vec![3, 6, 11, 14, 18], vec![17, 15, 11, 7, 3, 0])
+        );
+        assert_eq!(boundaries(text, DEFAULT_WORD_SEPARATORS, false), (vec![14, 18], vec![15, 0]));
     }
-    WordProperty::Other
 }
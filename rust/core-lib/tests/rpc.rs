@@ -15,10 +15,14 @@
 #[macro_use]
 extern crate serde_json;
 
+extern crate tempdir;
 extern crate xi_core_lib;
 extern crate xi_rpc;
 
+use std::fs;
 use std::io;
+use std::thread;
+use std::time::Duration;
 
 use xi_core_lib::test_helpers;
 use xi_core_lib::XiCore;
@@ -317,6 +321,144 @@ fn test_settings_commands() {
     assert_eq!(resp.0["params"]["changes"]["font_face"], json!("Papyrus"));
 }
 
+#[test]
+fn test_large_file() {
+    let mut state = XiCore::new();
+    let (tx, mut rx) = test_channel();
+    let mut rpc_looper = RpcLoop::new(tx);
+    let json = make_reader(
+        r#"{"method":"client_started","params":{}}
+{"method":"modify_user_config","params":{"domain":"general","changes":{"large_file_threshold":1}}}
+{"id":0,"method":"new_view","params":{"file_path":"../Cargo.toml"}}"#,
+    );
+    assert!(rpc_looper.mainloop(|| json, &mut state).is_ok());
+    rx.expect_rpc("available_languages");
+    rx.expect_rpc("available_themes");
+    rx.expect_response().unwrap();
+    let progress = rx.expect_rpc("load_progress");
+    assert_eq!(progress.0["params"]["loaded"], progress.0["params"]["total"]);
+    let mode = rx.expect_rpc("large_file_mode");
+    assert_eq!(mode.0["params"]["enabled"], json!(true));
+
+    // finish setting up the view
+    let json = make_reader("");
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    rx.expect_rpc("available_plugins");
+    rx.expect_rpc("config_changed");
+    rx.expect_rpc("language_changed");
+    rx.expect_rpc("update");
+    rx.expect_rpc("scroll_to");
+
+    let json = make_reader(r#"{"method":"exit_large_file_mode","params":{"view_id":"view-id-1"}}"#);
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    let mode = rx.expect_rpc("large_file_mode");
+    assert_eq!(mode.0["params"]["enabled"], json!(false));
+}
+
+#[test]
+/// Tests that unsaved changes are kept for recovery, and can be restored when
+/// their file is opened again after the core was killed.
+fn test_recovery() {
+    let tmp = tempdir::TempDir::new("xi-test-recovery").unwrap();
+    let path = tmp.path().join("file.txt");
+    let recovery_dir = tmp.path().join("config").join("recovery");
+    fs::write(&path, "hello\n").unwrap();
+    let client_started = json!({
+        "method": "client_started",
+        "params": {"config_dir": tmp.path().join("config")},
+    });
+    let new_view = json!({"id": 0, "method": "new_view", "params": {"file_path": path}});
+
+    let mut state = XiCore::new();
+    let mut rpc_looper = RpcLoop::new(io::sink());
+    let json = make_reader(format!(
+        r#"{}
+{{"method":"modify_user_config","params":{{"domain":"general","changes":{{"recovery_interval":1}}}}}}
+{}
+{{"method":"edit","params":{{"view_id":"view-id-1","method":"insert","params":{{"chars":"a"}}}}}}"#,
+        client_started, new_view
+    ));
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    // give the recovery timer time to fire
+    rpc_looper
+        .mainloop(
+            || {
+                thread::sleep(Duration::from_millis(100));
+                make_reader("")
+            },
+            &mut state,
+        )
+        .unwrap();
+    assert_eq!(fs::read_dir(&recovery_dir).unwrap().count(), 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+
+    // the core is killed without closing the view
+    let mut state = XiCore::new();
+    let (tx, mut rx) = test_channel();
+    let mut rpc_looper = RpcLoop::new(tx);
+    let json = make_reader(format!(
+        r#"{}
+{{"method":"modify_user_config","params":{{"domain":"general","changes":{{"autosave_on_focus_loss":true}}}}}}
+{}"#,
+        client_started, new_view
+    ));
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    let json = make_reader("");
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    while rx.expect_object().get_method() != Some("recovery_available") {}
+
+    let json = make_reader(
+        r#"{"method":"restore_recovery","params":{"view_id":"view-id-1"}}
+{"method":"focus_lost","params":{}}"#,
+    );
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "ahello\n");
+    assert_eq!(fs::read_dir(&recovery_dir).unwrap().count(), 0);
+}
+
+#[test]
+/// Tests that the classic Mac line endings of a file are reported, but not
+/// used for new lines, and can't be converted to.
+fn test_cr_line_endings() {
+    let tmp = tempdir::TempDir::new("xi-test-cr").unwrap();
+    let path = tmp.path().join("file.txt");
+    fs::write(&path, "a\rb\r").unwrap();
+    let new_view = json!({"id": 0, "method": "new_view", "params": {"file_path": path}});
+
+    let mut state = XiCore::new();
+    let (tx, mut rx) = test_channel();
+    let mut rpc_looper = RpcLoop::new(tx);
+    let json = make_reader(format!(
+        r#"{{"method":"client_started","params":{{}}}}
+{}"#,
+        new_view
+    ));
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    let json = make_reader("");
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    let changed = loop {
+        let object = rx.expect_object();
+        if object.get_method() == Some("line_ending_changed") {
+            break object;
+        }
+    };
+    assert_eq!(changed.0["params"]["line_ending"], json!("cr"));
+
+    let json = make_reader(
+        r#"{"method":"edit","params":{"view_id":"view-id-1","method":"insert_newline","params":[]}}
+{"method":"edit","params":{"view_id":"view-id-1","method":"convert_line_endings","params":{"line_ending":"cr"}}}
+{"id":1,"method":"debug_get_contents","params":{"view_id":"view-id-1"}}"#,
+    );
+    rpc_looper.mainloop(|| json, &mut state).unwrap();
+    let contents = loop {
+        let object = rx.expect_object();
+        if object.is_response() {
+            break object.into_response().unwrap();
+        }
+    };
+    assert_eq!(contents, Ok(json!("\na\rb\r")));
+}
+
 //TODO: test saving rpc
 //TODO: test plugin rpc
 
@@ -17,7 +17,7 @@ use serde_json::{self, Value};
 use std::path::{Path, PathBuf};
 
 use crate::xi_core::plugin_rpc::{
-    GetDataResponse, PluginBufferInfo, PluginEdit, ScopeSpan, TextUnit,
+    FoldRange, GetDataResponse, PluginBufferInfo, PluginEdit, ScopeSpan, TextUnit,
 };
 use crate::xi_core::{BufferConfig, ConfigTable, LanguageId, PluginPid, ViewId};
 use xi_core_lib::annotations::AnnotationType;
@@ -189,6 +189,20 @@ impl<C: Cache> View<C> {
         self.peer.send_rpc_notification("update_spans", &params);
     }
 
+    /// Replaces the foldable regions in the interval `start..start + len`.
+    /// The offsets of `ranges` are relative to `start`.
+    pub fn update_fold_ranges(&self, start: usize, len: usize, ranges: &[FoldRange]) {
+        let params = json!({
+            "plugin_id": self.plugin_id,
+            "view_id": self.view_id,
+            "start": start,
+            "len": len,
+            "rev": self.rev,
+            "ranges": ranges,
+        });
+        self.peer.send_rpc_notification("update_fold_ranges", &params);
+    }
+
     pub fn update_annotations(
         &self,
         start: usize,
@@ -318,6 +318,11 @@ impl Engine {
         self.find_rev_token(rev).map(|rev_index| self.rev_content_for_index(rev_index))
     }
 
+    /// Returns `true` if the revision with the given token is still in the history.
+    pub fn has_rev(&self, rev: RevToken) -> bool {
+        self.find_rev_token(rev).is_some()
+    }
+
     /// A delta that, when applied to `base_rev`, results in the current head. Returns
     /// an error if there is not at least one edit.
     pub fn try_delta_rev_head(&self, base_rev: RevToken) -> Result<Delta<RopeInfo>, Error> {
@@ -683,6 +688,23 @@ impl Engine {
         self.revs.append(&mut new_revs);
     }
 
+    /// Continues numbering the revisions of this session after the ones
+    /// already in the history. The revision counter isn't serialized, so this
+    /// should be called on a deserialized `Engine` before editing it, lest new
+    /// revisions collide with existing ones.
+    pub fn resume_rev_id_counter(&mut self) {
+        let session = self.session;
+        let last_num = self
+            .revs
+            .iter()
+            .filter(|rev| rev.rev_id.session_id() == session)
+            .map(|rev| rev.rev_id.num)
+            .max();
+        if let Some(num) = last_num {
+            self.rev_id_counter = self.rev_id_counter.max(num + 1);
+        }
+    }
+
     /// When merging between multiple concurrently-editing sessions, each session should have a unique ID
     /// set with this function, which will make the revisions they create not have colliding IDs.
     /// For safety, this will panic if any revisions have already been added to the Engine.
@@ -996,6 +1018,24 @@ mod tests {
         assert_eq!("0123456789abcDEEFghijklmnopqr999stuvz", String::from(engine.get_head()));
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn edit_rev_after_deserialize() {
+        let mut engine = Engine::new(Rope::from(TEST_STR));
+        let first_rev = engine.get_head_rev_id().token();
+        engine.edit_rev(0, 1, first_rev, build_delta_1());
+        let head_rev = engine.get_head_rev_id();
+
+        let json = serde_json::to_string(&engine).unwrap();
+        let mut engine: Engine = serde_json::from_str(&json).unwrap();
+        engine.resume_rev_id_counter();
+        engine.edit_rev(0, 2, head_rev.token(), Delta::simple_edit(Interval::new(0, 1), Rope::from("!"), 37));
+        assert_eq!(TEST_STR, String::from(engine.get_rev(first_rev).unwrap()));
+        assert_eq!("!123456789abcDEEFghijklmnopqr999stuvz", String::from(engine.get_head()));
+        engine.undo([2].iter().cloned().collect());
+        assert_eq!("0123456789abcDEEFghijklmnopqr999stuvz", String::from(engine.get_head()));
+    }
+
     #[test]
     fn edit_rev_empty() {
         let mut engine = Engine::new(Rope::from(TEST_STR));
@@ -1170,8 +1210,10 @@ mod tests {
         engine.edit_rev(1, 2, new_head, d2);
         let gc : BTreeSet<usize> = [1].iter().cloned().collect();
         engine.gc(&gc);
+        assert!(!engine.has_rev(new_head));
         let d3 = Delta::simple_edit(Interval::new(0,0), Rope::from("b"), TEST_STR.len()+1);
         let new_head_2 = engine.get_head_rev_id().token();
+        assert!(engine.has_rev(new_head_2));
         engine.edit_rev(1, 3, new_head_2, d3);
         engine.undo([3].iter().cloned().collect());
         assert_eq!("a0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz", String::from(engine.get_head()));